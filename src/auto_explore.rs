@@ -0,0 +1,280 @@
+use specs::prelude::*;
+use rltk::{DijkstraMap, Point};
+use crate::{Map, KnownTerrain, known_traps, Viewshed, CombatStats, Monster, Name, Item, Position, RunState, PlayerActivity,
+            Confused, Invisible, SeeingInvisible, try_move_player, get_item};
+use crate::game_log::GameLog;
+use crate::visibility_system::visible_hostile;
+use crate::identification::entity_name;
+
+// the furthest an unexplored tile can be from the player and still be walked to
+const MAX_EXPLORE_DEPTH : f32 = 1024.0;
+
+pub struct AutoExploreOptions {
+    pub pick_up_items : bool
+}
+
+/// Switches picking up items while exploring on or off, saying which it now is
+pub fn toggle_pick_up(ecs : &mut World) {
+    let mut options = ecs.fetch_mut::<AutoExploreOptions>();
+    options.pick_up_items = !options.pick_up_items;
+    let message = if options.pick_up_items { "You will pick up items as you explore." } else { "You will leave items where they lie as you explore." };
+    ecs.fetch_mut::<GameLog>().log(message);
+}
+
+// items the player can currently see lying on the map
+fn visible_items(ecs : &World) -> Vec<Entity> {
+    let player_entity = ecs.fetch::<Entity>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let items = ecs.read_storage::<Item>();
+    let positions = ecs.read_storage::<Position>();
+    let entities = ecs.entities();
+
+    let mut seen = Vec::new();
+    if let Some(viewshed) = viewsheds.get(*player_entity) {
+        for (entity, _item, pos) in (&entities, &items, &positions).join() {
+            if viewshed.visible_tiles.contains(&Point::new(pos.x, pos.y)) {
+                seen.push(entity);
+            }
+        }
+    }
+    seen
+}
+
+pub fn start_exploring(ecs : &mut World) -> RunState {
+    let seen_items = visible_items(ecs);
+    let hp = ecs.read_storage::<CombatStats>().get(*ecs.fetch::<Entity>()).map_or(0, |s| s.hp);
+    *ecs.write_resource::<PlayerActivity>() = PlayerActivity::Exploring{ seen_items, hp };
+    explore(ecs)
+}
+
+fn stop_exploring(ecs : &mut World, message : String) -> RunState {
+    *ecs.write_resource::<PlayerActivity>() = PlayerActivity::Idle;
+    ecs.write_resource::<GameLog>().log(message);
+    RunState::AwaitingInput
+}
+
+// takes one step towards the nearest unexplored tile, stopping if anything worth attention happens
+pub fn explore(ecs : &mut World) -> RunState {
+    let (seen_items, last_hp) = match &*ecs.fetch::<PlayerActivity>() {
+        PlayerActivity::Exploring{ seen_items, hp } => (seen_items.clone(), *hp),
+        _ => return RunState::AwaitingInput
+    };
+
+    let player_entity = *ecs.fetch::<Entity>();
+
+    let hostile = {
+        let map = ecs.fetch::<Map>();
+        let viewsheds = ecs.read_storage::<Viewshed>();
+        let monsters = ecs.read_storage::<Monster>();
+        let invisible = ecs.read_storage::<Invisible>();
+        let see_invisible = ecs.read_storage::<SeeingInvisible>().get(player_entity).is_some();
+        viewsheds.get(player_entity).and_then(|v| visible_hostile(&map, v, &monsters, &invisible, see_invisible))
+    };
+    if let Some(hostile) = hostile {
+        let name = ecs.read_storage::<Name>().get(hostile).map_or("something".to_string(), |n| n.name.clone());
+        return stop_exploring(ecs, format!("You spot the {}.", name));
+    }
+
+    let hp = ecs.read_storage::<CombatStats>().get(player_entity).map_or(0, |s| s.hp);
+    if hp < last_hp {
+        return stop_exploring(ecs, "You are hurt! You stop exploring.".to_string());
+    }
+
+    if ecs.read_storage::<Confused>().get(player_entity).is_some() {
+        return stop_exploring(ecs, "You are too confused to find your way.".to_string());
+    }
+
+    let new_item = visible_items(ecs).into_iter().find(|item| !seen_items.contains(item));
+    if let Some(item) = new_item {
+        let name = entity_name(ecs, item);
+        return stop_exploring(ecs, format!("You see a {}.", name));
+    }
+
+    if ecs.fetch::<AutoExploreOptions>().pick_up_items {
+        let player_pos = *ecs.fetch::<Point>();
+        let item_here = {
+            let items = ecs.read_storage::<Item>();
+            let positions = ecs.read_storage::<Position>();
+            (&items, &positions).join().any(|(_item, pos)| pos.x == player_pos.x && pos.y == player_pos.y)
+        };
+        if item_here {
+            get_item(ecs);
+            return RunState::PlayerTurn;
+        }
+    }
+
+    let traps = known_traps(ecs);
+    let step = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Point>();
+
+        // head for the unrevealed tiles on the edge of what has been seen, walking only known terrain and around known traps to get there
+        let starts : Vec<usize> = (0..map.tiles.len()).filter(|idx| map.is_frontier(*idx)).collect();
+        let terrain = KnownTerrain{ map: &map, frontier: true, traps: &traps };
+
+        let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+        let dijkstra = DijkstraMap::new(map.width, map.height, &starts, &terrain, MAX_EXPLORE_DEPTH);
+        if dijkstra.map[player_idx] >= MAX_EXPLORE_DEPTH {
+            None
+        } else {
+            DijkstraMap::find_lowest_exit(&dijkstra, player_idx, &terrain)
+                .map(|idx| (idx as i32 % map.width - player_pos.x, idx as i32 / map.width - player_pos.y))
+        }
+    };
+
+    match step {
+        None => stop_exploring(ecs, "There is nothing left to explore.".to_string()),
+        Some((delta_x, delta_y)) => {
+            try_move_player(delta_x, delta_y, ecs);
+            *ecs.write_resource::<PlayerActivity>() = PlayerActivity::Exploring{ seen_items, hp };
+            RunState::PlayerTurn
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Player, WantsToMelee, EntityMoved, Identifiable, TileType, EntryTrigger, WantsToPickupItem};
+    use crate::identification::Identification;
+
+    // a corridor running east from the player, of which only the first `revealed` tiles (and the walls beside them) have been seen
+    fn world(revealed : i32) -> (World, Entity) {
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<Player>();
+        ecs.register::<Viewshed>();
+        ecs.register::<CombatStats>();
+        ecs.register::<Monster>();
+        ecs.register::<Name>();
+        ecs.register::<Item>();
+        ecs.register::<WantsToMelee>();
+        ecs.register::<EntityMoved>();
+        ecs.register::<Confused>();
+        ecs.register::<EntryTrigger>();
+        ecs.register::<Invisible>();
+        ecs.register::<SeeingInvisible>();
+
+        let corridor : Vec<(i32, i32)> = (1..=10).map(|x| (x, 5)).collect();
+        let mut map = Map::with_floor(&corridor);
+        for y in 4..=6 {
+            for x in 0..=revealed + 1 {
+                let idx = map.xy_idx(x, y);
+                if x <= revealed || map.tiles[idx] == TileType::Wall {
+                    map.revealed_tiles[idx] = true;
+                }
+            }
+        }
+        let player = ecs.create_entity()
+            .with(Position{ x: 2, y: 5 })
+            .with(Player{})
+            .with(Viewshed{ visible_tiles: (1..=revealed).map(|x| Point::new(x, 5)).collect(), range: 8, dirty: false })
+            .with(CombatStats{ max_hp: 20, hp: 20, defense: 0, power: 0, initiative: 0 })
+            .build();
+        ecs.insert(map);
+        ecs.insert(player);
+        ecs.insert(Point::new(2, 5));
+        ecs.insert(GameLog::default());
+        ecs.register::<Identifiable>();
+        ecs.insert(Identification::new(1, &mut rltk::RandomNumberGenerator::seeded(1)));
+        ecs.insert(PlayerActivity::Idle);
+        ecs.insert(AutoExploreOptions{ pick_up_items: false });
+        (ecs, player)
+    }
+
+    fn last_message(ecs : &World) -> String {
+        ecs.fetch::<GameLog>().entries.last().map(|e| e.text()).unwrap_or_default()
+    }
+
+    #[test]
+    fn steps_towards_the_unexplored_end_of_the_corridor() {
+        let (mut ecs, _player) = world(5);
+        assert!(start_exploring(&mut ecs) == RunState::PlayerTurn);
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(3, 5));
+    }
+
+    #[test]
+    fn stops_once_everything_is_explored() {
+        let (mut ecs, _player) = world(10);
+        assert!(start_exploring(&mut ecs) == RunState::AwaitingInput);
+        assert!(*ecs.fetch::<PlayerActivity>() == PlayerActivity::Idle);
+        assert_eq!(last_message(&ecs), "There is nothing left to explore.");
+    }
+
+    #[test]
+    fn stops_when_a_monster_comes_into_view() {
+        let (mut ecs, _player) = world(5);
+        let orc = ecs.create_entity().with(Monster{}).with(Name{ name: "Orc".to_string() }).build();
+        {
+            let mut map = ecs.fetch_mut::<Map>();
+            let idx = map.xy_idx(4, 5);
+            map.tile_content[idx].push(orc);
+        }
+        assert!(start_exploring(&mut ecs) == RunState::AwaitingInput);
+        assert_eq!(last_message(&ecs), "You spot the Orc.");
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(2, 5));
+    }
+
+    #[test]
+    fn stops_when_a_new_item_is_spotted() {
+        let (mut ecs, _player) = world(5);
+        assert!(start_exploring(&mut ecs) == RunState::PlayerTurn);
+        ecs.create_entity().with(Item{}).with(Position{ x: 4, y: 5 }).with(Name{ name: "Dagger".to_string() }).build();
+        assert!(explore(&mut ecs) == RunState::AwaitingInput);
+        assert_eq!(last_message(&ecs), "You see a Dagger.");
+    }
+
+    #[test]
+    fn items_already_in_view_do_not_stop_it() {
+        let (mut ecs, _player) = world(5);
+        ecs.create_entity().with(Item{}).with(Position{ x: 4, y: 5 }).with(Name{ name: "Dagger".to_string() }).build();
+        assert!(start_exploring(&mut ecs) == RunState::PlayerTurn);
+    }
+
+    #[test]
+    fn stops_when_hurt() {
+        let (mut ecs, player) = world(5);
+        assert!(start_exploring(&mut ecs) == RunState::PlayerTurn);
+        ecs.write_storage::<CombatStats>().get_mut(player).unwrap().hp -= 3;
+        assert!(explore(&mut ecs) == RunState::AwaitingInput);
+        assert_eq!(last_message(&ecs), "You are hurt! You stop exploring.");
+    }
+
+    #[test]
+    fn stops_when_confused() {
+        let (mut ecs, player) = world(5);
+        assert!(start_exploring(&mut ecs) == RunState::PlayerTurn);
+        ecs.write_storage::<Confused>().insert(player, Confused{ turns: 2 }).expect("Unable to insert");
+        assert!(explore(&mut ecs) == RunState::AwaitingInput);
+        assert_eq!(last_message(&ecs), "You are too confused to find your way.");
+    }
+
+    #[test]
+    fn picking_up_items_can_be_switched_on_and_off() {
+        let (mut ecs, _player) = world(5);
+        toggle_pick_up(&mut ecs);
+        assert!(ecs.fetch::<AutoExploreOptions>().pick_up_items);
+        assert_eq!(last_message(&ecs), "You will pick up items as you explore.");
+        toggle_pick_up(&mut ecs);
+        assert!(!ecs.fetch::<AutoExploreOptions>().pick_up_items);
+        assert_eq!(last_message(&ecs), "You will leave items where they lie as you explore.");
+    }
+
+    #[test]
+    fn picks_up_the_item_underfoot_only_when_asked_to() {
+        let (mut ecs, player) = world(5);
+        ecs.register::<WantsToPickupItem>();
+        ecs.create_entity().with(Item{}).with(Position{ x: 2, y: 5 }).with(Name{ name: "Dagger".to_string() }).build();
+        assert!(start_exploring(&mut ecs) == RunState::PlayerTurn);
+        assert!(ecs.read_storage::<WantsToPickupItem>().get(player).is_none());
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(3, 5));
+
+        let (mut ecs, player) = world(5);
+        ecs.register::<WantsToPickupItem>();
+        ecs.create_entity().with(Item{}).with(Position{ x: 2, y: 5 }).with(Name{ name: "Dagger".to_string() }).build();
+        ecs.fetch_mut::<AutoExploreOptions>().pick_up_items = true;
+        assert!(start_exploring(&mut ecs) == RunState::PlayerTurn);
+        assert!(ecs.read_storage::<WantsToPickupItem>().get(player).is_some());
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(2, 5));
+    }
+}
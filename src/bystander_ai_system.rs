@@ -0,0 +1,48 @@
+use specs::prelude::*;
+use rltk::RandomNumberGenerator;
+use crate::{Map, Position, Bystander, EntityMoved, RunState};
+
+pub struct BystanderAI {}
+
+// townsfolk amble about at random, never attacking anyone
+impl<'a> System<'a> for BystanderAI {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( WriteExpect<'a, Map>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        Entities<'a>,
+                        ReadStorage<'a, Bystander>,
+                        WriteStorage<'a, Position>,
+                        WriteStorage<'a, EntityMoved>);
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (mut map, runstate, mut rng, entities, bystanders, mut positions, mut moved) = data;
+
+        if *runstate != RunState::MonsterTurn { return; }
+
+        for (entity, _bystander, pos) in (&entities, &bystanders, &mut positions).join() {
+            // half the time they stand and stare
+            let (delta_x, delta_y) = match rng.roll_dice(1, 8) {
+                1 => (-1, 0),
+                2 => (1, 0),
+                3 => (0, -1),
+                4 => (0, 1),
+                _ => (0, 0)
+            };
+            if delta_x == 0 && delta_y == 0 { continue; }
+
+            let x = pos.x + delta_x;
+            let y = pos.y + delta_y;
+            if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 { continue; }
+            let destination_idx = map.xy_idx(x, y);
+            if !map.blocked[destination_idx] {
+                let idx = map.xy_idx(pos.x, pos.y);
+                map.blocked[idx] = false;
+                pos.x = x;
+                pos.y = y;
+                map.blocked[destination_idx] = true;
+                moved.insert(entity, EntityMoved{}).expect("Unable to insert moved marker");
+            }
+        }
+    }
+}
@@ -1,12 +1,27 @@
 use specs::prelude::*;
 use specs_derive::*;
-use rltk::{RGB};
+use rltk::{RGB, DiceType};
+use std::collections::HashMap;
 
 #[derive(Component)]
 pub struct Player {
 
 }
 
+// a short-lived visual effect, deleted once its time runs out however many turns pass
+#[derive(Component, Debug)]
+pub struct ParticleLifetime {
+    pub lifetime_ms : f32
+}
+
+// where an entity on a level the player is not on is waiting; it replaces Position so no system sees it
+#[derive(Component, Debug)]
+pub struct OtherLevelPosition {
+    pub x : i32,
+    pub y : i32,
+    pub depth : i32
+}
+
 #[derive(Component)]
 pub struct Position {
     pub x: i32,
@@ -33,6 +48,19 @@ pub struct Viewshed {
     pub dirty : bool
 }
 
+// how close something has to be for the player to make it out without any light on it
+#[derive(Component, Debug)]
+pub struct DarkVision {
+    pub range : i32
+}
+
+// sheds light of a colour around itself, fading out towards the edge of its range
+#[derive(Component, Debug)]
+pub struct LightSource {
+    pub colour : rltk::RGB,
+    pub range : i32
+}
+
 #[derive(Component)]
 pub struct Monster {
 
@@ -43,15 +71,142 @@ pub struct Name {
     pub name : String
 }
 
+// flavour text shown when the entity is inspected
+#[derive(Component, Debug)]
+pub struct Description {
+    pub text : String
+}
+
 #[derive(Component,Debug)]
 pub struct BlocksTile {}
 
+// max_hp, defense, power and initiative are derived from Attributes, Skills and equipment by the StatsSystem
 #[derive(Component, Debug)]
 pub struct CombatStats {
     pub max_hp : i32,
     pub hp : i32,
     pub defense : i32,
-    pub power : i32
+    pub power : i32,
+    pub initiative : i32
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Attribute {
+    pub base : i32,
+    pub modifiers : i32,
+    pub bonus : i32
+}
+
+impl Attribute {
+    pub fn new(base : i32) -> Attribute {
+        Attribute{ base, modifiers: 0, bonus: crate::stats_system::attr_bonus(base) }
+    }
+
+    pub fn total(&self) -> i32 {
+        self.base + self.modifiers
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Attributes {
+    pub might : Attribute,
+    pub fitness : Attribute,
+    pub quickness : Attribute,
+    pub intelligence : Attribute
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Skills {
+    pub melee : i32,
+    pub defence : i32,
+    pub magic : i32
+}
+
+#[derive(Component, Debug)]
+pub struct Progression {
+    pub level : i32,
+    pub xp : i32
+}
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum DamageType { Slashing, Piercing, Bludgeoning, Fire, Cold, Poison }
+
+impl DamageType {
+    // what the victim is on the receiving end of, for log messages
+    pub fn noun(&self) -> &'static str {
+        match self {
+            DamageType::Slashing => "blade",
+            DamageType::Piercing => "point",
+            DamageType::Bludgeoning => "blow",
+            DamageType::Fire => "flames",
+            DamageType::Cold => "frost",
+            DamageType::Poison => "poison"
+        }
+    }
+}
+
+// an attack an entity makes with its own body when it has no weapon equipped
+#[derive(Component, Debug, Clone)]
+pub struct NaturalAttack {
+    pub name : String,
+    pub hit_bonus : i32,
+    pub damage : DiceType,
+    pub damage_type : DamageType
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct MeleeWeapon {
+    pub hit_bonus : i32,
+    pub damage : DiceType,
+    pub damage_type : DamageType
+}
+
+// damage of another type a weapon adds on top of every hit
+#[derive(Component, Debug)]
+pub struct ExtraDamage {
+    pub damage : DiceType,
+    pub damage_type : DamageType
+}
+
+// how many affixes a generated item rolled, shown through the colour of its name
+#[derive(Component, Debug, PartialEq, Copy, Clone)]
+pub enum Rarity { Common, Magic, Rare }
+
+impl Rarity {
+    pub fn colour(&self) -> RGB {
+        match self {
+            Rarity::Common => RGB::named(rltk::WHITE),
+            Rarity::Magic => RGB::from_u8(80, 160, 255),
+            Rarity::Rare => RGB::named(rltk::GOLD)
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum EquipmentSlot { Melee, Armour }
+
+#[derive(Component, Debug, Clone)]
+pub struct Equippable {
+    pub slot : EquipmentSlot
+}
+
+#[derive(Component, Debug)]
+pub struct Equipped {
+    pub owner : Entity,
+    pub slot : EquipmentSlot
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct DefenseBonus {
+    pub defense : i32
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct AttributeBonus {
+    pub might : i32,
+    pub fitness : i32,
+    pub quickness : i32,
+    pub intelligence : i32
 }
 
 #[derive(Component, Debug)]
@@ -59,31 +214,331 @@ pub struct WantsToMelee {
     pub target : Entity
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct DamageInstance {
+    pub amount : i32,
+    pub damage_type : DamageType,
+    pub source : Option<Entity>
+}
+
 #[derive(Component, Debug)]
 pub struct SufferDamage {
-    pub amount : Vec<i32>
+    pub amount : Vec<DamageInstance>
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim : Entity, amount : i32){
+    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim : Entity, amount : i32, damage_type : DamageType, source : Option<Entity>){
+        let instance = DamageInstance{ amount, damage_type, source };
         if let Some(suffering) = store.get_mut(victim) {
-            suffering.amount.push(amount);
+            suffering.amount.push(instance);
         }
         else {
-            let dmg = SufferDamage { amount : vec![amount]};
+            let dmg = SufferDamage { amount : vec![instance]};
             store.insert(victim,dmg).expect("Unable to insert damage");
         }
     }
 }
 
+// the most recent thing to hurt an entity, kept so deaths can be attributed after the source is gone
+#[derive(Component, Debug, Clone)]
+pub struct LastDamagedBy {
+    pub source : Option<Entity>,
+    pub source_name : Option<String>,
+    pub damage_type : DamageType,
+    pub turn : i32
+}
+
+impl LastDamagedBy {
+    // e.g. "an Orc" or "the flames"
+    pub fn describe(&self) -> String {
+        match &self.source_name {
+            Some(name) => {
+                let article = if name.starts_with(|c : char| "AEIOUaeiou".contains(c)) { "an" } else { "a" };
+                format!("{} {}", article, name)
+            },
+            None => format!("the {}", self.damage_type.noun())
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DamageResponse { Resistant, Immune, Vulnerable }
+
+// damage types missing from the map are taken at full strength
+#[derive(Component, Debug, Clone, Default)]
+pub struct Resistances {
+    pub responses : HashMap<DamageType, DamageResponse>
+}
+
 #[derive(Component,Debug)]
 pub struct Item {}
 
+// in pounds, for a single item of a stack
+#[derive(Component, Debug)]
+pub struct Weight {
+    pub weight : f32
+}
+
+// what a single item of a stack is worth to a vendor, in gold
+#[derive(Component, Debug)]
+pub struct Value {
+    pub gold : i32
+}
+
+// the coins an entity carries; a monster's purse is left on the floor when it dies
+#[derive(Component, Debug)]
+pub struct Purse {
+    pub gold : i32
+}
+
+// a pile of coins lying on the map, added straight to the purse of whoever picks it up
+#[derive(Component, Debug)]
+pub struct Gold {
+    pub amount : i32
+}
+
+// someone who trades; their stock is whatever is in their backpack
+#[derive(Component, Debug)]
+pub struct Vendor {}
+
+// a harmless townsperson who wanders about at random
+#[derive(Component, Debug)]
+pub struct Bystander {}
+
+// things an entity says when the player bumps into them, one picked at random each time
+#[derive(Component, Debug)]
+pub struct Quips {
+    pub lines : Vec<String>
+}
+
+// how much an entity is carrying against what it can manage, kept up to date by the StatsSystem
+#[derive(Component, Debug)]
+pub struct Encumbrance {
+    pub carried : f32,
+    pub capacity : f32
+}
+
+impl Encumbrance {
+    pub fn overburdened(&self) -> bool {
+        self.carried > self.capacity
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct Consumable {}
+
+// the spawner template an item was made from; items from the same stackable template merge into one entity
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum ItemTemplate {
+    HealthPotion,
+    IdentifyScroll,
+    RemoveCurseScroll,
+    MagicMappingScroll,
+    DetectMonstersPotion,
+    FireballScroll,
+    PoisonCloudScroll,
+    TeleportScroll,
+    BlinkScroll,
+    LevelTeleportScroll,
+    TelepathyPotion,
+    SeeInvisiblePotion,
+    BlindnessPotion
+}
+
+// a magic item that goes by its appearance until its template is identified
 #[derive(Component, Debug)]
-pub struct Potion {
+pub struct Identifiable {
+    pub template : ItemTemplate
+}
+
+// reading it reveals what another carried item is
+#[derive(Component, Debug)]
+pub struct IdentifiesItem {}
+
+// once worn, a cursed item cannot be taken off again; it remembers what the curse took so lifting it can give it back
+#[derive(Component, Debug)]
+pub struct Cursed {
+    pub hit_penalty : i32,
+    pub defense_penalty : i32,
+    pub might_penalty : i32
+}
+
+// gear the player has not yet learned whether or not is cursed; every generated item starts with it, cursed or not
+#[derive(Component, Debug)]
+pub struct CurseUnknown {}
+
+// reading it lifts the curses from everything its reader carries and wears
+#[derive(Component, Debug)]
+pub struct RemovesCurse {}
+
+// using it reveals the whole of the current level
+#[derive(Component, Debug)]
+pub struct MagicMapper {}
+
+// using it lets its user sense every monster on the level for a number of turns
+#[derive(Component, Debug)]
+pub struct DetectsMonsters {
+    pub turns : i32
+}
+
+// used on a tile chosen with a targeting cursor, up to this many tiles away
+#[derive(Component, Debug)]
+pub struct Ranged {
+    pub range : i32
+}
+
+// hits everything within this radius of the target tile that the blast can reach past the walls
+#[derive(Component, Debug)]
+pub struct AreaOfEffect {
+    pub radius : i32
+}
+
+#[derive(Component, Debug)]
+pub struct InflictsDamage {
+    pub damage : DiceType,
+    pub damage_type : DamageType
+}
+
+// leaves everything it hits poisoned for a number of turns
+#[derive(Component, Debug)]
+pub struct Poisons {
+    pub turns : i32,
+    pub damage : i32
+}
+
+// takes poison damage every turn until the poison wears off; the source is credited with any kill
+#[derive(Component, Debug)]
+pub struct Poisoned {
+    pub turns : i32,
+    pub damage : i32,
+    pub source : Option<Entity>
+}
+
+// counts down the turns an entity can still sense monsters it cannot see
+#[derive(Component, Debug)]
+pub struct DetectingMonsters {
+    pub turns : i32
+}
+
+// lets the drinker sense the minds of monsters within range, walls or no walls, for a number of turns
+#[derive(Component, Debug)]
+pub struct GrantsTelepathy {
+    pub range : i32,
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct Telepathic {
+    pub range : i32,
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct GrantsSeeInvisible {
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct SeeingInvisible {
+    pub turns : i32
+}
+
+// leaves the user unable to see further than the tiles next to them for a number of turns
+#[derive(Component, Debug)]
+pub struct Blinds {
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct Blinded {
+    pub turns : i32
+}
+
+// cannot be seen, only sensed, unless the onlooker can see the invisible
+#[derive(Component, Debug)]
+pub struct Invisible {}
+
+// leaves everything it hits too addled to act for a number of turns
+#[derive(Component, Debug)]
+pub struct Confusion {
+    pub turns : i32
+}
+
+#[derive(Component, Debug)]
+pub struct Confused {
+    pub turns : i32
+}
+
+// sends whoever it is used on to a random open tile on the level
+#[derive(Component, Debug)]
+pub struct Teleports {}
+
+// with Ranged, moves the user to the tile it was aimed at, if they can see it and nothing stands there
+#[derive(Component, Debug)]
+pub struct Blinks {}
+
+// drops the player onto a random open tile of another level of the dungeon
+#[derive(Component, Debug)]
+pub struct TeleportsLevel {}
+
+// a trap: goes off when anything steps onto its tile, as if the one who stepped there had used it
+#[derive(Component, Debug)]
+pub struct EntryTrigger {}
+
+// marks an entity that moved this turn, so that traps on its new tile can go off
+#[derive(Component, Debug)]
+pub struct EntityMoved {}
+
+// the magical energy spells are cast with; max_mana is derived from Intelligence by the StatsSystem
+#[derive(Component, Debug)]
+pub struct Mana {
+    pub max_mana : i32,
+    pub mana : i32
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SpellTemplate {
+    FireBolt,
+    MendWounds,
+    Confuse,
+    Fireball,
+    Blink
+}
+
+// a spell is an entity carrying the same effect components as the items that do the same thing
+#[derive(Component, Debug)]
+pub struct Spell {
+    pub template : SpellTemplate,
+    pub mana_cost : i32
+}
+
+#[derive(Component, Debug)]
+pub struct KnownSpells {
+    pub spells : Vec<Entity>
+}
+
+// a spellbook, read to learn the spell it holds
+#[derive(Component, Debug)]
+pub struct TeachesSpell {
+    pub spell : Entity
+}
+
+#[derive(Component, Debug)]
+pub struct Stackable {
+    pub template : ItemTemplate
+}
+
+// how many items a stack holds; an item without one is a single item
+#[derive(Component, Debug)]
+pub struct Quantity {
     pub amount : i32
 }
 
+#[derive(Component, Debug)]
+pub struct ProvidesHealing {
+    pub heal_amount : i32
+}
+
 #[derive(Component, Debug)]
 pub struct InBackpack {
     pub owner : Entity
@@ -101,6 +556,15 @@ pub struct WantsToDropItem {
 }
 
 #[derive(Component, Debug)]
-pub struct WantsToDrinkPotion {
-    pub potion : Entity
+pub struct WantsToUseItem {
+    pub item : Entity,
+    // another item the used one acts upon, such as the item an identify scroll is read on
+    pub target_item : Option<Entity>,
+    // the tile a ranged item was aimed at
+    pub target : Option<rltk::Point>
+}
+
+#[derive(Component, Debug)]
+pub struct WantsToRemoveItem {
+    pub item : Entity
 }
\ No newline at end of file
@@ -1,21 +1,76 @@
 use specs::prelude::*;
-use crate::{CombatStats, SufferDamage, Player, Name};
-use rltk::console;
-use crate::game_log::GameLog;
+use rltk::RGB;
+use crate::{CombatStats, SufferDamage, Player, Name, Resistances, DamageResponse, Progression, LastDamagedBy, RunState, Renderable,
+            Position, Purse};
+use crate::game_log::{GameLog, entity_colour};
+use crate::run_stats::RunStats;
+use crate::stats_system::xp_for_kill;
+use crate::spawner;
 
 pub struct DamageSystem {}
 
 impl<'a> System<'a> for DamageSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, RunStats>,
         WriteStorage<'a, CombatStats>,
-        WriteStorage<'a, SufferDamage>
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Resistances>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, Progression>,
+        WriteStorage<'a, LastDamagedBy>,
+        ReadStorage<'a, Renderable>
     );
 
     fn run(&mut self, data : Self::SystemData){
-        let (mut stats, mut damage) = data;
+        let (entities, mut gamelog, run_stats, mut stats, mut damage, resistances, names, mut progression, mut last_damaged, renderables) = data;
 
-        for (mut stats, damage) in (&mut stats, &damage).join() {
-            stats.hp -= damage.amount.iter().sum::<i32>();
+        for (entity, stats, damage) in (&entities, &mut stats, &damage).join() {
+            let victim_name = names.get(entity).map_or("Something", |n| &n.name);
+            let victim_colour = entity_colour(&renderables, entity);
+
+            for instance in damage.amount.iter() {
+                let response = resistances.get(entity).and_then(|r| r.responses.get(&instance.damage_type));
+                let amount = match response {
+                    None => instance.amount,
+                    Some(DamageResponse::Resistant) => {
+                        gamelog.entry().colour(victim_colour, victim_name).text(format!(" resists the {}.", instance.damage_type.noun())).log();
+                        instance.amount / 2
+                    },
+                    Some(DamageResponse::Immune) => {
+                        gamelog.entry().colour(victim_colour, victim_name).text(format!(" shrugs off the {}.", instance.damage_type.noun())).log();
+                        0
+                    },
+                    Some(DamageResponse::Vulnerable) => {
+                        gamelog.entry().colour(victim_colour, victim_name).text(format!(" is badly hurt by the {}!", instance.damage_type.noun())).log();
+                        instance.amount * 2
+                    }
+                };
+
+                let was_alive = stats.hp > 0;
+                stats.hp -= amount;
+
+                if amount > 0 {
+                    last_damaged.insert(entity, LastDamagedBy{
+                        source: instance.source,
+                        source_name: instance.source.and_then(|s| names.get(s)).map(|n| n.name.clone()),
+                        damage_type: instance.damage_type,
+                        turn: run_stats.turn
+                    }).expect("Unable to insert last damaged by");
+                }
+
+                // whoever lands the killing blow earns experience based on the victim's level
+                if was_alive && stats.hp < 1 {
+                    if let Some(source) = instance.source {
+                        let victim_level = progression.get(entity).map_or(1, |p| p.level);
+                        if let Some(progress) = progression.get_mut(source) {
+                            progress.xp += xp_for_kill(victim_level);
+                        }
+                    }
+                }
+            }
         }
 
         damage.clear();
@@ -24,28 +79,64 @@ impl<'a> System<'a> for DamageSystem {
 
 pub fn delete_the_dead(ecs : &mut World) {
     let mut dead : Vec<Entity> = Vec::new();
+    // coins the dead were carrying, left where they fell
+    let mut dropped_gold : Vec<(i32, i32, i32)> = Vec::new();
 
     {
         let combat_stats = ecs.read_storage::<CombatStats>();
         let players = ecs.read_storage::<Player>();
         let entities = ecs.entities();
         let names = ecs.read_storage::<Name>();
+        let renderables = ecs.read_storage::<Renderable>();
+        let last_damaged = ecs.read_storage::<LastDamagedBy>();
+        let player_entity = ecs.fetch::<Entity>();
         let mut log = ecs.write_resource::<GameLog>();
+        let mut run_stats = ecs.write_resource::<RunStats>();
+        let mut run_state = ecs.write_resource::<RunState>();
+        let positions = ecs.read_storage::<Position>();
+        let purses = ecs.read_storage::<Purse>();
 
         for (entity, stats) in (&entities, &combat_stats).join() {
             if stats.hp < 1 {
+                let killer = last_damaged.get(entity);
                 // check if entity is player so they don't get deleted
                 let player = players.get(entity);
                 match player {
                     None => {
+                        let slain_by_player = killer.and_then(|k| k.source) == Some(*player_entity);
                         let victim_name = names.get(entity);
                         if let Some(victim_name) = victim_name {
-                            log.entries.push(format!("{} has been slain!", &victim_name.name));
+                            let victim_colour = entity_colour(&renderables, entity);
+                            if slain_by_player {
+                                log.entry().text("You slay the ").colour(victim_colour, &victim_name.name).text("!").log();
+                            } else {
+                                let entry = log.entry().colour(victim_colour, &victim_name.name);
+                                match killer.and_then(|k| k.source_name.as_ref()) {
+                                    Some(killer_name) => entry.text(format!(" has been slain by {}!", killer_name)).log(),
+                                    None => entry.text(" has been slain!").log()
+                                }
+                            }
+                        }
+                        if slain_by_player {
+                            run_stats.kills += 1;
+                        }
+                        if let (Some(pos), Some(purse)) = (positions.get(entity), purses.get(entity)) {
+                            if purse.gold > 0 { dropped_gold.push((pos.x, pos.y, purse.gold)); }
                         }
                         dead.push(entity)
                     },
                     // TODO: add victory conditions
-                    Some(_) => console::log("You have been deaded.")
+                    Some(_) => {
+                        if *run_state != RunState::GameOver {
+                            let cause = match killer {
+                                Some(killer) => format!("You were slain by {} on turn {}.", killer.describe(), killer.turn),
+                                None => format!("You died on turn {}.", run_stats.turn)
+                            };
+                            log.entry().colour(RGB::named(rltk::RED), &cause).log();
+                            run_stats.cause_of_death = Some(cause);
+                            *run_state = RunState::GameOver;
+                        }
+                    }
                 }
             }
         }
@@ -54,4 +145,98 @@ pub fn delete_the_dead(ecs : &mut World) {
     for victim in dead {
         ecs.delete_entity(victim).expect("Unable to delete entity");
     }
-}
\ No newline at end of file
+    for (x, y, amount) in dropped_gold {
+        spawner::gold(ecs, x, y, amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DamageType;
+
+    // what is left of a 20 hp victim after taking 6 fire damage with the given response to fire
+    fn hp_after_fire(response : Option<DamageResponse>) -> i32 {
+        let mut ecs = World::new();
+        ecs.register::<CombatStats>();
+        ecs.register::<SufferDamage>();
+        ecs.register::<Resistances>();
+        ecs.register::<Name>();
+        ecs.register::<Progression>();
+        ecs.register::<LastDamagedBy>();
+        ecs.register::<Renderable>();
+        ecs.insert(GameLog::default());
+        ecs.insert(RunStats{ turn: 1, kills: 0, cause_of_death: None });
+
+        let mut resistances = Resistances::default();
+        if let Some(response) = response { resistances.responses.insert(DamageType::Fire, response); }
+        let victim = ecs.create_entity()
+            .with(CombatStats{ max_hp: 20, hp: 20, defense: 0, power: 0, initiative: 0 })
+            .with(resistances)
+            .build();
+        SufferDamage::new_damage(&mut ecs.write_storage::<SufferDamage>(), victim, 6, DamageType::Fire, None);
+
+        DamageSystem{}.run_now(&ecs);
+        let stats = ecs.read_storage::<CombatStats>();
+        stats.get(victim).unwrap().hp
+    }
+
+    #[test]
+    fn damage_is_taken_in_full_without_a_response() {
+        assert_eq!(hp_after_fire(None), 14);
+    }
+
+    #[test]
+    fn resistance_halves_damage() {
+        assert_eq!(hp_after_fire(Some(DamageResponse::Resistant)), 17);
+    }
+
+    #[test]
+    fn immunity_ignores_damage() {
+        assert_eq!(hp_after_fire(Some(DamageResponse::Immune)), 20);
+    }
+
+    #[test]
+    fn vulnerability_doubles_damage() {
+        assert_eq!(hp_after_fire(Some(DamageResponse::Vulnerable)), 8);
+    }
+
+    // the message logged when a goblin dies, last hurt either by the player or by an orc
+    fn death_message(killed_by_player : bool) -> String {
+        let mut ecs = World::new();
+        ecs.register::<CombatStats>();
+        ecs.register::<Player>();
+        ecs.register::<Name>();
+        ecs.register::<Renderable>();
+        ecs.register::<LastDamagedBy>();
+        ecs.register::<Position>();
+        ecs.register::<Purse>();
+        ecs.insert(GameLog::default());
+        ecs.insert(RunStats{ turn: 3, kills: 0, cause_of_death: None });
+        ecs.insert(RunState::PlayerTurn);
+
+        let player = ecs.create_entity().with(Player{}).with(Name{ name: "Player".to_string() }).build();
+        let orc = ecs.create_entity().with(Name{ name: "Orc".to_string() }).build();
+        let (source, source_name) = if killed_by_player { (player, "Player") } else { (orc, "Orc") };
+        ecs.create_entity()
+            .with(Name{ name: "Goblin".to_string() })
+            .with(CombatStats{ max_hp: 5, hp: 0, defense: 0, power: 0, initiative: 0 })
+            .with(LastDamagedBy{ source: Some(source), source_name: Some(source_name.to_string()), damage_type: DamageType::Slashing, turn: 3 })
+            .build();
+        ecs.insert(player);
+
+        delete_the_dead(&mut ecs);
+        let log = ecs.fetch::<GameLog>();
+        log.entries.last().unwrap().text()
+    }
+
+    #[test]
+    fn the_player_slays_what_they_kill() {
+        assert_eq!(death_message(true), "You slay the Goblin!");
+    }
+
+    #[test]
+    fn other_deaths_name_the_killer() {
+        assert_eq!(death_message(false), "Goblin has been slain by Orc!");
+    }
+}
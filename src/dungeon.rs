@@ -0,0 +1,126 @@
+use specs::prelude::*;
+use std::collections::HashMap;
+use crate::{Map, Position, OtherLevelPosition, Viewshed};
+
+/// Every level the player has visited, keyed by depth, so that leaving one does not lose it.
+/// The level being played lives in the `Map` resource; its copy here is only refreshed when the player leaves.
+#[derive(Default)]
+pub struct MasterDungeonMap {
+    maps : HashMap<i32, Map>
+}
+
+impl MasterDungeonMap {
+    pub fn store_map(&mut self, map : &Map) {
+        self.maps.insert(map.depth, map.clone());
+    }
+
+    pub fn get_map(&self, depth : i32) -> Option<Map> {
+        self.maps.get(&depth).cloned()
+    }
+}
+
+/// Takes everything but the player off the current level, swapping each Position for an OtherLevelPosition
+pub fn freeze_level_entities(ecs : &mut World) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let player_entity = ecs.fetch::<Entity>();
+    let depth = ecs.fetch::<Map>().depth;
+
+    let mut frozen : Vec<Entity> = Vec::new();
+    for (entity, pos) in (&entities, &positions).join() {
+        if entity != *player_entity {
+            other_level_positions.insert(entity, OtherLevelPosition{ x: pos.x, y: pos.y, depth }).expect("Unable to insert other level position");
+            frozen.push(entity);
+        }
+    }
+    for entity in frozen {
+        positions.remove(entity);
+    }
+}
+
+/// Puts back everything that was left waiting on the level at `depth`, exactly where it stood
+pub fn thaw_level_entities(ecs : &mut World, depth : i32) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut other_level_positions = ecs.write_storage::<OtherLevelPosition>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+
+    let mut thawed : Vec<Entity> = Vec::new();
+    for (entity, pos) in (&entities, &other_level_positions).join() {
+        if pos.depth == depth {
+            positions.insert(entity, Position{ x: pos.x, y: pos.y }).expect("Unable to insert position");
+            thawed.push(entity);
+        }
+    }
+    for entity in thawed {
+        other_level_positions.remove(entity);
+        if let Some(viewshed) = viewsheds.get_mut(entity) {
+            viewshed.dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileType;
+
+    fn world(depth : i32) -> (World, Entity) {
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<OtherLevelPosition>();
+        ecs.register::<Viewshed>();
+        let mut map = Map::with_floor(&[(1, 1)]);
+        map.depth = depth;
+        ecs.insert(map);
+        let player = ecs.create_entity().with(Position{ x: 1, y: 1 }).build();
+        ecs.insert(player);
+        (ecs, player)
+    }
+
+    #[test]
+    fn stored_levels_come_back_unchanged() {
+        let mut dungeon = MasterDungeonMap::default();
+        let mut map = Map::with_floor(&[(3, 3)]);
+        map.depth = 2;
+        map.revealed_tiles[5] = true;
+        dungeon.store_map(&map);
+
+        let loaded = dungeon.get_map(2).expect("depth 2 was stored");
+        assert!(loaded.tiles[loaded.xy_idx(3, 3)] == TileType::Floor);
+        assert!(loaded.revealed_tiles[5]);
+        assert!(dungeon.get_map(3).is_none());
+    }
+
+    #[test]
+    fn freezing_lifts_everything_but_the_player_off_the_level() {
+        let (mut ecs, player) = world(2);
+        let orc = ecs.create_entity().with(Position{ x: 4, y: 5 }).build();
+
+        freeze_level_entities(&mut ecs);
+        assert!(ecs.read_storage::<Position>().get(player).is_some());
+        assert!(ecs.read_storage::<Position>().get(orc).is_none());
+        let others = ecs.read_storage::<OtherLevelPosition>();
+        let frozen = others.get(orc).unwrap();
+        assert_eq!((frozen.x, frozen.y, frozen.depth), (4, 5, 2));
+    }
+
+    #[test]
+    fn thawing_only_brings_back_that_level() {
+        let (mut ecs, _player) = world(1);
+        let orc = ecs.create_entity()
+            .with(OtherLevelPosition{ x: 4, y: 5, depth: 1 })
+            .with(Viewshed{ visible_tiles: Vec::new(), range: 8, dirty: false })
+            .build();
+        let goblin = ecs.create_entity().with(OtherLevelPosition{ x: 6, y: 7, depth: 3 }).build();
+
+        thaw_level_entities(&mut ecs, 1);
+        let positions = ecs.read_storage::<Position>();
+        let pos = positions.get(orc).unwrap();
+        assert_eq!((pos.x, pos.y), (4, 5));
+        assert!(ecs.read_storage::<OtherLevelPosition>().get(orc).is_none());
+        assert!(ecs.read_storage::<Viewshed>().get(orc).unwrap().dirty);
+        assert!(positions.get(goblin).is_none());
+    }
+}
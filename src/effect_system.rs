@@ -0,0 +1,197 @@
+use specs::prelude::*;
+use rltk::{RGB, Point, RandomNumberGenerator};
+use crate::{Map, RunState, WantsToUseItem, Name, Renderable, Position, CombatStats, SufferDamage, ProvidesHealing, MagicMapper,
+            Ranged, AreaOfEffect, InflictsDamage, Poisons, Poisoned, Identifiable, DamageType,
+            Confusion, Confused, Item};
+use crate::game_log::{GameLog, entity_colour};
+use crate::identification::{Identification, display_name};
+use crate::particle_system::ParticleBuilder;
+
+/// The tiles a blast of `radius` centred on `target` reaches: everything the impact point can see, so walls shelter what is behind them
+pub fn blast_tiles(map : &Map, target : Point, radius : i32) -> Vec<Point> {
+    let mut tiles = rltk::field_of_view(target, radius, map);
+    tiles.retain(|p| p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1);
+    tiles
+}
+
+// what a burst of each kind of damage looks like on the tiles it covers
+fn blast_colour(damage_type : DamageType) -> RGB {
+    match damage_type {
+        DamageType::Fire => RGB::named(rltk::ORANGE),
+        DamageType::Cold => RGB::named(rltk::CYAN),
+        DamageType::Poison => RGB::named(rltk::GREEN),
+        _ => RGB::named(rltk::RED)
+    }
+}
+
+pub struct ItemEffectSystem {}
+
+// applies what a used item does to whoever it was used on; the ItemUseSystem deals with equipping, identifying and using it up,
+// and the SenseEffectSystem with anything that changes how the user perceives the level
+impl<'a> System<'a> for ItemEffectSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        Entities<'a>,
+        ReadStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Identifiable>,
+        ReadExpect<'a, Identification>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, ProvidesHealing>,
+        ReadStorage<'a, MagicMapper>,
+        ReadStorage<'a, Ranged>,
+        ReadStorage<'a, AreaOfEffect>,
+        ReadStorage<'a, InflictsDamage>,
+        ReadStorage<'a, Poisons>,
+        WriteStorage<'a, Poisoned>,
+        ReadStorage<'a, Confusion>,
+        WriteStorage<'a, Confused>,
+        WriteExpect<'a, RunState>,
+        WriteExpect<'a, ParticleBuilder>,
+        ReadStorage<'a, Item>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, mut gamelog, map, mut rng, entities, wants_use, names, renderables, positions, identifiables, identification,
+            mut combat_stats, mut suffer_damage, healing, magic_mappers, ranged, area_of_effect,
+            inflicts_damage, poisons, mut poisoned, confusion, mut confused, mut runstate, mut particle_builder, items) = data;
+
+        for (entity, useitem) in (&entities, &wants_use).join() {
+            let item_name = display_name(&names, &identifiables, &identification, useitem.item);
+            let item_colour = entity_colour(&renderables, useitem.item);
+            // spells are announced as they are cast, and magic items as they are used up here
+            let magic_item = items.get(useitem.item).is_some();
+
+            // a ranged item hits whatever stands on the tile it was aimed at, or everything its blast reaches
+            let targets : Vec<Entity> = match (ranged.get(useitem.item), useitem.target) {
+                (Some(_), Some(target)) => {
+                    let tiles = match area_of_effect.get(useitem.item) {
+                        Some(aoe) => blast_tiles(&map, target, aoe.radius),
+                        None => vec![target]
+                    };
+                    let colour = inflicts_damage.get(useitem.item).map_or(RGB::named(rltk::ORANGE), |d| blast_colour(d.damage_type));
+                    let mut hit : Vec<Entity> = Vec::new();
+                    for tile in tiles.iter() {
+                        let idx = map.xy_idx(tile.x, tile.y);
+                        hit.extend(map.tile_content[idx].iter().filter(|e| combat_stats.get(**e).is_some()));
+                        particle_builder.request(tile.x, tile.y, colour, RGB::named(rltk::BLACK), rltk::to_cp437('░'), 200.0);
+                    }
+                    hit
+                }
+                _ => vec![entity]
+            };
+
+            if ranged.get(useitem.item).is_some() && entity == *player_entity {
+                if magic_item {
+                    gamelog.entry().text("You read the ").colour(item_colour, &item_name).text(".").log();
+                }
+                if targets.is_empty() && area_of_effect.get(useitem.item).is_some() {
+                    gamelog.log("The blast catches nothing.");
+                }
+            }
+
+            if let Some(healer) = healing.get(useitem.item) {
+                for target in targets.iter() {
+                    if let Some(stats) = combat_stats.get_mut(*target) {
+                        stats.hp = i32::min(stats.max_hp, stats.hp + healer.heal_amount);
+                        if let Some(pos) = positions.get(*target) {
+                            particle_builder.request(pos.x, pos.y, RGB::named(rltk::GREEN), RGB::named(rltk::BLACK), rltk::to_cp437('♥'), 200.0);
+                        }
+                        if *target == *player_entity && magic_item {
+                            gamelog.entry().text("You drink the ").colour(item_colour, &item_name)
+                                .text(", healing ").healing(healer.heal_amount).text(".").log();
+                        } else if *target == *player_entity {
+                            gamelog.entry().text("Your wounds close, healing ").healing(healer.heal_amount).text(".").log();
+                        }
+                    }
+                }
+            }
+
+            if let Some(damage) = inflicts_damage.get(useitem.item) {
+                for target in targets.iter() {
+                    let amount = i32::max(1, rng.roll(damage.damage));
+                    SufferDamage::new_damage(&mut suffer_damage, *target, amount, damage.damage_type, Some(entity));
+                    let target_name = if *target == *player_entity { "You".to_string() } else { names.get(*target).map_or("Something".to_string(), |n| n.name.clone()) };
+                    let verb = if *target == *player_entity { "are" } else { "is" };
+                    gamelog.entry().colour(entity_colour(&renderables, *target), target_name)
+                        .text(format!(" {} caught in the {}, taking ", verb, damage.damage_type.noun())).damage(amount).text(".").log();
+                }
+            }
+
+            // a fresh dose of poison replaces whatever was left of the last one
+            if let Some(poison) = poisons.get(useitem.item) {
+                for target in targets.iter() {
+                    poisoned.insert(*target, Poisoned{ turns: poison.turns, damage: poison.damage, source: Some(entity) }).expect("Unable to insert poisoned");
+                }
+            }
+
+            if let Some(confusion) = confusion.get(useitem.item) {
+                for target in targets.iter() {
+                    confused.insert(*target, Confused{ turns: confusion.turns }).expect("Unable to insert confused");
+                    if *target == *player_entity {
+                        gamelog.entry().colour(RGB::named(rltk::MAGENTA), "Your head swims.").log();
+                    } else if let Some(name) = names.get(*target) {
+                        gamelog.entry().colour(entity_colour(&renderables, *target), &name.name).colour(RGB::named(rltk::MAGENTA), " looks confused.").log();
+                    }
+                }
+            }
+
+            // the map is revealed a row at a time over the next few frames
+            if magic_mappers.get(useitem.item).is_some() && entity == *player_entity {
+                gamelog.entry().text("You read the ").colour(item_colour, &item_name).text(".").log();
+                gamelog.log("The layout of the level floods into your mind!");
+                *runstate = RunState::MagicMapReveal{ row: 0 };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileType;
+
+    // a level that is open floor inside its outer walls, with a wall running down column `wall_x`
+    fn walled_map(wall_x : i32) -> Map {
+        let mut map = Map::new(1);
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = if x == wall_x { TileType::Wall } else { TileType::Floor };
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn a_blast_reaches_its_radius_and_no_further() {
+        let map = walled_map(-1);
+        let tiles = blast_tiles(&map, Point::new(20, 20), 2);
+        assert!(tiles.contains(&Point::new(20, 20)));
+        assert!(tiles.contains(&Point::new(22, 20)));
+        assert!(!tiles.contains(&Point::new(23, 20)));
+    }
+
+    #[test]
+    fn walls_shelter_what_is_behind_them() {
+        let map = walled_map(21);
+        let tiles = blast_tiles(&map, Point::new(20, 20), 3);
+        assert!(tiles.contains(&Point::new(19, 20)));
+        assert!(tiles.iter().all(|p| p.x <= 21));
+    }
+
+    #[test]
+    fn a_blast_never_spills_onto_the_edge_of_the_map() {
+        let map = walled_map(-1);
+        let tiles = blast_tiles(&map, Point::new(1, 1), 3);
+        assert!(tiles.contains(&Point::new(1, 1)));
+        assert!(tiles.iter().all(|p| p.x > 0 && p.y > 0));
+    }
+}
@@ -1,4 +1,114 @@
+use specs::prelude::*;
+use rltk::RGB;
+use crate::Renderable;
+
+#[derive(Clone)]
+pub struct LogFragment {
+    pub colour : RGB,
+    pub text : String
+}
+
+pub struct LogEntry {
+    pub fragments : Vec<LogFragment>,
+    pub turn : i32,
+    // how many times in a row this message was logged
+    pub count : i32
+}
+
+impl LogEntry {
+    pub fn text(&self) -> String {
+        self.fragments.iter().map(|f| f.text.as_str()).collect()
+    }
+}
+
+#[derive(Default)]
 pub struct GameLog {
-    pub entries : Vec<String>
+    pub entries : Vec<LogEntry>,
+    // stamped onto new entries; kept in step with RunStats by the main loop
+    pub turn : i32
 }
 
+impl GameLog {
+    /// Logs a plain, uncoloured message
+    pub fn log<S: ToString>(&mut self, text : S) {
+        self.entry().text(text).log();
+    }
+
+    /// Starts a message built out of coloured fragments
+    pub fn entry(&mut self) -> LogBuilder<'_> {
+        LogBuilder{ log: self, fragments: Vec::new() }
+    }
+
+    fn push(&mut self, fragments : Vec<LogFragment>) {
+        let turn = self.turn;
+        let entry = LogEntry{ fragments, turn, count: 1 };
+
+        // a repeat of the previous message bumps its counter instead of adding a line
+        if let Some(last) = self.entries.last_mut() {
+            if last.text() == entry.text() {
+                last.count += 1;
+                last.turn = turn;
+                return;
+            }
+        }
+        self.entries.push(entry);
+    }
+}
+
+pub struct LogBuilder<'a> {
+    log : &'a mut GameLog,
+    fragments : Vec<LogFragment>
+}
+
+impl<'a> LogBuilder<'a> {
+    pub fn text<S: ToString>(self, text : S) -> Self {
+        self.colour(RGB::named(rltk::WHITE), text)
+    }
+
+    pub fn colour<S: ToString>(mut self, colour : RGB, text : S) -> Self {
+        self.fragments.push(LogFragment{ colour, text : text.to_string() });
+        self
+    }
+
+    pub fn damage(self, amount : i32) -> Self {
+        self.colour(RGB::named(rltk::RED), format!("{} hp", amount))
+    }
+
+    pub fn healing(self, amount : i32) -> Self {
+        self.colour(RGB::named(rltk::GREEN), format!("{} hp", amount))
+    }
+
+    pub fn log(self) {
+        self.log.push(self.fragments);
+    }
+}
+
+// the colour an entity is drawn in, so its name in the log matches what is on screen
+pub fn entity_colour(renderables : &ReadStorage<Renderable>, entity : Entity) -> RGB {
+    renderables.get(entity).map_or(RGB::named(rltk::WHITE), |r| r.fg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_messages_collapse_into_one_entry() {
+        let mut log = GameLog::default();
+        log.log("You hit the Orc.");
+        log.turn = 2;
+        log.log("You hit the Orc.");
+        log.log("The Orc dies.");
+        assert_eq!(log.entries.len(), 2);
+        assert_eq!(log.entries[0].count, 2);
+        assert_eq!(log.entries[0].turn, 2);
+    }
+
+    #[test]
+    fn fragments_join_into_the_entry_text() {
+        let mut log = GameLog::default();
+        log.entry().text("You take ").damage(3).text(".").log();
+        assert_eq!(log.entries[0].text(), "You take 3 hp.");
+        assert_eq!(log.entries[0].fragments.len(), 3);
+    }
+}
@@ -1,7 +1,23 @@
 use specs::prelude::*;
 use rltk::{Rltk, RGB, Point, VirtualKeyCode};
-use crate::{CombatStats, Player, Map, Name, Position, State, InBackpack};
-use crate::game_log::GameLog;
+use crate::{CombatStats, Player, Map, known_traps, Name, Position, State, InBackpack, Equipped, Attributes, Attribute, Skills, Progression,
+            Description, Resistances, DamageResponse, Quantity, Encumbrance, Identifiable, Rarity,
+            Cursed, CurseUnknown, Ranged, AreaOfEffect, Mana, KnownSpells, Spell, Poisoned, Confused, Blinded, Telepathic,
+            SeeingInvisible, DetectingMonsters, NaturalAttack, MeleeWeapon};
+use crate::identification::{Identification, display_name, entity_name, is_unidentified};
+use crate::inventory_system::stack_name;
+use crate::melee_combat_system::{BASE_ARMOUR_CLASS, wielded_hit_bonus, attack_bonus};
+use crate::stats_system::xp_to_next_level;
+use crate::run_stats::RunStats;
+use crate::RunState;
+use crate::keybindings::{KeyBindings, COMMANDS};
+use crate::shop;
+use crate::effect_system::blast_tiles;
+use crate::visibility_system::player_perceives;
+use crate::game_log::{GameLog, LogEntry};
+
+// lines of history shown per page of the message history screen
+const LOG_PAGE_LENGTH : i32 = 44;
 
 pub fn draw_ui(ecs:&World, ctx:&mut Rltk) {
     ctx.draw_box(0, 43, 79, 6, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
@@ -13,64 +29,102 @@ pub fn draw_ui(ecs:&World, ctx:&mut Rltk) {
         ctx.print_color(12,43, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &health);
 
         // health bar
-        ctx.draw_bar_horizontal(28, 43, 51, stats.hp, stats.max_hp, RGB::named(rltk::RED), RGB::named(rltk::BLACK));
+        ctx.draw_bar_horizontal(28, 43, 22, stats.hp, stats.max_hp, RGB::named(rltk::RED), RGB::named(rltk::BLACK));
+    }
+
+    let manas = ecs.read_storage::<Mana>();
+    for (_player, mana) in (&players, &manas).join() {
+        let mana_text = format!(" MP: {} / {} ", mana.mana, mana.max_mana);
+        ctx.print_color(51, 43, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), &mana_text);
+        ctx.draw_bar_horizontal(65, 43, 14, mana.mana, mana.max_mana, RGB::named(rltk::BLUE), RGB::named(rltk::BLACK));
+    }
+
+    let encumbrance = ecs.read_storage::<Encumbrance>();
+    for (_player, burden) in (&players, &encumbrance).join() {
+        if burden.overburdened() {
+            ctx.print_color(2, 43, RGB::named(rltk::ORANGE), RGB::named(rltk::BLACK), "Burdened");
+        }
     }
 
     // message log
     let log = ecs.fetch::<GameLog>();
-    // console::log(log.entries.len());
-    let mut y = 44;
-    for s in log.entries.iter().rev() {
-        if y < 49 { ctx.print(2, y,s)}
-        y += 1;
+    for (y, entry) in (44..49).zip(log.entries.iter().rev()) {
+        draw_log_entry(ctx, 2, y, entry);
     }
 
-    // mouse over
+    // mouse over, previewing the route a click would travel
     let mouse_pos = ctx.mouse_pos();
+    if *ecs.fetch::<RunState>() == RunState::AwaitingInput {
+        draw_travel_path(ecs, ctx, Point::new(mouse_pos.0, mouse_pos.1));
+    }
     ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::MAGENTA));
 
     draw_tooltips(ecs, ctx);
 }
 
+// prints an entry fragment by fragment, returning the column after the last character
+fn draw_log_entry(ctx : &mut Rltk, x : i32, y : i32, entry : &LogEntry) -> i32 {
+    let mut x = x;
+    for fragment in entry.fragments.iter() {
+        ctx.print_color(x, y, fragment.colour, RGB::named(rltk::BLACK), &fragment.text);
+        x += fragment.text.len() as i32;
+    }
+    if entry.count > 1 {
+        let repeats = format!(" x{}", entry.count);
+        ctx.print_color(x, y, RGB::named(rltk::GREY), RGB::named(rltk::BLACK), &repeats);
+        x += repeats.len() as i32;
+    }
+    x
+}
+
 #[derive(PartialEq, Copy, Clone)]
-pub enum ItemMenuResult { Cancel, NoResponse, Selected }
+pub enum ItemMenuResult { Cancel, NoResponse, Selected, Page(i32) }
 
-pub fn show_inventory(gs : &mut State, ctx : &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
-    let player_entity = gs.ecs.fetch::<Entity>();
-    let names = gs.ecs.read_storage::<Name>();
-    let backpack = gs.ecs.read_storage::<InBackpack>();
-    let entities = gs.ecs.entities();
+// a page of an item menu only uses the letters a to t, leaving room on screen for the frame
+const ITEMS_PER_PAGE : i32 = 20;
+
+// one line of an item menu
+pub struct MenuItem {
+    pub entity : Entity,
+    pub label : String,
+    pub colour : RGB
+}
 
-    let inventory = (&backpack, &names).join().filter(|item| item.0.owner == *player_entity);
-    let count = inventory.count();
+// draws one page of a lettered list of items and reads the player's choice from it
+fn item_menu(ctx : &mut Rltk, title : &str, footer : &str, items : &[MenuItem], page : i32) -> (ItemMenuResult, Option<Entity>) {
+    let pages = i32::max(1, (items.len() as i32 + ITEMS_PER_PAGE - 1) / ITEMS_PER_PAGE);
+    let page = i32::min(pages - 1, i32::max(0, page));
+    let first = (page * ITEMS_PER_PAGE) as usize;
+    let shown = &items[first .. usize::min(items.len(), first + ITEMS_PER_PAGE as usize)];
+    let count = shown.len() as i32;
 
-    let mut y = (25 - (count / 2)) as i32;
-    ctx.draw_box(15, y - 2, 31, (count + 3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
-    ctx.print_color(18, y - 2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Inventory");
-    ctx.print_color(18, y + count as i32 + 1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE TO CANCEL");
+    let top = 25 - (count / 2);
+    ctx.draw_box(15, top - 2, 40, count + 3, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(18, top - 2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), title);
+    ctx.print_color(18, top + count + 1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), footer);
+    if pages > 1 {
+        ctx.print_color(44, top - 2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), format!("< {}/{} >", page + 1, pages));
+    }
 
-    let mut equippable : Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (entity, _backpack, name) in (&entities, &backpack, &names).join().filter(|item| item.1.owner == *player_entity) {
+    for (y, (j, item)) in (top..).zip(shown.iter().enumerate()) {
         ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
         ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as rltk::FontCharType);
         ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
 
-        ctx.print(21, y, &name.name.to_string());
-        equippable.push(entity);
-        y+=1;
-        j+=1;
+        ctx.print_color(21, y, item.colour, RGB::named(rltk::BLACK), &item.label);
     }
 
     match ctx.key {
         None => (ItemMenuResult::NoResponse, None),
         Some(key) => {
             match key {
-                VirtualKeyCode::Escape => { return (ItemMenuResult::Cancel, None)},
+                VirtualKeyCode::Escape => { (ItemMenuResult::Cancel, None)},
+                VirtualKeyCode::PageUp | VirtualKeyCode::Left if page > 0 => (ItemMenuResult::Page(page - 1), None),
+                VirtualKeyCode::PageDown | VirtualKeyCode::Right if page < pages - 1 => (ItemMenuResult::Page(page + 1), None),
                 _ => {
                     let selection = rltk::letter_to_option(key);
-                    if selection > -1 && selection < count as i32 {
-                        return (ItemMenuResult::Selected, Some(equippable[selection as usize]));
+                    if selection > -1 && selection < count {
+                        return (ItemMenuResult::Selected, Some(shown[selection as usize].entity));
                     }
                     (ItemMenuResult::NoResponse, None)
                 }
@@ -79,50 +133,636 @@ pub fn show_inventory(gs : &mut State, ctx : &mut Rltk) -> (ItemMenuResult, Opti
     }
 }
 
-pub fn drop_items_menu(gs : &mut State, ctx : &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
-    let player_entity = gs.ecs.fetch::<Entity>();
+// the player's backpack, labelled with stack sizes
+fn backpack_items(ecs : &World) -> Vec<MenuItem> {
+    pack_items(ecs, *ecs.fetch::<Entity>())
+}
+
+// everything in someone's backpack, labelled with stack sizes
+fn pack_items(ecs : &World, owner : Entity) -> Vec<MenuItem> {
+    let names = ecs.read_storage::<Name>();
+    let identifiables = ecs.read_storage::<Identifiable>();
+    let identification = ecs.fetch::<Identification>();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let quantities = ecs.read_storage::<Quantity>();
+    let rarities = ecs.read_storage::<Rarity>();
+    let entities = ecs.entities();
+
+    (&entities, &backpack).join()
+        .filter(|item| item.1.owner == owner)
+        .map(|(entity, _backpack)| {
+            let label = stack_name(&display_name(&names, &identifiables, &identification, entity), quantities.get(entity));
+            if known_curse(ecs, entity) {
+                MenuItem{ entity, label: format!("{} (cursed)", label), colour: RGB::named(rltk::RED) }
+            } else {
+                MenuItem{ entity, label, colour: rarity_colour(&rarities, entity) }
+            }
+        })
+        .collect()
+}
+
+// everything the player is wearing or wielding, with any curse they know about
+fn equipped_items(ecs : &World) -> Vec<MenuItem> {
+    let player_entity = *ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let identifiables = ecs.read_storage::<Identifiable>();
+    let identification = ecs.fetch::<Identification>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let rarities = ecs.read_storage::<Rarity>();
+    let entities = ecs.entities();
+
+    (&entities, &equipped).join()
+        .filter(|item| item.1.owner == player_entity)
+        .map(|(entity, _equipped)| {
+            let label = display_name(&names, &identifiables, &identification, entity);
+            if known_curse(ecs, entity) {
+                MenuItem{ entity, label: format!("{} (cursed)", label), colour: RGB::named(rltk::RED) }
+            } else {
+                MenuItem{ entity, label, colour: rarity_colour(&rarities, entity) }
+            }
+        })
+        .collect()
+}
+
+// true once the player has found out that an item is cursed
+fn known_curse(ecs : &World, entity : Entity) -> bool {
+    ecs.read_storage::<Cursed>().get(entity).is_some() && ecs.read_storage::<CurseUnknown>().get(entity).is_none()
+}
+
+// carried items an identify scroll could be read on, leaving out the scroll's own kind;
+// equipment counts while nobody knows whether it is cursed
+pub fn unidentified_items(ecs : &World, scroll : Entity) -> Vec<MenuItem> {
+    let identifiables = ecs.read_storage::<Identifiable>();
+    let curse_unknown = ecs.read_storage::<CurseUnknown>();
+    let identification = ecs.fetch::<Identification>();
+    let scroll_template = identifiables.get(scroll).map(|i| i.template);
+
+    backpack_items(ecs).into_iter()
+        .filter(|item| is_unidentified(&identifiables, &identification, item.entity) || curse_unknown.get(item.entity).is_some())
+        .filter(|item| identifiables.get(item.entity).map(|i| i.template) != scroll_template)
+        .collect()
+}
+
+// items without a rarity are plain, fixed templates
+fn rarity_colour(rarities : &ReadStorage<Rarity>, entity : Entity) -> RGB {
+    rarities.get(entity).map_or(RGB::named(rltk::WHITE), |r| r.colour())
+}
+
+// "12.5/60 lbs, 40 gold" for the player's current load and purse
+fn load_description(ecs : &World) -> String {
+    let encumbrance = ecs.read_storage::<Encumbrance>();
+    let player_entity = *ecs.fetch::<Entity>();
+    match encumbrance.get(player_entity) {
+        Some(burden) => format!("{:.1}/{:.0} lbs, {} gold", burden.carried, burden.capacity, shop::gold_carried(ecs, player_entity)),
+        None => String::new()
+    }
+}
+
+pub fn show_inventory(gs : &mut State, ctx : &mut Rltk, page : i32) -> (ItemMenuResult, Option<Entity>) {
+    let items = backpack_items(&gs.ecs);
+    let title = format!("Inventory {}", load_description(&gs.ecs));
+    item_menu(ctx, &title, "ESCAPE TO CANCEL", &items, page)
+}
+
+pub fn cast_menu(gs : &mut State, ctx : &mut Rltk, page : i32) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = *gs.ecs.fetch::<Entity>();
     let names = gs.ecs.read_storage::<Name>();
-    let backpack = gs.ecs.read_storage::<InBackpack>();
-    let entities = gs.ecs.entities();
+    let spells = gs.ecs.read_storage::<Spell>();
+    let mana = gs.ecs.read_storage::<Mana>().get(player_entity).map_or(0, |m| m.mana);
+    let known = gs.ecs.read_storage::<KnownSpells>().get(player_entity).map_or(Vec::new(), |k| k.spells.clone());
 
-    let inventory = (&backpack, &names).join().filter(|item| item.0.owner == *player_entity);
-    let count = inventory.count();
+    // spells the player cannot pay for right now are greyed out
+    let items : Vec<MenuItem> = known.iter().map(|spell| {
+        let cost = spells.get(*spell).map_or(0, |s| s.mana_cost);
+        MenuItem{
+            entity: *spell,
+            label: format!("{} ({} mana)", names.get(*spell).unwrap().name, cost),
+            colour: if cost <= mana { RGB::named(rltk::CYAN) } else { RGB::named(rltk::GREY) }
+        }
+    }).collect();
+    item_menu(ctx, "Cast Which Spell?", "ESCAPE TO CANCEL", &items, page)
+}
 
-    let mut y = (25 - (count / 2)) as i32;
-    ctx.draw_box(15, y - 2, 31, (count + 3) as i32, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
-    ctx.print_color(18, y - 2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Drop Item");
-    ctx.print_color(18, y + count as i32 + 1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESC TO CANCEL");
+pub fn identify_menu(gs : &mut State, ctx : &mut Rltk, scroll : Entity, page : i32) -> (ItemMenuResult, Option<Entity>) {
+    let items = unidentified_items(&gs.ecs, scroll);
+    item_menu(ctx, "Identify Which Item?", "ESCAPE TO CANCEL", &items, page)
+}
 
-    let mut equippable : Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (entity, _backpack, name) in (&entities, &backpack, &names).join().filter(|item| item.1.owner == *player_entity) {
-        ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
-        ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as rltk::FontCharType);
-        ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
+// besides the chosen item, returns how many of its stack to drop
+pub fn drop_items_menu(gs : &mut State, ctx : &mut Rltk, page : i32) -> (ItemMenuResult, Option<Entity>, i32) {
+    let items = backpack_items(&gs.ecs);
+    let title = format!("Drop Item {}", load_description(&gs.ecs));
+    let (result, item) = item_menu(ctx, &title, "SHIFT: DROP ONE, ESC: CANCEL", &items, page);
+
+    match item {
+        None => (result, None, 0),
+        Some(item) => {
+            let quantities = gs.ecs.read_storage::<Quantity>();
+            let stack = quantities.get(item).map_or(1, |q| q.amount);
+            let amount = if ctx.shift { 1 } else { stack };
+            (result, Some(item), amount)
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum TradeResult { Leave, NoResponse, SwitchMode, Page(i32), Selected(Entity) }
+
+// buying lists the vendor's stock at their asking price, selling lists the backpack at what they will pay
+pub fn trade_menu(gs : &mut State, ctx : &mut Rltk, vendor : Entity, buying : bool, page : i32) -> TradeResult {
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    let (owner, title, footer) = if buying {
+        (vendor, format!("Buy ({} gold)", shop::gold_carried(&gs.ecs, player_entity)), "TAB: SELL, ESC: LEAVE")
+    } else {
+        let vendor_name = gs.ecs.read_storage::<Name>().get(vendor).map_or("Vendor".to_string(), |n| n.name.clone());
+        (player_entity, format!("Sell ({}: {} gold)", vendor_name, shop::gold_carried(&gs.ecs, vendor)), "TAB: BUY, ESC: LEAVE")
+    };
+
+    let items : Vec<MenuItem> = pack_items(&gs.ecs, owner).into_iter()
+        .map(|item| {
+            let price = if buying { shop::buy_price(&gs.ecs, item.entity) } else { shop::sell_price(&gs.ecs, item.entity) };
+            MenuItem{ label: format!("{} - {}g", item.label, price), ..item }
+        })
+        .collect();
+    let (result, item) = item_menu(ctx, &title, footer, &items, page);
+
+    if ctx.key == Some(VirtualKeyCode::Tab) {
+        return TradeResult::SwitchMode;
+    }
+    match (result, item) {
+        (ItemMenuResult::Cancel, _) => TradeResult::Leave,
+        (ItemMenuResult::Page(page), _) => TradeResult::Page(page),
+        (ItemMenuResult::Selected, Some(item)) => TradeResult::Selected(item),
+        _ => TradeResult::NoResponse
+    }
+}
+
+pub fn remove_item_menu(gs : &mut State, ctx : &mut Rltk, page : i32) -> (ItemMenuResult, Option<Entity>) {
+    let items = equipped_items(&gs.ecs);
+    item_menu(ctx, "Remove Which Item?", "ESCAPE TO CANCEL", &items, page)
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ScreenResult { Close, NoResponse }
+
+// lists every command with the keys currently bound to it
+pub fn show_help(ecs : &World, ctx : &mut Rltk) -> ScreenResult {
+    let bindings = ecs.fetch::<KeyBindings>();
 
-        ctx.print(21, y, &name.name.to_string());
-        equippable.push(entity);
-        y+=1;
-        j+=1;
+    ctx.draw_box(10, 4, 59, 41, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(13, 4, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Commands");
+    ctx.print_color(13, 45, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE TO CLOSE");
+    ctx.print_color(12, 6, RGB::named(rltk::GREY), RGB::named(rltk::BLACK),
+        format!("Preset: {}   (edit {} to change)", bindings.preset.name(), crate::keybindings::KEYBINDINGS_FILE));
+
+    let mut y = 8;
+    for command in COMMANDS.iter() {
+        ctx.print(12, y, command.description());
+        ctx.print_color(48, y, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), bindings.describe(*command));
+        y += 1;
     }
 
+    // keys that are the same whatever the bindings say
+    y += 1;
+    ctx.print(12, y, "Travel to a tile");
+    ctx.print_color(48, y, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), "Left click");
+    ctx.print(12, y + 1, "Confirm a cursor");
+    ctx.print_color(48, y + 1, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), "Return");
+    ctx.print(12, y + 2, "Cycle look targets");
+    ctx.print_color(48, y + 2, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), "Tab");
+    ctx.print(12, y + 3, "Close a menu, stop an activity");
+    ctx.print_color(48, y + 3, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), "Escape");
+
     match ctx.key {
-        None => (ItemMenuResult::NoResponse, None),
-        Some(key) => {
-            match key {
-                VirtualKeyCode::Escape => { return (ItemMenuResult::Cancel, None)},
-                _ => {
-                    let selection = rltk::letter_to_option(key);
-                    if selection > -1 && selection < count as i32 {
-                        return (ItemMenuResult::Selected, Some(equippable[selection as usize]));
-                    }
-                    (ItemMenuResult::NoResponse, None)
-                }
+        Some(VirtualKeyCode::Escape) => ScreenResult::Close,
+        _ => ScreenResult::NoResponse
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LogHistoryResult { Close, NoResponse, Scrolled(i32) }
+
+// full screen message history; offset counts how many lines back from the newest message the view ends
+pub fn show_log_history(ecs : &World, ctx : &mut Rltk, offset : i32) -> LogHistoryResult {
+    let log = ecs.fetch::<GameLog>();
+    let total = log.entries.len() as i32;
+    let max_offset = i32::max(0, total - LOG_PAGE_LENGTH);
+
+    ctx.cls();
+    ctx.draw_box(0, 0, 79, 49, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(2, 0, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Message History");
+    ctx.print_color(2, 49, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "UP/DOWN to scroll, PAGE UP/PAGE DOWN to page, ESCAPE to close");
+
+    let last = total - offset;
+    let first = i32::max(0, last - LOG_PAGE_LENGTH);
+    for (y, entry) in (2..).zip(log.entries[first as usize .. last as usize].iter()) {
+        let stamp = format!("{:>5} ", entry.turn);
+        ctx.print_color(2, y, RGB::named(rltk::GREY), RGB::named(rltk::BLACK), &stamp);
+        draw_log_entry(ctx, 2 + stamp.len() as i32, y, entry);
+    }
+
+    let scroll_to = |new_offset : i32| LogHistoryResult::Scrolled(i32::min(max_offset, i32::max(0, new_offset)));
+    match ctx.key {
+        None => LogHistoryResult::NoResponse,
+        Some(key) => match key {
+            VirtualKeyCode::Escape => LogHistoryResult::Close,
+            VirtualKeyCode::Up => scroll_to(offset + 1),
+            VirtualKeyCode::Down => scroll_to(offset - 1),
+            VirtualKeyCode::PageUp => scroll_to(offset + LOG_PAGE_LENGTH),
+            VirtualKeyCode::PageDown => scroll_to(offset - LOG_PAGE_LENGTH),
+            VirtualKeyCode::Home => scroll_to(max_offset),
+            VirtualKeyCode::End => scroll_to(0),
+            _ => LogHistoryResult::NoResponse
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum CursorResult { Cancel, NoResponse, Moved(Point), Selected(Point) }
+
+pub fn draw_travel_path(ecs : &World, ctx : &mut Rltk, target : Point) {
+    let traps = known_traps(ecs);
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    if let Some(path) = map.known_path(*player_pos, target, &traps) {
+        for idx in path.iter() {
+            ctx.set_bg(*idx as i32 % map.width, *idx as i32 / map.width, RGB::named(rltk::DARKBLUE));
+        }
+    }
+}
+
+// moves a cursor around the map with the movement keys until a tile is chosen
+fn map_cursor(ecs : &World, ctx : &mut Rltk, cursor : Point) -> CursorResult {
+    let map = ecs.fetch::<Map>();
+    ctx.set_bg(cursor.x, cursor.y, RGB::named(rltk::MAGENTA));
+
+    match ctx.key {
+        None => CursorResult::NoResponse,
+        Some(VirtualKeyCode::Escape) => CursorResult::Cancel,
+        Some(VirtualKeyCode::Return) => CursorResult::Selected(cursor),
+        Some(key) => match ecs.fetch::<KeyBindings>().direction(key) {
+            None => CursorResult::NoResponse,
+            Some((delta_x, delta_y)) => {
+                let x = i32::min(map.width - 1, i32::max(0, cursor.x + delta_x));
+                let y = i32::min(map.height - 1, i32::max(0, cursor.y + delta_y));
+                CursorResult::Moved(Point::new(x, y))
             }
         }
     }
 }
 
+pub fn travel_cursor(ecs : &World, ctx : &mut Rltk, cursor : Point) -> CursorResult {
+    draw_travel_path(ecs, ctx, cursor);
+    ctx.print_color(2, 0, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Travel where? ENTER to go, ESCAPE to cancel");
+    map_cursor(ecs, ctx, cursor)
+}
+
+// a tile a ranged item can be aimed at: one the player can see, within the item's range
+fn in_range(ecs : &World, item : Entity, target : Point) -> bool {
+    let map = ecs.fetch::<Map>();
+    let player_pos = *ecs.fetch::<Point>();
+    let range = ecs.read_storage::<Ranged>().get(item).map_or(0, |r| r.range);
+    if target.x < 0 || target.x >= map.width || target.y < 0 || target.y >= map.height { return false; }
+    map.visible_tiles[map.xy_idx(target.x, target.y)]
+        && rltk::DistanceAlg::Pythagoras.distance2d(player_pos, target) <= range as f32
+}
+
+/// What the targeting cursor shades, worked out again only when the cursor moves; it starts afresh each time aiming begins
+#[derive(Default)]
+pub struct TargetingPreview {
+    aimed : Option<(Entity, Point)>,
+    reachable : Vec<Point>,
+    blast : Vec<Point>
+}
+
+impl TargetingPreview {
+    fn refresh(&mut self, ecs : &World, item : Entity, cursor : Point) {
+        if self.aimed == Some((item, cursor)) { return; }
+        let reachable_before = self.aimed.is_some_and(|(aimed_item, _)| aimed_item == item);
+        self.aimed = Some((item, cursor));
+
+        // nothing moves while aiming, so the reachable tiles stay put until something else is aimed
+        if !reachable_before {
+            let map = ecs.fetch::<Map>();
+            self.reachable = (0..map.visible_tiles.len())
+                .filter(|idx| map.visible_tiles[*idx])
+                .map(|idx| Point::new(idx as i32 % map.width, idx as i32 / map.width))
+                .filter(|tile| in_range(ecs, item, *tile))
+                .collect();
+        }
+
+        self.blast.clear();
+        if self.reachable.contains(&cursor) {
+            if let Some(aoe) = ecs.read_storage::<AreaOfEffect>().get(item) {
+                self.blast = blast_tiles(&ecs.fetch::<Map>(), cursor, aoe.radius);
+            }
+        }
+    }
+}
+
+/// Aims a ranged item: shades the tiles it can reach and previews where its blast would land
+pub fn targeting_cursor(ecs : &World, ctx : &mut Rltk, item : Entity, cursor : Point) -> CursorResult {
+    ctx.print_color(2, 0, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Aim where? ENTER or click to use, ESCAPE to cancel");
+
+    {
+        let mut preview = ecs.fetch_mut::<TargetingPreview>();
+        preview.refresh(ecs, item, cursor);
+        for tile in preview.reachable.iter() {
+            ctx.set_bg(tile.x, tile.y, RGB::named(rltk::DARKBLUE));
+        }
+        for tile in preview.blast.iter() {
+            ctx.set_bg(tile.x, tile.y, RGB::named(rltk::DARK_RED));
+        }
+    }
+
+    if ctx.left_click {
+        let (x, y) = ctx.mouse_pos();
+        let clicked = Point::new(x, y);
+        if in_range(ecs, item, clicked) { return CursorResult::Selected(clicked); }
+        return CursorResult::Moved(cursor);
+    }
+
+    match map_cursor(ecs, ctx, cursor) {
+        CursorResult::Selected(target) if !in_range(ecs, item, target) => CursorResult::NoResponse,
+        result => result
+    }
+}
+
+fn attribute_line(ctx : &mut Rltk, y : i32, label : &str, attr : &Attribute) {
+    ctx.print_color(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), label);
+    ctx.print(32, y, format!("{:>3} {:>+3} = {:>3}", attr.base, attr.modifiers, attr.total()));
+    ctx.print_color(50, y, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), format!("({:+})", attr.bonus));
+}
+
+pub fn show_character_sheet(gs : &mut State, ctx : &mut Rltk) -> ScreenResult {
+    let player_entity = gs.ecs.fetch::<Entity>();
+    let names = gs.ecs.read_storage::<Name>();
+    let combat_stats = gs.ecs.read_storage::<CombatStats>();
+    let attributes = gs.ecs.read_storage::<Attributes>();
+    let skills = gs.ecs.read_storage::<Skills>();
+    let progression = gs.ecs.read_storage::<Progression>();
+    let equipped = gs.ecs.read_storage::<Equipped>();
+
+    ctx.draw_box(15, 5, 49, 36, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(18, 5, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Character Sheet");
+    ctx.print_color(18, 41, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE TO CLOSE");
+
+    let mut y = 7;
+    if let Some(progress) = progression.get(*player_entity) {
+        ctx.print(17, y, format!("Level {}   XP {} / {}", progress.level, progress.xp, xp_to_next_level(progress.level)));
+        y += 2;
+    }
+
+    if let Some(attrs) = attributes.get(*player_entity) {
+        ctx.print_color(17, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Attributes      base mod total");
+        y += 1;
+        attribute_line(ctx, y, "Might", &attrs.might);
+        attribute_line(ctx, y + 1, "Fitness", &attrs.fitness);
+        attribute_line(ctx, y + 2, "Quickness", &attrs.quickness);
+        attribute_line(ctx, y + 3, "Intelligence", &attrs.intelligence);
+        y += 5;
+    }
+
+    if let Some(skills) = skills.get(*player_entity) {
+        ctx.print_color(17, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Skills");
+        ctx.print(17, y + 1, format!("Melee    {:>+3}", skills.melee));
+        ctx.print(17, y + 2, format!("Defence  {:>+3}", skills.defence));
+        ctx.print(17, y + 3, format!("Magic    {:>+3}", skills.magic));
+        y += 5;
+    }
+
+    if let Some(stats) = combat_stats.get(*player_entity) {
+        ctx.print_color(17, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Derived");
+        ctx.print(17, y + 1, format!("Hit Points   {} / {}", stats.hp, stats.max_hp));
+        let hit_bonus = wielded_hit_bonus(*player_entity, &gs.ecs.entities(), &gs.ecs.read_storage::<NaturalAttack>(),
+            &gs.ecs.read_storage::<MeleeWeapon>(), &equipped);
+        ctx.print(17, y + 2, format!("To-Hit       {:+}", attack_bonus(hit_bonus, skills.get(*player_entity), stats)));
+        ctx.print(17, y + 3, format!("Damage       {:+}", stats.power));
+        ctx.print(17, y + 4, format!("Armour Class {}", BASE_ARMOUR_CLASS + stats.defense));
+        ctx.print(17, y + 5, format!("Initiative   {:+}", stats.initiative));
+        y += 7;
+    }
+
+    if let Some(burden) = gs.ecs.read_storage::<Encumbrance>().get(*player_entity) {
+        let colour = if burden.overburdened() { RGB::named(rltk::ORANGE) } else { RGB::named(rltk::WHITE) };
+        ctx.print_color(17, y, colour, RGB::named(rltk::BLACK), format!("Carrying     {:.1} / {:.0} lbs", burden.carried, burden.capacity));
+        ctx.print_color(17, y + 1, RGB::named(rltk::GOLD), RGB::named(rltk::BLACK), format!("Gold         {}", shop::gold_carried(&gs.ecs, *player_entity)));
+        y += 3;
+    }
+
+    ctx.print_color(17, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Equipment");
+    y += 1;
+    for (entity, worn, name) in (&gs.ecs.entities(), &equipped, &names).join() {
+        if worn.owner == *player_entity {
+            if known_curse(&gs.ecs, entity) {
+                ctx.print_color(17, y, RGB::named(rltk::RED), RGB::named(rltk::BLACK), format!("{} (cursed)", name.name));
+            } else {
+                ctx.print(17, y, &name.name);
+            }
+            y += 1;
+        }
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => ScreenResult::Close,
+        _ => ScreenResult::NoResponse
+    }
+}
+
+pub fn game_over(ecs : &World, ctx : &mut Rltk) -> ScreenResult {
+    let player_entity = ecs.fetch::<Entity>();
+    let run_stats = ecs.fetch::<RunStats>();
+    let progression = ecs.read_storage::<Progression>();
+
+    ctx.draw_box(15, 14, 49, 14, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color_centered(16, RGB::named(rltk::RED), RGB::named(rltk::BLACK), "Your journey has ended!");
+    if let Some(cause) = &run_stats.cause_of_death {
+        ctx.print_color_centered(18, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), cause);
+    }
+    if let Some(progress) = progression.get(*player_entity) {
+        ctx.print_color_centered(20, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), format!("You reached level {}.", progress.level));
+    }
+    ctx.print_color_centered(21, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), format!("You survived {} turns.", run_stats.turn));
+    ctx.print_color_centered(22, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), format!("You slew {} monsters.", run_stats.kills));
+    ctx.print_color_centered(25, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE TO QUIT");
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => ScreenResult::Close,
+        _ => ScreenResult::NoResponse
+    }
+}
+
+pub fn health_description(stats : &CombatStats) -> &'static str {
+    let ratio = stats.hp as f32 / i32::max(1, stats.max_hp) as f32;
+    if ratio >= 1.0 { "unhurt" }
+    else if ratio >= 0.75 { "lightly wounded" }
+    else if ratio >= 0.5 { "wounded" }
+    else if ratio >= 0.25 { "badly wounded" }
+    else { "almost dead" }
+}
+
+fn wrap_text(text : &str, width : usize) -> Vec<String> {
+    let mut lines : Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + word.len() + 1 > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() { line.push(' '); }
+        line.push_str(word);
+    }
+    if !line.is_empty() { lines.push(line); }
+    lines
+}
+
+// named entities the player can perceive, nearest to the player first
+fn visible_named_entities(ecs : &World) -> Vec<(Entity, Point)> {
+    let player_pos = ecs.fetch::<Point>();
+    let player_entity = ecs.fetch::<Entity>();
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let entities = ecs.entities();
+    let perceives = player_perceives(ecs);
+
+    let mut visible : Vec<(Entity, Point)> = (&entities, &names, &positions).join()
+        .filter(|(entity, _name, pos)| *entity != *player_entity && perceives(*entity, Point::new(pos.x, pos.y)))
+        .map(|(entity, _name, pos)| (entity, Point::new(pos.x, pos.y)))
+        .collect();
+    visible.sort_by(|a, b| {
+        let da = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, a.1);
+        let db = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, b.1);
+        da.partial_cmp(&db).unwrap()
+    });
+    visible
+}
+
+// the most interesting perceived entity under the cursor, preferring creatures over items
+fn look_target(ecs : &World, cursor : Point) -> Option<Entity> {
+    let map = ecs.fetch::<Map>();
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let entities = ecs.entities();
+
+    let perceives = player_perceives(ecs);
+
+    if cursor.x < 0 || cursor.x >= map.width || cursor.y < 0 || cursor.y >= map.height { return None; }
+
+    let mut found : Option<Entity> = None;
+    for (entity, _name, pos) in (&entities, &names, &positions).join() {
+        if pos.x == cursor.x && pos.y == cursor.y && perceives(entity, cursor) && (found.is_none() || combat_stats.get(entity).is_some()) {
+            found = Some(entity);
+        }
+    }
+    found
+}
+
+// the timed effects currently on an entity, as they read in the look panel
+fn status_effects(ecs : &World, entity : Entity) -> Vec<&'static str> {
+    let mut statuses = Vec::new();
+    if ecs.read_storage::<Poisoned>().get(entity).is_some() { statuses.push("poisoned"); }
+    if ecs.read_storage::<Confused>().get(entity).is_some() { statuses.push("confused"); }
+    if ecs.read_storage::<Blinded>().get(entity).is_some() { statuses.push("blind"); }
+    if ecs.read_storage::<Telepathic>().get(entity).is_some() { statuses.push("telepathic"); }
+    if ecs.read_storage::<SeeingInvisible>().get(entity).is_some() { statuses.push("seeing invisible"); }
+    if ecs.read_storage::<DetectingMonsters>().get(entity).is_some() { statuses.push("sensing monsters"); }
+    statuses
+}
+
+fn inspect_lines(ecs : &World, entity : Entity) -> Vec<(RGB, String)> {
+    let names = ecs.read_storage::<Name>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let equipped = ecs.read_storage::<Equipped>();
+    let descriptions = ecs.read_storage::<Description>();
+    let resistances = ecs.read_storage::<Resistances>();
+
+    let identifiables = ecs.read_storage::<Identifiable>();
+    let identification = ecs.fetch::<Identification>();
+
+    let mut lines : Vec<(RGB, String)> = Vec::new();
+    if names.get(entity).is_some() {
+        let colour = ecs.read_storage::<Rarity>().get(entity).map_or(RGB::named(rltk::YELLOW), |r| r.colour());
+        lines.push((colour, display_name(&names, &identifiables, &identification, entity)));
+    }
+    if let Some(stats) = combat_stats.get(entity) {
+        lines.push((RGB::named(rltk::WHITE), format!("It is {}.", health_description(stats))));
+    }
+    if known_curse(ecs, entity) {
+        lines.push((RGB::named(rltk::RED), "It is cursed.".to_string()));
+    }
+    let statuses = status_effects(ecs, entity);
+    if !statuses.is_empty() {
+        lines.push((RGB::named(rltk::MAGENTA), format!("Status: {}", statuses.join(", "))));
+    }
+
+    let gear : Vec<String> = (&equipped, &names).join()
+        .filter(|(worn, _name)| worn.owner == entity)
+        .map(|(_worn, name)| name.name.to_string())
+        .collect();
+    if !gear.is_empty() {
+        lines.push((RGB::named(rltk::CYAN), format!("Equipped: {}", gear.join(", "))));
+    }
+
+    if let Some(resistances) = resistances.get(entity) {
+        for (damage_type, response) in resistances.responses.iter() {
+            let text = match response {
+                DamageResponse::Resistant => format!("Resists {}", damage_type.noun()),
+                DamageResponse::Immune => format!("Immune to {}", damage_type.noun()),
+                DamageResponse::Vulnerable => format!("Vulnerable to {}", damage_type.noun())
+            };
+            lines.push((RGB::named(rltk::ORANGE), text));
+        }
+    }
+
+    // an unidentified item's description would give its nature away
+    if is_unidentified(&identifiables, &identification, entity) {
+        lines.push((RGB::named(rltk::GREY), "You do not know what it does.".to_string()));
+    } else if let Some(description) = descriptions.get(entity) {
+        for line in wrap_text(&description.text, 28) {
+            lines.push((RGB::named(rltk::GREY), line));
+        }
+    }
+    lines
+}
+
+// writes a short summary of what is under the cursor to the message log, so it can be followed without reading the panel
+pub fn announce_look(ecs : &World, cursor : Point) {
+    if let Some(entity) = look_target(ecs, cursor) {
+        let mut summary = vec![entity_name(ecs, entity)];
+        if let Some(stats) = ecs.read_storage::<CombatStats>().get(entity) {
+            summary.push(health_description(stats).to_string());
+        }
+        summary.extend(status_effects(ecs, entity).into_iter().map(|s| s.to_string()));
+        ecs.write_resource::<GameLog>().log(format!("You see: {}.", summary.join(", ")));
+    }
+}
+
+pub fn look_cursor(ecs : &World, ctx : &mut Rltk, cursor : Point) -> CursorResult {
+    ctx.print_color(2, 0, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Looking. TAB to cycle targets, ESCAPE to stop");
+
+    if let Some(entity) = look_target(ecs, cursor) {
+        let lines = inspect_lines(ecs, entity);
+        let x = if cursor.x > 40 { 1 } else { 48 };
+        ctx.draw_box(x, 2, 30, lines.len() as i32 + 1, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+        for (i, (colour, line)) in lines.iter().enumerate() {
+            ctx.print_color(x + 1, 3 + i as i32, *colour, RGB::named(rltk::BLACK), line);
+        }
+    }
+
+    if ctx.key == Some(VirtualKeyCode::Tab) {
+        let visible = visible_named_entities(ecs);
+        if visible.is_empty() { return CursorResult::NoResponse; }
+        let current = visible.iter().position(|(_entity, pos)| *pos == cursor);
+        let next = match current {
+            None => 0,
+            Some(i) => (i + 1) % visible.len()
+        };
+        return CursorResult::Moved(visible[next].1);
+    }
+    map_cursor(ecs, ctx, cursor)
+}
+
 fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
     let map = ecs.fetch::<Map>();
     let names = ecs.read_storage::<Name>();
@@ -131,17 +771,21 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
     let mouse_pos = ctx.mouse_pos();
     // only concerned about things within the map's limits
     if mouse_pos.0 >= map.width || mouse_pos.1 >= map.height { return; }
-    let mut tooltip : Vec<String> = Vec::new();
-    for (name, position) in (&names, &positions).join() {
-        let idx = map.xy_idx(position.x, position.y);
-        if position.x == mouse_pos.0 && position.y == mouse_pos.1 && map.visible_tiles[idx] {
-            tooltip.push(name.name.to_string());
+    let mut tooltip : Vec<(String, RGB)> = Vec::new();
+    let identifiables = ecs.read_storage::<Identifiable>();
+    let identification = ecs.fetch::<Identification>();
+    let rarities = ecs.read_storage::<Rarity>();
+    let entities = ecs.entities();
+    let perceives = player_perceives(ecs);
+    for (entity, _name, position) in (&entities, &names, &positions).join() {
+        if position.x == mouse_pos.0 && position.y == mouse_pos.1 && perceives(entity, Point::new(position.x, position.y)) {
+            tooltip.push((display_name(&names, &identifiables, &identification, entity), rarity_colour(&rarities, entity)));
         }
     }
 
     if !tooltip.is_empty() {
         let mut width:i32 = 0;
-        for s in tooltip.iter() {
+        for (s, _colour) in tooltip.iter() {
             if width < s.len() as i32 { width = s.len() as i32; }
         }
         width += 3;
@@ -150,30 +794,164 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
         if mouse_pos.0 > 40 {
             let arrow_pos = Point::new(mouse_pos.0 - 2, mouse_pos.1);
             let left_x = mouse_pos.0 - width;
-            let mut y = mouse_pos.1;
 
-            for s in tooltip.iter() {
-                ctx.print_color(left_x, y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), s);
+            for (y, (s, colour)) in (mouse_pos.1..).zip(tooltip.iter()) {
+                ctx.print_color(left_x, y, *colour, RGB::named(rltk::DARKBLUE), s);
                 let padding = (width - s.len() as i32) - 1;
                 for i in 0..padding {
-                    ctx.print_color(arrow_pos.x - i, y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), &" ".to_string());
+                    ctx.print_color(arrow_pos.x - i, y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), " ".to_string());
                 }
-                y += 1;
             }
-            ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), &"->".to_string());
+            ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), "->".to_string());
         } else { // print to right of target
             let arrow_pos = Point::new(mouse_pos.0 + 1, mouse_pos.1);
             let left_x = mouse_pos.0 + 3;
-            let mut y = mouse_pos.1;
-            for s in tooltip.iter() {
-                ctx.print_color(left_x + 1, y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), s);
+            for (y, (s, colour)) in (mouse_pos.1..).zip(tooltip.iter()) {
+                ctx.print_color(left_x + 1, y, *colour, RGB::named(rltk::DARKBLUE), s);
                 let padding = (width - s.len() as i32) - 1;
                 for i in 0..padding {
-                    ctx.print_color(arrow_pos.x + 1 + i, y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), &" ".to_string());
+                    ctx.print_color(arrow_pos.x + 1 + i, y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), " ".to_string());
                 }
-                y += 1;
             }
-            ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), &"<-".to_string());
+            ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::DARKBLUE), "<-".to_string());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DamageType, EquipmentSlot, Monster, Invisible};
+    use rltk::RandomNumberGenerator;
+    use std::collections::HashMap;
+
+    fn world() -> World {
+        let mut ecs = World::new();
+        ecs.register::<Name>();
+        ecs.register::<Position>();
+        ecs.register::<CombatStats>();
+        ecs.register::<Equipped>();
+        ecs.register::<Description>();
+        ecs.register::<Resistances>();
+        ecs.register::<Rarity>();
+        ecs.register::<Cursed>();
+        ecs.register::<CurseUnknown>();
+        let mut map = Map::with_floor(&[(5, 5), (6, 5)]);
+        let idx = map.xy_idx(5, 5);
+        map.visible_tiles[idx] = true;
+        ecs.insert(map);
+        ecs.register::<Identifiable>();
+        ecs.insert(Identification::new(1, &mut RandomNumberGenerator::seeded(1)));
+        ecs.register::<Monster>();
+        ecs.register::<Invisible>();
+        ecs.register::<SeeingInvisible>();
+        ecs.register::<DetectingMonsters>();
+        ecs.register::<Telepathic>();
+        ecs.register::<Poisoned>();
+        ecs.register::<Confused>();
+        ecs.register::<Blinded>();
+        ecs.insert(GameLog::default());
+        let player = ecs.create_entity().build();
+        ecs.insert(player);
+        ecs.insert(Point::new(1, 1));
+        ecs
+    }
+
+    #[test]
+    fn wounds_are_described_by_how_much_health_is_left() {
+        let stats = |hp| CombatStats{ max_hp: 20, hp, defense: 0, power: 0, initiative: 0 };
+        assert_eq!(health_description(&stats(20)), "unhurt");
+        assert_eq!(health_description(&stats(15)), "lightly wounded");
+        assert_eq!(health_description(&stats(10)), "wounded");
+        assert_eq!(health_description(&stats(5)), "badly wounded");
+        assert_eq!(health_description(&stats(1)), "almost dead");
+    }
+
+    #[test]
+    fn descriptions_wrap_on_word_boundaries() {
+        assert_eq!(wrap_text("a squat and ugly thing", 10), vec!["a squat", "and ugly", "thing"]);
+    }
+
+    #[test]
+    fn looking_prefers_creatures_and_ignores_unseen_tiles() {
+        let mut ecs = world();
+        ecs.create_entity().with(Name{ name: "Dagger".to_string() }).with(Position{ x: 5, y: 5 }).build();
+        let orc = ecs.create_entity()
+            .with(Name{ name: "Orc".to_string() })
+            .with(Position{ x: 5, y: 5 })
+            .with(CombatStats{ max_hp: 16, hp: 16, defense: 1, power: 4, initiative: 0 })
+            .build();
+        ecs.create_entity().with(Name{ name: "Goblin".to_string() }).with(Position{ x: 6, y: 5 }).build();
+
+        assert_eq!(look_target(&ecs, Point::new(5, 5)), Some(orc));
+        assert_eq!(look_target(&ecs, Point::new(6, 5)), None);
+        assert_eq!(look_target(&ecs, Point::new(-1, 5)), None);
+    }
+
+    #[test]
+    fn inspecting_lists_health_gear_resistances_and_description() {
+        let mut ecs = world();
+        let mut responses = HashMap::new();
+        responses.insert(DamageType::Fire, DamageResponse::Immune);
+        let troll = ecs.create_entity()
+            .with(Name{ name: "Troll".to_string() })
+            .with(CombatStats{ max_hp: 20, hp: 10, defense: 1, power: 4, initiative: 0 })
+            .with(Resistances{ responses })
+            .with(Description{ text: "Big.".to_string() })
+            .build();
+        ecs.create_entity()
+            .with(Name{ name: "Club".to_string() })
+            .with(Equipped{ owner: troll, slot: EquipmentSlot::Melee })
+            .build();
+
+        let lines : Vec<String> = inspect_lines(&ecs, troll).into_iter().map(|(_colour, line)| line).collect();
+        assert_eq!(lines, vec!["Troll", "It is wounded.", "Equipped: Club", "Immune to flames", "Big."]);
+    }
+
+    #[test]
+    fn looking_lists_status_effects_and_logs_a_summary() {
+        let mut ecs = world();
+        ecs.create_entity()
+            .with(Name{ name: "Orc".to_string() })
+            .with(Position{ x: 5, y: 5 })
+            .with(CombatStats{ max_hp: 16, hp: 16, defense: 1, power: 4, initiative: 0 })
+            .with(Poisoned{ turns: 3, damage: 1, source: None })
+            .with(Confused{ turns: 2 })
+            .build();
+
+        announce_look(&ecs, Point::new(5, 5));
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].text(), "You see: Orc, unhurt, poisoned, confused.");
+        // nothing in view leaves the log alone
+        announce_look(&ecs, Point::new(6, 5));
+        assert_eq!(ecs.fetch::<GameLog>().entries.len(), 1);
+    }
+
+    #[test]
+    fn the_targeting_preview_follows_the_cursor() {
+        let mut ecs = World::new();
+        ecs.register::<Ranged>();
+        ecs.register::<AreaOfEffect>();
+        let floor : Vec<(i32, i32)> = (1..=12).map(|x| (x, 5)).collect();
+        let mut map = Map::with_floor(&floor);
+        for (x, y) in floor.iter() {
+            let idx = map.xy_idx(*x, *y);
+            map.visible_tiles[idx] = true;
+        }
+        ecs.insert(map);
+        ecs.insert(Point::new(1, 5));
+        let scroll = ecs.create_entity().with(Ranged{ range: 6 }).with(AreaOfEffect{ radius: 1 }).build();
+
+        let mut preview = TargetingPreview::default();
+        preview.refresh(&ecs, scroll, Point::new(4, 5));
+        assert_eq!(preview.reachable.len(), 7);
+        assert!(preview.blast.contains(&Point::new(3, 5)) && preview.blast.contains(&Point::new(5, 5)));
+        assert!(!preview.blast.contains(&Point::new(6, 5)));
+
+        preview.refresh(&ecs, scroll, Point::new(6, 5));
+        assert!(preview.blast.contains(&Point::new(7, 5)) && !preview.blast.contains(&Point::new(4, 5)));
+
+        // out of reach, nothing is shown as caught in the blast
+        preview.refresh(&ecs, scroll, Point::new(10, 5));
+        assert!(preview.blast.is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use specs::prelude::*;
+use rltk::RandomNumberGenerator;
+use std::collections::{HashMap, HashSet};
+use crate::{ItemTemplate, Identifiable, Name};
+
+#[derive(PartialEq, Copy, Clone)]
+enum Disguise { Potion, Scroll }
+
+// magic items that start every run unidentified, and what sort of disguise they wear
+const MAGIC_ITEMS : [(ItemTemplate, Disguise); 13] = [
+    (ItemTemplate::HealthPotion, Disguise::Potion),
+    (ItemTemplate::IdentifyScroll, Disguise::Scroll),
+    (ItemTemplate::RemoveCurseScroll, Disguise::Scroll),
+    (ItemTemplate::MagicMappingScroll, Disguise::Scroll),
+    (ItemTemplate::DetectMonstersPotion, Disguise::Potion),
+    (ItemTemplate::FireballScroll, Disguise::Scroll),
+    (ItemTemplate::PoisonCloudScroll, Disguise::Scroll),
+    (ItemTemplate::TeleportScroll, Disguise::Scroll),
+    (ItemTemplate::BlinkScroll, Disguise::Scroll),
+    (ItemTemplate::LevelTeleportScroll, Disguise::Scroll),
+    (ItemTemplate::TelepathyPotion, Disguise::Potion),
+    (ItemTemplate::SeeInvisiblePotion, Disguise::Potion),
+    (ItemTemplate::BlindnessPotion, Disguise::Potion)
+];
+
+/// The save file for a seed's item knowledge, e.g. `identification-42.sav`. It lives in the working directory,
+/// is written as items are identified and read back only when that same seed is played again, so runs on
+/// different seeds never share what they have learned.
+pub fn save_file(seed : u64) -> String {
+    format!("identification-{}.sav", seed)
+}
+
+const POTION_LOOKS : [&str; 8] = ["murky", "bubbling", "cloudy", "glowing", "oily", "fizzy", "viscous", "smoky"];
+const POTION_COLOURS : [&str; 8] = ["purple", "green", "amber", "crimson", "silver", "blue", "black", "pink"];
+const SCROLL_SYLLABLES : [&str; 12] = ["xo", "lum", "bar", "ka", "zed", "ith", "mor", "ul", "vex", "ra", "nok", "tha"];
+
+/// The disguises this run's magic items wear and which of them the player has seen through.
+/// Appearances are drawn from the seeded game rng, so a seed always produces the same ones.
+pub struct Identification {
+    seed : u64,
+    appearances : HashMap<ItemTemplate, String>,
+    known : HashSet<ItemTemplate>,
+    unsaved : bool
+}
+
+// the name a magic item's template goes by in the save file
+fn template_name(template : ItemTemplate) -> String {
+    format!("{:?}", template)
+}
+
+fn template_named(name : &str) -> Option<ItemTemplate> {
+    MAGIC_ITEMS.iter().map(|(template, _disguise)| *template).find(|t| template_name(*t) == name)
+}
+
+impl Identification {
+    pub fn new(seed : u64, rng : &mut RandomNumberGenerator) -> Identification {
+        let mut appearances = HashMap::new();
+        let mut used : HashSet<String> = HashSet::new();
+
+        for (template, disguise) in MAGIC_ITEMS.iter() {
+            // keep rolling until the appearance is not already worn by another item
+            let appearance = loop {
+                let candidate = match disguise {
+                    Disguise::Potion => format!("{} {} potion",
+                        POTION_LOOKS[(rng.roll_dice(1, POTION_LOOKS.len() as i32) - 1) as usize],
+                        POTION_COLOURS[(rng.roll_dice(1, POTION_COLOURS.len() as i32) - 1) as usize]),
+                    Disguise::Scroll => {
+                        let words : Vec<String> = (0..2).map(|_| {
+                            (0..rng.roll_dice(1, 2) + 1)
+                                .map(|_| SCROLL_SYLLABLES[(rng.roll_dice(1, SCROLL_SYLLABLES.len() as i32) - 1) as usize])
+                                .collect::<String>()
+                                .to_uppercase()
+                        }).collect();
+                        format!("scroll labelled {}", words.join(" "))
+                    }
+                };
+                if used.insert(candidate.clone()) { break candidate; }
+            };
+            appearances.insert(*template, appearance);
+        }
+
+        Identification{ seed, appearances, known : HashSet::new(), unsaved : false }
+    }
+
+    /// The save file's contents: the seed the run was started with, every appearance, and each kind already identified
+    pub fn to_save_text(&self) -> String {
+        let mut lines = vec![format!("seed {}", self.seed)];
+        for (template, _disguise) in MAGIC_ITEMS.iter() {
+            if let Some(appearance) = self.appearances.get(template) {
+                lines.push(format!("appearance {} {}", template_name(*template), appearance));
+            }
+        }
+        for (template, _disguise) in MAGIC_ITEMS.iter() {
+            if self.known.contains(template) {
+                lines.push(format!("known {}", template_name(*template)));
+            }
+        }
+        lines.join("\n")
+    }
+
+    pub fn from_save_text(text : &str) -> Result<Identification, String> {
+        let mut seed = None;
+        let mut appearances = HashMap::new();
+        let mut known = HashSet::new();
+
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let mut words = line.splitn(3, ' ');
+            match (words.next(), words.next(), words.next()) {
+                (Some("seed"), Some(value), None) => seed = Some(value.parse::<u64>().map_err(|_| format!("bad seed '{}'", value))?),
+                (Some("appearance"), Some(name), Some(appearance)) => {
+                    let template = template_named(name).ok_or(format!("unknown item '{}'", name))?;
+                    appearances.insert(template, appearance.to_string());
+                }
+                (Some("known"), Some(name), None) => {
+                    known.insert(template_named(name).ok_or(format!("unknown item '{}'", name))?);
+                }
+                _ => return Err(format!("unreadable line '{}'", line))
+            }
+        }
+
+        let seed = seed.ok_or("no seed")?;
+        if appearances.len() != MAGIC_ITEMS.len() { return Err("missing appearances".to_string()); }
+        Ok(Identification{ seed, appearances, known, unsaved : false })
+    }
+
+    /// What was learned in an earlier session of the run started from `seed`, if it was saved
+    pub fn load(seed : u64) -> Option<Identification> {
+        let text = std::fs::read_to_string(save_file(seed)).ok()?;
+        Identification::from_save_text(&text).ok().filter(|saved| saved.seed == seed)
+    }
+
+    /// Where this run's item knowledge is saved
+    pub fn save_path(&self) -> String {
+        save_file(self.seed)
+    }
+
+    /// Writes the run's item knowledge to its seed's save file if anything has been identified since it was last saved
+    pub fn save_if_changed(&mut self) -> std::io::Result<()> {
+        if !self.unsaved { return Ok(()); }
+        std::fs::write(self.save_path(), self.to_save_text())?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    pub fn is_known(&self, template : ItemTemplate) -> bool {
+        self.known.contains(&template) || !self.appearances.contains_key(&template)
+    }
+
+    // learns what a kind of item is, returning true if it was not already known
+    pub fn identify(&mut self, template : ItemTemplate) -> bool {
+        let learned = self.appearances.contains_key(&template) && self.known.insert(template);
+        self.unsaved |= learned;
+        learned
+    }
+
+    fn name_for(&self, template : ItemTemplate, real_name : &str) -> String {
+        if self.is_known(template) {
+            real_name.to_string()
+        } else {
+            self.appearances[&template].clone()
+        }
+    }
+}
+
+/// What the player calls an entity: its real name, or its disguise while that is still unidentified
+pub fn display_name(names : &ReadStorage<Name>, identifiables : &ReadStorage<Identifiable>, identification : &Identification, entity : Entity) -> String {
+    let real_name = names.get(entity).map_or("something", |n| &n.name);
+    match identifiables.get(entity) {
+        Some(identifiable) => identification.name_for(identifiable.template, real_name),
+        None => real_name.to_string()
+    }
+}
+
+pub fn entity_name(ecs : &World, entity : Entity) -> String {
+    display_name(&ecs.read_storage::<Name>(), &ecs.read_storage::<Identifiable>(), &ecs.fetch::<Identification>(), entity)
+}
+
+// true for an item whose kind the player has yet to work out
+pub fn is_unidentified(identifiables : &ReadStorage<Identifiable>, identification : &Identification, entity : Entity) -> bool {
+    identifiables.get(entity).is_some_and(|i| !identification.is_known(i.template))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identification(seed : u64) -> Identification {
+        Identification::new(seed, &mut RandomNumberGenerator::seeded(seed))
+    }
+
+    #[test]
+    fn every_magic_item_wears_its_own_disguise() {
+        let id = identification(7);
+        let looks : HashSet<&String> = id.appearances.values().collect();
+        assert_eq!(id.appearances.len(), MAGIC_ITEMS.len());
+        assert_eq!(looks.len(), MAGIC_ITEMS.len());
+        for (template, disguise) in MAGIC_ITEMS.iter() {
+            let appearance = &id.appearances[template];
+            match disguise {
+                Disguise::Potion => assert!(appearance.ends_with(" potion")),
+                Disguise::Scroll => assert!(appearance.starts_with("scroll labelled ")),
+            }
+        }
+    }
+
+    #[test]
+    fn a_seed_always_deals_out_the_same_disguises() {
+        assert_eq!(identification(42).appearances, identification(42).appearances);
+    }
+
+    #[test]
+    fn identifying_reveals_the_real_name() {
+        let mut id = identification(3);
+        assert!(!id.is_known(ItemTemplate::IdentifyScroll));
+        assert!(id.identify(ItemTemplate::IdentifyScroll));
+        assert!(!id.identify(ItemTemplate::IdentifyScroll));
+        assert_eq!(id.name_for(ItemTemplate::IdentifyScroll, "Scroll of Identify"), "Scroll of Identify");
+    }
+
+    #[test]
+    fn save_text_round_trips() {
+        let mut id = identification(11);
+        id.identify(ItemTemplate::HealthPotion);
+        id.identify(ItemTemplate::BlinkScroll);
+        let loaded = Identification::from_save_text(&id.to_save_text()).expect("save text should load");
+        assert_eq!(loaded.seed, 11);
+        assert_eq!(loaded.appearances, id.appearances);
+        assert_eq!(loaded.known, id.known);
+    }
+
+    #[test]
+    fn save_text_with_missing_appearances_is_refused() {
+        assert!(Identification::from_save_text("seed 11\nknown HealthPotion").is_err());
+        assert!(Identification::from_save_text("seed eleven").is_err());
+    }
+
+    #[test]
+    fn each_seed_saves_to_its_own_file() {
+        assert_eq!(identification(11).save_path(), "identification-11.sav");
+        assert_ne!(identification(11).save_path(), identification(12).save_path());
+    }
+}
@@ -1,29 +1,143 @@
 use specs::prelude::*;
-use crate::game_log::GameLog;
-use crate::{WantsToPickupItem, Position, InBackpack, Name, WantsToDrinkPotion, Potion, CombatStats, WantsToDropItem};
+use rltk::RGB;
+use crate::game_log::{GameLog, entity_colour};
+use crate::{WantsToPickupItem, Position, InBackpack, Name, WantsToUseItem, Consumable, WantsToDropItem,
+            Equippable, Equipped, WantsToRemoveItem, Renderable, Stackable, Quantity, Identifiable, IdentifiesItem,
+            Cursed, CurseUnknown, RemovesCurse, Gold, Purse, TeachesSpell, KnownSpells, Spell,
+            MeleeWeapon, DefenseBonus, AttributeBonus};
+use crate::spawner;
+use crate::identification::{Identification, display_name};
+
+// an item's name with the size of its stack, e.g. "Health Potion (x4)"
+pub fn stack_name(name : &str, quantity : Option<&Quantity>) -> String {
+    match quantity {
+        Some(quantity) if quantity.amount > 1 => format!("{} (x{})", name, quantity.amount),
+        _ => name.to_string()
+    }
+}
+
+/// Splits `amount` items off a stack in a backpack, returning the entity holding them.
+/// Asking for the whole stack (or an item that does not stack) returns the item itself.
+pub fn split_stack(ecs : &mut World, item : Entity, amount : i32) -> Entity {
+    let (template, owner) = {
+        let stackables = ecs.read_storage::<Stackable>();
+        let backpack = ecs.read_storage::<InBackpack>();
+        let mut quantities = ecs.write_storage::<Quantity>();
+        match (stackables.get(item), backpack.get(item), quantities.get_mut(item)) {
+            (Some(stackable), Some(pack), Some(quantity)) if amount < quantity.amount => {
+                quantity.amount -= amount;
+                (stackable.template, pack.owner)
+            }
+            _ => return item
+        }
+    };
+
+    spawner::stackable_item(ecs, template)
+        .with(InBackpack{ owner })
+        .with(Quantity{ amount })
+        .build()
+}
+
+/// Puts an item into `owner`'s backpack, merging it into a stack of the same template if they already have one.
+/// Returns the entity the item ended up as.
+pub fn stow(ecs : &mut World, item : Entity, owner : Entity) -> Entity {
+    let existing_stack = {
+        let entities = ecs.entities();
+        let backpack = ecs.read_storage::<InBackpack>();
+        let stackables = ecs.read_storage::<Stackable>();
+        stackables.get(item).and_then(|stackable| {
+            (&entities, &backpack, &stackables).join()
+                .find(|(carried, pack, carried_stackable)| *carried != item && pack.owner == owner && carried_stackable.template == stackable.template)
+                .map(|(carried, _pack, _stackable)| carried)
+        })
+    };
+
+    ecs.write_storage::<Position>().remove(item);
+    match existing_stack {
+        Some(stack) => {
+            let mut quantities = ecs.write_storage::<Quantity>();
+            let amount = quantities.get(item).map_or(1, |q| q.amount);
+            if let Some(quantity) = quantities.get_mut(stack) { quantity.amount += amount; }
+            ecs.entities().delete(item).expect("Delete failed");
+            stack
+        }
+        None => {
+            ecs.write_storage::<InBackpack>().insert(item, InBackpack{ owner }).expect("Unable to insert backpack entry");
+            item
+        }
+    }
+}
 
 pub struct ItemCollectionSystem {}
 
 impl <'a> System<'a> for ItemCollectionSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         ReadExpect<'a, Entity>,
         WriteExpect<'a, GameLog>,
+        Entities<'a>,
         WriteStorage<'a, WantsToPickupItem>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Name>,
-        WriteStorage<'a, InBackpack>
+        WriteStorage<'a, InBackpack>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Stackable>,
+        WriteStorage<'a, Quantity>,
+        ReadStorage<'a, Identifiable>,
+        ReadExpect<'a, Identification>,
+        ReadStorage<'a, Gold>,
+        WriteStorage<'a, Purse>
     );
 
     fn run(&mut self, data : Self::SystemData) {
-        let (player_entity, mut gamelog, mut wants_pickup, mut positions, names, mut backpack) = data;
+        let (player_entity, mut gamelog, entities, mut wants_pickup, mut positions, names, mut backpack, renderables, stackables, mut quantities,
+            identifiables, identification, gold, mut purses) = data;
 
         // picking up an item removes it's Position component so it won't be displayed on the game map
         for pickup in wants_pickup.join() {
             positions.remove(pickup.item);
-            backpack.insert(pickup.item, InBackpack{ owner: pickup.collected_by}).expect("Unable to insert backpack entry");
+
+            // coins go straight into the purse rather than the backpack
+            if let Some(coins) = gold.get(pickup.item) {
+                if let Some(purse) = purses.get_mut(pickup.collected_by) {
+                    purse.gold += coins.amount;
+                    if pickup.collected_by == *player_entity {
+                        gamelog.entry().text("You pick up ").colour(RGB::named(rltk::GOLD), format!("{} gold", coins.amount))
+                            .text(format!(", now carrying {}.", purse.gold)).log();
+                    }
+                }
+                entities.delete(pickup.item).expect("Delete failed");
+                continue;
+            }
+
+            // a stackable item joins a stack of the same template already being carried
+            let mut existing_stack : Option<Entity> = None;
+            if let Some(stackable) = stackables.get(pickup.item) {
+                for (carried, pack, carried_stackable) in (&entities, &backpack, &stackables).join() {
+                    if carried != pickup.item && pack.owner == pickup.collected_by && carried_stackable.template == stackable.template {
+                        existing_stack = Some(carried);
+                    }
+                }
+            }
+
+            let picked_name = stack_name(&display_name(&names, &identifiables, &identification, pickup.item), quantities.get(pickup.item));
+            let mut carried = String::new();
+            match existing_stack {
+                Some(stack) => {
+                    let amount = quantities.get(pickup.item).map_or(1, |q| q.amount);
+                    let total = quantities.get(stack).map_or(1, |q| q.amount) + amount;
+                    quantities.insert(stack, Quantity{ amount: total }).expect("Unable to insert quantity");
+                    entities.delete(pickup.item).expect("Delete failed");
+                    carried = format!(", now carrying {}", total);
+                }
+                None => {
+                    backpack.insert(pickup.item, InBackpack{ owner: pickup.collected_by}).expect("Unable to insert backpack entry");
+                }
+            }
 
             if pickup.collected_by == *player_entity {
-                gamelog.entries.push(format!("You have picked up a {}.", names.get(pickup.item).unwrap().name));
+                gamelog.entry().text("You have picked up the ")
+                    .colour(entity_colour(&renderables, pickup.item), picked_name).text(format!("{}.", carried)).log();
             }
         }
 
@@ -34,6 +148,7 @@ impl <'a> System<'a> for ItemCollectionSystem {
 pub struct ItemDropSystem {}
 
 impl <'a> System<'a> for ItemDropSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         ReadExpect<'a, Entity>,
         WriteExpect<'a, GameLog>,
@@ -41,24 +156,45 @@ impl <'a> System<'a> for ItemDropSystem {
         WriteStorage<'a, WantsToDropItem>,
         ReadStorage<'a, Name>,
         WriteStorage<'a, Position>,
-        WriteStorage<'a, InBackpack>
+        WriteStorage<'a, InBackpack>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Quantity>,
+        ReadStorage<'a, Identifiable>,
+        ReadExpect<'a, Identification>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Cursed>,
+        WriteStorage<'a, CurseUnknown>
     );
 
     fn run (&mut self, data : Self::SystemData) {
-        let (player_entity, mut gamelog, entities, mut wants_to_drop, names, mut positions, mut backpack) = data;
+        let (player_entity, mut gamelog, entities, mut wants_to_drop, names, mut positions, mut backpack, renderables, quantities,
+            identifiables, identification, equipped, cursed, mut curse_unknown) = data;
 
         for (entity, to_drop) in (&entities, &wants_to_drop).join() {
+            // cursed gear stays on its wearer until the curse is lifted
+            if equipped.get(to_drop.item).is_some() && cursed.get(to_drop.item).is_some() {
+                curse_unknown.remove(to_drop.item);
+                if entity == *player_entity {
+                    gamelog.entry().text("You cannot drop the ").colour(entity_colour(&renderables, to_drop.item), &names.get(to_drop.item).unwrap().name)
+                        .colour(RGB::named(rltk::RED), ", it is cursed!").log();
+                }
+                continue;
+            }
+
             let mut dropper_pos : Position = Position{x:0, y:0};
             {
                 let dropped_pos = positions.get(entity).unwrap();
                 dropper_pos.x = dropped_pos.x;
                 dropper_pos.y = dropped_pos.y;
             }
-            positions.insert(to_drop.item, Position{ x: dropper_pos.x, y: dropper_pos.y});
+            positions.insert(to_drop.item, Position{ x: dropper_pos.x, y: dropper_pos.y}).expect("Unable to insert position");
             backpack.remove(to_drop.item);
 
             if entity == *player_entity {
-                gamelog.entries.push(format!("You drop the {}.", names.get(to_drop.item).unwrap().name));
+                gamelog.entry().text("You drop the ")
+                    .colour(entity_colour(&renderables, to_drop.item),
+                        stack_name(&display_name(&names, &identifiables, &identification, to_drop.item), quantities.get(to_drop.item)))
+                    .text(".").log();
             }
         }
 
@@ -66,36 +202,355 @@ impl <'a> System<'a> for ItemDropSystem {
     }
 }
 
-pub struct PotionUseSystem {}
+pub struct ItemUseSystem {}
 
-impl <'a> System<'a> for PotionUseSystem {
+impl <'a> System<'a> for ItemUseSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         ReadExpect<'a, Entity>,
         WriteExpect<'a, GameLog>,
         Entities<'a>,
-        WriteStorage<'a, WantsToDrinkPotion>,
+        WriteStorage<'a, WantsToUseItem>,
         ReadStorage<'a, Name>,
-        ReadStorage<'a, Potion>,
-        WriteStorage<'a, CombatStats>
+        ReadStorage<'a, Consumable>,
+        ReadStorage<'a, Equippable>,
+        WriteStorage<'a, Equipped>,
+        WriteStorage<'a, InBackpack>,
+        ReadStorage<'a, Renderable>,
+        WriteStorage<'a, Quantity>,
+        ReadStorage<'a, Identifiable>,
+        ReadStorage<'a, IdentifiesItem>,
+        WriteExpect<'a, Identification>,
+        WriteStorage<'a, Cursed>,
+        WriteStorage<'a, CurseUnknown>,
+        ReadStorage<'a, RemovesCurse>,
+        ReadStorage<'a, TeachesSpell>,
+        WriteStorage<'a, KnownSpells>,
+        ReadStorage<'a, Spell>,
+        WriteStorage<'a, MeleeWeapon>,
+        WriteStorage<'a, DefenseBonus>,
+        WriteStorage<'a, AttributeBonus>
     );
 
     fn run (&mut self, data : Self::SystemData) {
-        let (player_entity, mut gamelog, entities, mut wants_drink, names, potions, mut combat_stats) = data;
-
-        for (entity, drink, stats) in (&entities, &wants_drink, &mut combat_stats).join() {
-            let potion = potions.get(drink.potion);
-            match potion {
-                None => {},
-                Some(potion) => {
-                    stats.hp = i32::min(stats.max_hp, stats.hp + potion.amount);
+        let (player_entity, mut gamelog, entities, mut wants_use, names, consumables, equippable, mut equipped, mut backpack,
+            renderables, mut quantities, identifiables, identifies, mut identification, mut cursed, mut curse_unknown, removes_curse,
+            teaches_spell, mut known_spells, spells, mut weapons, mut defense_bonuses, mut attribute_bonuses) = data;
+
+        for (entity, useitem) in (&entities, &wants_use).join() {
+            // named before any identifying happens, so the log says what the player thought they were using
+            let item_name = display_name(&names, &identifiables, &identification, useitem.item);
+            // an identify scroll with nothing to work on is rolled back up rather than wasted
+            let mut kept = false;
+
+            // equipping an item swaps out whatever currently occupies the same slot
+            if let Some(can_equip) = equippable.get(useitem.item) {
+                let target_slot = can_equip.slot;
+
+                let mut to_unequip : Vec<Entity> = Vec::new();
+                let mut stuck : Option<Entity> = None;
+                for (item_entity, already_equipped) in (&entities, &equipped).join() {
+                    if already_equipped.owner == entity && already_equipped.slot == target_slot {
+                        if cursed.get(item_entity).is_some() { stuck = Some(item_entity); }
+                        to_unequip.push(item_entity);
+                    }
+                }
+
+                // a cursed item in the slot refuses to make way
+                if let Some(stuck) = stuck {
+                    curse_unknown.remove(stuck);
+                    if entity == *player_entity {
+                        gamelog.entry().text("You cannot take off the ").colour(entity_colour(&renderables, stuck), &names.get(stuck).unwrap().name)
+                            .colour(RGB::named(rltk::RED), ", it is cursed!").log();
+                    }
+                    continue;
+                }
+
+                for item in to_unequip.iter() {
+                    equipped.remove(*item);
+                    backpack.insert(*item, InBackpack{ owner: entity }).expect("Unable to insert backpack entry");
+                    if entity == *player_entity {
+                        gamelog.entry().text("You unequip the ").colour(entity_colour(&renderables, *item), &names.get(*item).unwrap().name).text(".").log();
+                    }
+                }
+
+                equipped.insert(useitem.item, Equipped{ owner: entity, slot: target_slot }).expect("Unable to insert equipped component");
+                backpack.remove(useitem.item);
+                if entity == *player_entity {
+                    gamelog.entry().text("You equip the ")
+                        .colour(entity_colour(&renderables, useitem.item), &names.get(useitem.item).unwrap().name).text(".").log();
+                }
+
+                // putting something on is the surest way to find out it is cursed
+                if curse_unknown.remove(useitem.item).is_some() && cursed.get(useitem.item).is_some() && entity == *player_entity {
+                    gamelog.entry().colour(RGB::named(rltk::RED), "It tightens around you. It is cursed!").log();
+                }
+            }
+
+            if identifies.get(useitem.item).is_some() && entity == *player_entity {
+                gamelog.entry().text("You read the ").colour(entity_colour(&renderables, useitem.item), &item_name).text(".").log();
+                match useitem.target_item {
+                    Some(target) => {
+                        let target_colour = entity_colour(&renderables, target);
+                        let old_name = display_name(&names, &identifiables, &identification, target);
+                        if let Some(identifiable) = identifiables.get(target) {
+                            identification.identify(identifiable.template);
+                            gamelog.entry().text("The ").colour(target_colour, old_name).text(" is a ")
+                                .colour(target_colour, display_name(&names, &identifiables, &identification, target)).text("!").log();
+                        } else if curse_unknown.remove(target).is_some() {
+                            if cursed.get(target).is_some() {
+                                gamelog.entry().text("The ").colour(target_colour, old_name).colour(RGB::named(rltk::RED), " is cursed!").log();
+                            } else {
+                                gamelog.entry().text("The ").colour(target_colour, old_name).text(" is free of curses.").log();
+                            }
+                        }
+                    }
+                    None => {
+                        gamelog.log("You have nothing else to identify, so you roll the scroll back up.");
+                        kept = true;
+                    }
+                }
+            }
+
+            if removes_curse.get(useitem.item).is_some() {
+                let lifted : Vec<Entity> = (&entities, &cursed).join()
+                    .filter(|(item, _cursed)| equipped.get(*item).is_some_and(|e| e.owner == entity) || backpack.get(*item).is_some_and(|b| b.owner == entity))
+                    .map(|(item, _cursed)| item)
+                    .collect();
+                for item in lifted.iter() {
+                    // the item gets back whatever the curse took from it
+                    if let Some(curse) = cursed.remove(*item) {
+                        if let Some(weapon) = weapons.get_mut(*item) { weapon.hit_bonus += curse.hit_penalty; }
+                        if curse.defense_penalty != 0 {
+                            match defense_bonuses.get_mut(*item) {
+                                Some(bonus) => bonus.defense += curse.defense_penalty,
+                                None => { defense_bonuses.insert(*item, DefenseBonus{ defense: curse.defense_penalty }).expect("Unable to insert defense bonus"); }
+                            }
+                        }
+                        if curse.might_penalty != 0 {
+                            match attribute_bonuses.get_mut(*item) {
+                                Some(bonus) => bonus.might += curse.might_penalty,
+                                None => { attribute_bonuses.insert(*item, AttributeBonus{ might: curse.might_penalty, fitness: 0, quickness: 0, intelligence: 0 }).expect("Unable to insert attribute bonus"); }
+                            }
+                        }
+                    }
+                    curse_unknown.remove(*item);
+                }
+                if entity == *player_entity {
+                    gamelog.entry().text("You read the ").colour(entity_colour(&renderables, useitem.item), &item_name).text(".").log();
+                    if lifted.is_empty() {
+                        gamelog.log("You feel as if someone is watching over you.");
+                    } else {
+                        gamelog.entry().colour(RGB::named(rltk::GREEN), "A weight lifts from your belongings.").log();
+                    }
+                }
+            }
+
+            // a spellbook crumbles once learned; one the reader already knows is left alone
+            if let Some(teaches) = teaches_spell.get(useitem.item) {
+                let spell_name = &names.get(teaches.spell).unwrap().name;
+                if let Some(known) = known_spells.get_mut(entity) {
+                    let template = spells.get(teaches.spell).unwrap().template;
+                    if known.spells.iter().any(|s| spells.get(*s).is_some_and(|k| k.template == template)) {
+                        if entity == *player_entity {
+                            gamelog.entry().text("You already know how to cast ").colour(RGB::named(rltk::CYAN), spell_name).text(".").log();
+                        }
+                        continue;
+                    }
+                    known.spells.push(teaches.spell);
                     if entity == *player_entity {
-                        gamelog.entries.push(format!("You drink the {}, healing {} hp.", names.get(drink.potion).unwrap().name, potion.amount));
+                        gamelog.entry().text("You study the ").colour(entity_colour(&renderables, useitem.item), &item_name)
+                            .text(" and learn to cast ").colour(RGB::named(rltk::CYAN), spell_name).text(".").log();
                     }
-                    entities.delete(drink.potion).expect("Delete failed");
                 }
             }
+
+            // using a magic item reveals what it is, for every other one like it too
+            if let Some(identifiable) = identifiables.get(useitem.item) {
+                if identification.identify(identifiable.template) && entity == *player_entity {
+                    gamelog.entry().text("That was a ")
+                        .colour(entity_colour(&renderables, useitem.item), display_name(&names, &identifiables, &identification, useitem.item)).text(".").log();
+                }
+            }
+
+            // using up one of a stack leaves the rest behind
+            if consumables.get(useitem.item).is_some() && !kept {
+                match quantities.get_mut(useitem.item) {
+                    Some(quantity) if quantity.amount > 1 => quantity.amount -= 1,
+                    _ => entities.delete(useitem.item).expect("Delete failed")
+                }
+            }
+        }
+
+        wants_use.clear();
+    }
+}
+
+pub struct ItemRemoveSystem {}
+
+impl <'a> System<'a> for ItemRemoveSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToRemoveItem>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, Equipped>,
+        WriteStorage<'a, InBackpack>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Cursed>,
+        WriteStorage<'a, CurseUnknown>
+    );
+
+    fn run (&mut self, data : Self::SystemData) {
+        let (player_entity, mut gamelog, entities, mut wants_remove, names, mut equipped, mut backpack, renderables, cursed, mut curse_unknown) = data;
+
+        for (entity, to_remove) in (&entities, &wants_remove).join() {
+            if cursed.get(to_remove.item).is_some() {
+                curse_unknown.remove(to_remove.item);
+                if entity == *player_entity {
+                    gamelog.entry().text("You cannot take off the ").colour(entity_colour(&renderables, to_remove.item), &names.get(to_remove.item).unwrap().name)
+                        .colour(RGB::named(rltk::RED), ", it is cursed!").log();
+                }
+                continue;
+            }
+
+            equipped.remove(to_remove.item);
+            backpack.insert(to_remove.item, InBackpack{ owner: entity }).expect("Unable to insert backpack entry");
+
+            if entity == *player_entity {
+                gamelog.entry().text("You take off the ")
+                    .colour(entity_colour(&renderables, to_remove.item), &names.get(to_remove.item).unwrap().name).text(".").log();
+            }
         }
 
-        wants_drink.clear();
+        wants_remove.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Description, Item, ItemTemplate, Weight, Value, Gold, Purse, ProvidesHealing, EquipmentSlot};
+
+    fn world() -> (World, Entity) {
+        let mut ecs = World::new();
+        ecs.register::<Renderable>();
+        ecs.register::<Name>();
+        ecs.register::<Description>();
+        ecs.register::<Item>();
+        ecs.register::<Consumable>();
+        ecs.register::<ProvidesHealing>();
+        ecs.register::<Stackable>();
+        ecs.register::<Quantity>();
+        ecs.register::<Weight>();
+        ecs.register::<Value>();
+        ecs.register::<Gold>();
+        ecs.register::<Purse>();
+        ecs.register::<Identifiable>();
+        ecs.register::<InBackpack>();
+        ecs.register::<Position>();
+        ecs.register::<WantsToPickupItem>();
+        let player = ecs.create_entity().build();
+        ecs.insert(player);
+        ecs.insert(GameLog::default());
+        // the tests read potions by their real names
+        let mut identification = Identification::new(1, &mut rltk::RandomNumberGenerator::seeded(1));
+        identification.identify(ItemTemplate::HealthPotion);
+        ecs.insert(identification);
+        (ecs, player)
+    }
+
+    fn carried_potions(ecs : &mut World, owner : Entity, amount : i32) -> Entity {
+        spawner::stackable_item(ecs, ItemTemplate::HealthPotion).with(InBackpack{ owner }).with(Quantity{ amount }).build()
+    }
+
+    fn amount(ecs : &World, item : Entity) -> i32 {
+        let quantities = ecs.read_storage::<Quantity>();
+        quantities.get(item).unwrap().amount
+    }
+
+    #[test]
+    fn splitting_takes_items_off_the_stack() {
+        let (mut ecs, player) = world();
+        let stack = carried_potions(&mut ecs, player, 5);
+        let split = split_stack(&mut ecs, stack, 2);
+        assert!(split != stack);
+        assert_eq!(amount(&ecs, stack), 3);
+        assert_eq!(amount(&ecs, split), 2);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(split).unwrap().owner, player);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn splitting_off_the_whole_stack_returns_it() {
+        let (mut ecs, player) = world();
+        let stack = carried_potions(&mut ecs, player, 3);
+        assert!(split_stack(&mut ecs, stack, 3) == stack);
+        assert_eq!(amount(&ecs, stack), 3);
+    }
+
+    #[test]
+    fn picking_up_joins_the_stack_already_carried() {
+        let (mut ecs, player) = world();
+        let stack = carried_potions(&mut ecs, player, 2);
+        let found = spawner::stackable_item(&mut ecs, ItemTemplate::HealthPotion)
+            .with(Position{ x: 1, y: 1 }).with(Quantity{ amount: 3 }).build();
+        ecs.write_storage::<WantsToPickupItem>()
+            .insert(player, WantsToPickupItem{ collected_by: player, item: found }).expect("Unable to insert want to pickup");
+
+        ItemCollectionSystem{}.run_now(&ecs);
+        ecs.maintain();
+
+        assert_eq!(amount(&ecs, stack), 5);
+        assert!(!ecs.is_alive(found));
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].text(), "You have picked up the Health Potion (x3), now carrying 5.");
+    }
+
+    #[test]
+    fn stowing_merges_into_a_carried_stack() {
+        let (mut ecs, player) = world();
+        let stack = carried_potions(&mut ecs, player, 2);
+        let found = spawner::stackable_item(&mut ecs, ItemTemplate::HealthPotion)
+            .with(Position{ x: 1, y: 1 }).with(Quantity{ amount: 2 }).build();
+        assert!(stow(&mut ecs, found, player) == stack);
+        ecs.maintain();
+        assert_eq!(amount(&ecs, stack), 4);
+        assert!(!ecs.is_alive(found));
+    }
+
+    #[test]
+    fn stowing_starts_a_new_stack_for_someone_else() {
+        let (mut ecs, player) = world();
+        carried_potions(&mut ecs, player, 2);
+        let vendor = ecs.create_entity().build();
+        let found = spawner::stackable_item(&mut ecs, ItemTemplate::HealthPotion)
+            .with(Position{ x: 1, y: 1 }).with(Quantity{ amount: 1 }).build();
+        assert!(stow(&mut ecs, found, vendor) == found);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(found).unwrap().owner, vendor);
+        assert!(ecs.read_storage::<Position>().get(found).is_none());
+    }
+
+    #[test]
+    fn worn_cursed_gear_cannot_be_dropped() {
+        let (mut ecs, player) = world();
+        ecs.register::<WantsToDropItem>();
+        ecs.register::<Equipped>();
+        ecs.register::<Cursed>();
+        ecs.register::<CurseUnknown>();
+        let mail = ecs.create_entity()
+            .with(Name{ name: "Chain Mail".to_string() })
+            .with(Equipped{ owner: player, slot: EquipmentSlot::Armour })
+            .with(Cursed{ hit_penalty: 0, defense_penalty: 1, might_penalty: 0 })
+            .with(CurseUnknown{})
+            .build();
+        ecs.write_storage::<Position>().insert(player, Position{ x: 3, y: 3 }).expect("Unable to insert");
+        ecs.write_storage::<WantsToDropItem>().insert(player, WantsToDropItem{ item: mail }).expect("Unable to insert");
+
+        ItemDropSystem{}.run_now(&ecs);
+        assert!(ecs.read_storage::<Position>().get(mail).is_none());
+        assert!(ecs.read_storage::<Equipped>().get(mail).is_some());
+        assert!(ecs.read_storage::<CurseUnknown>().get(mail).is_none());
+        assert_eq!(ecs.fetch::<GameLog>().entries.last().unwrap().text(), "You cannot drop the Chain Mail, it is cursed!");
+    }
+}
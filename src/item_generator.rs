@@ -0,0 +1,312 @@
+use specs::prelude::*;
+use rltk::{RGB, RandomNumberGenerator, DiceType};
+use crate::{Renderable, Name, Description, Item, Equippable, EquipmentSlot, MeleeWeapon, DefenseBonus, AttributeBonus,
+            DamageType, Weight, Value, Rarity, ExtraDamage, Cursed, CurseUnknown};
+use crate::spawner::RenderOrder;
+
+enum BaseKind {
+    Weapon { hit_bonus : i32, n_dice : i32, die_type : i32, damage_type : DamageType },
+    Armour { defense : i32 }
+}
+
+// the plain item an affixed item is built on
+struct BaseItem {
+    name : &'static str,
+    description : &'static str,
+    glyph : char,
+    colour : (u8, u8, u8),
+    weight : f32,
+    value : i32,
+    min_depth : i32,
+    kind : BaseKind
+}
+
+const BASE_ITEMS : [BaseItem; 6] = [
+    BaseItem{ name: "Dagger", description: "A short, wicked blade, quick in the hand.", glyph: '/', colour: (0, 255, 255), weight: 1.0, value: 10,
+        min_depth: 1, kind: BaseKind::Weapon{ hit_bonus: 2, n_dice: 1, die_type: 4, damage_type: DamageType::Piercing } },
+    BaseItem{ name: "Longsword", description: "A long, straight blade. Heavy, but it bites deep.", glyph: '/', colour: (0, 255, 255), weight: 4.0, value: 30,
+        min_depth: 1, kind: BaseKind::Weapon{ hit_bonus: 0, n_dice: 1, die_type: 8, damage_type: DamageType::Slashing } },
+    BaseItem{ name: "Mace", description: "A flanged iron head on a stout haft, for denting armour and the heads inside it.", glyph: '/', colour: (0, 255, 255), weight: 5.0, value: 25,
+        min_depth: 2, kind: BaseKind::Weapon{ hit_bonus: 0, n_dice: 2, die_type: 4, damage_type: DamageType::Bludgeoning } },
+    BaseItem{ name: "Greataxe", description: "A huge crescent of steel that takes both hands and all of your strength.", glyph: '/', colour: (0, 255, 255), weight: 7.0, value: 60,
+        min_depth: 4, kind: BaseKind::Weapon{ hit_bonus: -1, n_dice: 1, die_type: 12, damage_type: DamageType::Slashing } },
+    BaseItem{ name: "Leather Armour", description: "A jerkin of boiled leather that turns aside the worst of a blow.", glyph: '[', colour: (255, 64, 64), weight: 10.0, value: 20,
+        min_depth: 1, kind: BaseKind::Armour{ defense: 2 } },
+    BaseItem{ name: "Chain Mail", description: "Thousands of riveted iron rings. It clinks with every step.", glyph: '[', colour: (255, 64, 64), weight: 20.0, value: 75,
+        min_depth: 3, kind: BaseKind::Armour{ defense: 4 } }
+];
+
+#[derive(Copy, Clone)]
+enum AffixEffect {
+    HitBonus(i32),
+    DamageBonus(i32),
+    Defense(i32),
+    Might(i32),
+    Fitness(i32),
+    Quickness(i32),
+    // turns the weapon's own damage into another type
+    DamageType(DamageType),
+    // a second helping of damage of another type every time the weapon hits
+    ExtraDamage(i32, DamageType)
+}
+
+// a prefix or suffix; the numbers in its effect grow with the depth it is found at
+struct Affix {
+    name : &'static str,
+    min_depth : i32,
+    weapons : bool,
+    armour : bool,
+    effect : AffixEffect
+}
+
+const PREFIXES : [Affix; 7] = [
+    Affix{ name: "Keen", min_depth: 1, weapons: true, armour: false, effect: AffixEffect::HitBonus(1) },
+    Affix{ name: "Vicious", min_depth: 1, weapons: true, armour: false, effect: AffixEffect::DamageBonus(1) },
+    Affix{ name: "Sturdy", min_depth: 1, weapons: false, armour: true, effect: AffixEffect::Defense(1) },
+    Affix{ name: "Flaming", min_depth: 2, weapons: true, armour: false, effect: AffixEffect::DamageType(DamageType::Fire) },
+    Affix{ name: "Frozen", min_depth: 2, weapons: true, armour: false, effect: AffixEffect::DamageType(DamageType::Cold) },
+    Affix{ name: "Venomous", min_depth: 3, weapons: true, armour: false, effect: AffixEffect::DamageType(DamageType::Poison) },
+    Affix{ name: "Reinforced", min_depth: 3, weapons: false, armour: true, effect: AffixEffect::Defense(2) }
+];
+
+const SUFFIXES : [Affix; 6] = [
+    Affix{ name: "of the Bear", min_depth: 1, weapons: true, armour: true, effect: AffixEffect::Might(1) },
+    Affix{ name: "of the Ox", min_depth: 1, weapons: true, armour: true, effect: AffixEffect::Fitness(1) },
+    Affix{ name: "of the Fox", min_depth: 1, weapons: true, armour: true, effect: AffixEffect::Quickness(1) },
+    Affix{ name: "of Protection", min_depth: 2, weapons: true, armour: true, effect: AffixEffect::Defense(1) },
+    Affix{ name: "of Embers", min_depth: 2, weapons: true, armour: false, effect: AffixEffect::ExtraDamage(4, DamageType::Fire) },
+    Affix{ name: "of Frost", min_depth: 3, weapons: true, armour: false, effect: AffixEffect::ExtraDamage(4, DamageType::Cold) }
+];
+
+// deeper levels roll rarer items, and their affixes roll bigger numbers
+fn roll_rarity(rng : &mut RandomNumberGenerator, depth : i32) -> Rarity {
+    let roll = rng.roll_dice(1, 100) + depth * 5;
+    if roll > 95 { Rarity::Rare } else if roll > 70 { Rarity::Magic } else { Rarity::Common }
+}
+
+// the odds, out of 100, that a generated item is cursed
+fn curse_chance(depth : i32) -> i32 {
+    10 + depth * 2
+}
+
+fn affix_tier(depth : i32) -> i32 {
+    1 + depth / 4
+}
+
+// what each affix adds to the base item's value, per tier
+const AFFIX_VALUE : i32 = 25;
+
+fn pick<'a, T>(rng : &mut RandomNumberGenerator, choices : &[&'a T]) -> Option<&'a T> {
+    if choices.is_empty() { return None; }
+    Some(choices[(rng.roll_dice(1, choices.len() as i32) - 1) as usize])
+}
+
+fn pick_affix<'a>(rng : &mut RandomNumberGenerator, affixes : &'a [Affix], depth : i32, is_weapon : bool) -> Option<&'a Affix> {
+    let allowed : Vec<&Affix> = affixes.iter()
+        .filter(|a| a.min_depth <= depth && if is_weapon { a.weapons } else { a.armour })
+        .collect();
+    pick(rng, &allowed)
+}
+
+/// Rolls a random weapon or piece of armour for the given depth; the caller decides where it goes
+pub fn equipment(ecs : &mut World, depth : i32) -> EntityBuilder<'_> {
+    let (base, rarity, prefix, suffix, cursed) = {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let bases : Vec<&BaseItem> = BASE_ITEMS.iter().filter(|b| b.min_depth <= depth).collect();
+        let base = pick(&mut rng, &bases).expect("There should always be a base item for depth 1 and deeper");
+        let is_weapon = matches!(base.kind, BaseKind::Weapon{..});
+
+        let rarity = roll_rarity(&mut rng, depth);
+        let (prefix, suffix) = match rarity {
+            Rarity::Common => (None, None),
+            Rarity::Magic => {
+                // a magic item carries one affix, either side of its name
+                if rng.roll_dice(1, 2) == 1 {
+                    (pick_affix(&mut rng, &PREFIXES, depth, is_weapon), None)
+                } else {
+                    (None, pick_affix(&mut rng, &SUFFIXES, depth, is_weapon))
+                }
+            }
+            Rarity::Rare => (pick_affix(&mut rng, &PREFIXES, depth, is_weapon), pick_affix(&mut rng, &SUFFIXES, depth, is_weapon))
+        };
+        let cursed = rng.roll_dice(1, 100) <= curse_chance(depth);
+        (base, rarity, prefix, suffix, cursed)
+    };
+
+    let mut name = base.name.to_string();
+    if let Some(prefix) = prefix { name = format!("{} {}", prefix.name, name); }
+    if let Some(suffix) = suffix { name = format!("{} {}", name, suffix.name); }
+
+    let mut weapon : Option<MeleeWeapon> = None;
+    let mut defense = 0;
+    let mut slot = EquipmentSlot::Armour;
+    match base.kind {
+        BaseKind::Weapon{ hit_bonus, n_dice, die_type, damage_type } => {
+            weapon = Some(MeleeWeapon{ hit_bonus, damage: DiceType::new(n_dice, die_type, 0), damage_type });
+            slot = EquipmentSlot::Melee;
+        }
+        BaseKind::Armour{ defense : armour } => defense = armour
+    }
+
+    let tier = affix_tier(depth);
+    let mut bonus = AttributeBonus{ might: 0, fitness: 0, quickness: 0, intelligence: 0 };
+    let mut extra_damage : Option<ExtraDamage> = None;
+    let mut properties : Vec<String> = Vec::new();
+    let mut value = base.value;
+    for affix in prefix.iter().chain(suffix.iter()) {
+        value += AFFIX_VALUE * tier;
+        match affix.effect {
+            AffixEffect::HitBonus(n) => {
+                if let Some(w) = weapon.as_mut() { w.hit_bonus += n * tier; }
+                properties.push(format!("{:+} to hit.", n * tier));
+            }
+            AffixEffect::DamageBonus(n) => {
+                if let Some(w) = weapon.as_mut() { w.damage.bonus += n * tier; }
+                properties.push(format!("{:+} damage.", n * tier));
+            }
+            AffixEffect::Defense(n) => {
+                defense += n * tier;
+                properties.push(format!("{:+} defense.", n * tier));
+            }
+            AffixEffect::Might(n) => {
+                bonus.might += n * tier;
+                properties.push(format!("{:+} might.", n * tier));
+            }
+            AffixEffect::Fitness(n) => {
+                bonus.fitness += n * tier;
+                properties.push(format!("{:+} fitness.", n * tier));
+            }
+            AffixEffect::Quickness(n) => {
+                bonus.quickness += n * tier;
+                properties.push(format!("{:+} quickness.", n * tier));
+            }
+            AffixEffect::DamageType(damage_type) => {
+                if let Some(w) = weapon.as_mut() { w.damage_type = damage_type; }
+                properties.push(format!("Strikes with {}.", damage_type.noun()));
+            }
+            AffixEffect::ExtraDamage(die_type, damage_type) => {
+                extra_damage = Some(ExtraDamage{ damage: DiceType::new(tier, die_type, 0), damage_type });
+                properties.push(format!("Adds {}d{} {} on a hit.", tier, die_type, damage_type.noun()));
+            }
+        }
+    }
+
+    // a curse drags the item's numbers down; it is left out of the description so the item gives nothing away
+    let curse = if cursed {
+        let penalty = match weapon {
+            Some(_) => Cursed{ hit_penalty: tier + 1, defense_penalty: 0, might_penalty: tier },
+            None => Cursed{ hit_penalty: 0, defense_penalty: tier, might_penalty: tier }
+        };
+        if let Some(w) = weapon.as_mut() { w.hit_bonus -= penalty.hit_penalty; }
+        defense -= penalty.defense_penalty;
+        bonus.might -= penalty.might_penalty;
+        Some(penalty)
+    } else {
+        None
+    };
+
+    let colour = match rarity {
+        Rarity::Common => RGB::from_u8(base.colour.0, base.colour.1, base.colour.2),
+        _ => rarity.colour()
+    };
+    let description = if properties.is_empty() { base.description.to_string() } else { format!("{} {}", base.description, properties.join(" ")) };
+
+    let mut builder = ecs.create_entity()
+        .with(Renderable{
+            glyph: rltk::to_cp437(base.glyph),
+            fg: colour,
+            bg: RGB::named(rltk::BLACK),
+            render_order: RenderOrder::Item as i32
+        })
+        .with(Name{ name })
+        .with(Description{ text: description })
+        .with(Item{})
+        .with(Equippable{ slot })
+        .with(Weight{ weight: base.weight })
+        .with(Value{ gold: value })
+        .with(CurseUnknown{})
+        .with(rarity);
+    if let Some(weapon) = weapon { builder = builder.with(weapon); }
+    if defense != 0 { builder = builder.with(DefenseBonus{ defense }); }
+    if bonus.might != 0 || bonus.fitness != 0 || bonus.quickness != 0 { builder = builder.with(bonus); }
+    if let Some(extra) = extra_damage { builder = builder.with(extra); }
+    if let Some(curse) = curse { builder = builder.with(curse); }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rolls a heap of equipment at the given depth and returns everything it made
+    fn generate(depth : i32, count : i32) -> World {
+        let mut ecs = World::new();
+        ecs.register::<Renderable>();
+        ecs.register::<Name>();
+        ecs.register::<Description>();
+        ecs.register::<Item>();
+        ecs.register::<Equippable>();
+        ecs.register::<MeleeWeapon>();
+        ecs.register::<DefenseBonus>();
+        ecs.register::<AttributeBonus>();
+        ecs.register::<Weight>();
+        ecs.register::<Value>();
+        ecs.register::<Rarity>();
+        ecs.register::<ExtraDamage>();
+        ecs.register::<Cursed>();
+        ecs.register::<CurseUnknown>();
+        ecs.insert(RandomNumberGenerator::seeded(5));
+        for _ in 0..count {
+            equipment(&mut ecs, depth).build();
+        }
+        ecs
+    }
+
+    #[test]
+    fn affixes_grow_every_four_levels() {
+        assert_eq!(affix_tier(1), 1);
+        assert_eq!(affix_tier(3), 1);
+        assert_eq!(affix_tier(4), 2);
+        assert_eq!(affix_tier(8), 3);
+    }
+
+    #[test]
+    fn deep_levels_only_roll_rare_items() {
+        let mut rng = RandomNumberGenerator::seeded(1);
+        for _ in 0..100 {
+            assert!(roll_rarity(&mut rng, 20) == Rarity::Rare);
+        }
+    }
+
+    #[test]
+    fn affixes_respect_depth_and_item_kind() {
+        let mut rng = RandomNumberGenerator::seeded(2);
+        for _ in 0..100 {
+            let prefix = pick_affix(&mut rng, &PREFIXES, 1, false).expect("Sturdy suits armour at depth 1");
+            assert!(prefix.min_depth <= 1 && prefix.armour);
+            let suffix = pick_affix(&mut rng, &SUFFIXES, 2, true).expect("there are weapon suffixes at depth 2");
+            assert!(suffix.min_depth <= 2 && suffix.weapons);
+        }
+    }
+
+    #[test]
+    fn affix_bonuses_scale_with_depth() {
+        let ecs = generate(8, 300);
+        let names = ecs.read_storage::<Name>();
+        let weapons = ecs.read_storage::<MeleeWeapon>();
+        let cursed = ecs.read_storage::<Cursed>();
+        let mut checked = 0;
+        for (name, weapon, cursed) in (&names, &weapons, cursed.maybe()).join() {
+            if name.name.starts_with("Keen Dagger") {
+                let curse = if cursed.is_some() { affix_tier(8) + 1 } else { 0 };
+                assert_eq!(weapon.hit_bonus, 2 + affix_tier(8) - curse);
+                checked += 1;
+            }
+            if name.name.starts_with("Vicious ") {
+                assert_eq!(weapon.damage.bonus, affix_tier(8));
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "the seed should roll some keen daggers or vicious weapons");
+    }
+}
@@ -0,0 +1,405 @@
+use rltk::VirtualKeyCode;
+use std::collections::HashMap;
+
+// read from the working directory at startup; the built in numpad preset is used when it is missing
+pub const KEYBINDINGS_FILE : &str = "keybindings.cfg";
+
+// keys the menus and cursors always use, so they cannot be bound to commands
+const RESERVED_KEYS : [VirtualKeyCode; 3] = [VirtualKeyCode::Escape, VirtualKeyCode::Return, VirtualKeyCode::Tab];
+
+// keys that may appear in the bindings file, named as they are written there
+const BINDABLE_KEYS : &[VirtualKeyCode] = &[
+    VirtualKeyCode::A, VirtualKeyCode::B, VirtualKeyCode::C, VirtualKeyCode::D, VirtualKeyCode::E, VirtualKeyCode::F,
+    VirtualKeyCode::G, VirtualKeyCode::H, VirtualKeyCode::I, VirtualKeyCode::J, VirtualKeyCode::K, VirtualKeyCode::L,
+    VirtualKeyCode::M, VirtualKeyCode::N, VirtualKeyCode::O, VirtualKeyCode::P, VirtualKeyCode::Q, VirtualKeyCode::R,
+    VirtualKeyCode::S, VirtualKeyCode::T, VirtualKeyCode::U, VirtualKeyCode::V, VirtualKeyCode::W, VirtualKeyCode::X,
+    VirtualKeyCode::Y, VirtualKeyCode::Z,
+    VirtualKeyCode::Key0, VirtualKeyCode::Key1, VirtualKeyCode::Key2, VirtualKeyCode::Key3, VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5, VirtualKeyCode::Key6, VirtualKeyCode::Key7, VirtualKeyCode::Key8, VirtualKeyCode::Key9,
+    VirtualKeyCode::Numpad0, VirtualKeyCode::Numpad1, VirtualKeyCode::Numpad2, VirtualKeyCode::Numpad3, VirtualKeyCode::Numpad4,
+    VirtualKeyCode::Numpad5, VirtualKeyCode::Numpad6, VirtualKeyCode::Numpad7, VirtualKeyCode::Numpad8, VirtualKeyCode::Numpad9,
+    VirtualKeyCode::F1, VirtualKeyCode::F2, VirtualKeyCode::F3, VirtualKeyCode::F4, VirtualKeyCode::F5, VirtualKeyCode::F6,
+    VirtualKeyCode::F7, VirtualKeyCode::F8, VirtualKeyCode::F9, VirtualKeyCode::F10, VirtualKeyCode::F11, VirtualKeyCode::F12,
+    VirtualKeyCode::Up, VirtualKeyCode::Down, VirtualKeyCode::Left, VirtualKeyCode::Right,
+    VirtualKeyCode::Home, VirtualKeyCode::End, VirtualKeyCode::PageUp, VirtualKeyCode::PageDown,
+    VirtualKeyCode::Insert, VirtualKeyCode::Delete, VirtualKeyCode::Back, VirtualKeyCode::Space,
+    VirtualKeyCode::Period, VirtualKeyCode::Comma, VirtualKeyCode::Slash, VirtualKeyCode::Semicolon,
+    VirtualKeyCode::Apostrophe, VirtualKeyCode::Minus, VirtualKeyCode::Equals, VirtualKeyCode::LBracket,
+    VirtualKeyCode::RBracket, VirtualKeyCode::Backslash, VirtualKeyCode::Grave
+];
+
+// everything the player can ask for from the map screen, independent of the key that asks for it
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum Command {
+    MoveNorth, MoveSouth, MoveEast, MoveWest,
+    MoveNorthEast, MoveNorthWest, MoveSouthEast, MoveSouthWest,
+    Wait, Rest, AutoExplore, ToggleAutoPickUp, Travel, TravelToStairs, Look, Descend, Ascend,
+    PickUp, Inventory, Drop, RemoveEquipment, Cast,
+    CharacterSheet, MessageHistory, Help
+}
+
+// every command, in the order the help screen lists them
+pub const COMMANDS : [Command; 25] = [
+    Command::MoveNorth, Command::MoveSouth, Command::MoveEast, Command::MoveWest,
+    Command::MoveNorthEast, Command::MoveNorthWest, Command::MoveSouthEast, Command::MoveSouthWest,
+    Command::Wait, Command::Rest, Command::AutoExplore, Command::ToggleAutoPickUp, Command::Travel, Command::TravelToStairs, Command::Look, Command::Descend, Command::Ascend,
+    Command::PickUp, Command::Inventory, Command::Drop, Command::RemoveEquipment, Command::Cast,
+    Command::CharacterSheet, Command::MessageHistory, Command::Help
+];
+
+impl Command {
+    // the name used for the command in the bindings file
+    pub fn config_name(self) -> &'static str {
+        match self {
+            Command::MoveNorth => "move_north",
+            Command::MoveSouth => "move_south",
+            Command::MoveEast => "move_east",
+            Command::MoveWest => "move_west",
+            Command::MoveNorthEast => "move_north_east",
+            Command::MoveNorthWest => "move_north_west",
+            Command::MoveSouthEast => "move_south_east",
+            Command::MoveSouthWest => "move_south_west",
+            Command::Wait => "wait",
+            Command::Rest => "rest",
+            Command::AutoExplore => "auto_explore",
+            Command::ToggleAutoPickUp => "toggle_auto_pick_up",
+            Command::Travel => "travel",
+            Command::TravelToStairs => "travel_to_stairs",
+            Command::Look => "look",
+            Command::Descend => "descend",
+            Command::Ascend => "ascend",
+            Command::PickUp => "pick_up",
+            Command::Inventory => "inventory",
+            Command::Drop => "drop",
+            Command::RemoveEquipment => "remove_equipment",
+            Command::Cast => "cast",
+            Command::CharacterSheet => "character_sheet",
+            Command::MessageHistory => "message_history",
+            Command::Help => "help"
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Command::MoveNorth => "Move or attack north",
+            Command::MoveSouth => "Move or attack south",
+            Command::MoveEast => "Move or attack east",
+            Command::MoveWest => "Move or attack west",
+            Command::MoveNorthEast => "Move or attack north east",
+            Command::MoveNorthWest => "Move or attack north west",
+            Command::MoveSouthEast => "Move or attack south east",
+            Command::MoveSouthWest => "Move or attack south west",
+            Command::Wait => "Wait a turn",
+            Command::Rest => "Rest until healed",
+            Command::AutoExplore => "Explore automatically",
+            Command::ToggleAutoPickUp => "Toggle auto pick-up when exploring",
+            Command::Travel => "Travel to a chosen spot",
+            Command::TravelToStairs => "Travel to the nearest stairs",
+            Command::Look => "Look around",
+            Command::Descend => "Go down, or travel to the way down",
+            Command::Ascend => "Go up, or travel to the way up",
+            Command::PickUp => "Pick up an item",
+            Command::Inventory => "Use an item",
+            Command::Drop => "Drop an item",
+            Command::RemoveEquipment => "Take off equipment",
+            Command::Cast => "Cast a spell",
+            Command::CharacterSheet => "Character sheet",
+            Command::MessageHistory => "Message history",
+            Command::Help => "This help screen"
+        }
+    }
+
+    fn from_config_name(name : &str) -> Option<Command> {
+        COMMANDS.iter().copied().find(|c| c.config_name() == name)
+    }
+
+    // the step a movement command takes, also used to steer on-screen cursors
+    pub fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Command::MoveNorth => Some((0, -1)),
+            Command::MoveSouth => Some((0, 1)),
+            Command::MoveEast => Some((1, 0)),
+            Command::MoveWest => Some((-1, 0)),
+            Command::MoveNorthEast => Some((1, -1)),
+            Command::MoveNorthWest => Some((-1, -1)),
+            Command::MoveSouthEast => Some((1, 1)),
+            Command::MoveSouthWest => Some((-1, 1)),
+            _ => None
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum Preset { Numpad, ViKeys, Wasd }
+
+impl Preset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Numpad => "numpad",
+            Preset::ViKeys => "vi",
+            Preset::Wasd => "wasd"
+        }
+    }
+
+    fn from_name(name : &str) -> Option<Preset> {
+        [Preset::Numpad, Preset::ViKeys, Preset::Wasd].iter().copied().find(|p| p.name() == name)
+    }
+
+    fn bindings(self) -> HashMap<Command, Vec<VirtualKeyCode>> {
+        use VirtualKeyCode::*;
+
+        let mut keys = HashMap::new();
+        // Period and Comma are the '>' and '<' keys, so they take the stairs and Space waits
+        keys.insert(Command::Wait, vec![Space]);
+        keys.insert(Command::Descend, vec![Period]);
+        keys.insert(Command::Ascend, vec![Comma]);
+        keys.insert(Command::Rest, vec![Z]);
+        keys.insert(Command::AutoExplore, vec![O]);
+        keys.insert(Command::ToggleAutoPickUp, vec![F2]);
+        keys.insert(Command::Travel, vec![T]);
+        keys.insert(Command::TravelToStairs, vec![Backslash]);
+        keys.insert(Command::Look, vec![X]);
+        keys.insert(Command::PickUp, vec![G]);
+        keys.insert(Command::Inventory, vec![I]);
+        keys.insert(Command::Drop, vec![D]);
+        keys.insert(Command::RemoveEquipment, vec![R]);
+        keys.insert(Command::Cast, vec![F]);
+        keys.insert(Command::CharacterSheet, vec![C]);
+        keys.insert(Command::MessageHistory, vec![M]);
+        keys.insert(Command::Help, vec![F1, Slash]);
+
+        // the arrow keys move in every preset
+        match self {
+            Preset::Numpad => {
+                keys.insert(Command::MoveNorth, vec![Up, Numpad8]);
+                keys.insert(Command::MoveSouth, vec![Down, Numpad2]);
+                keys.insert(Command::MoveEast, vec![Right, Numpad6]);
+                keys.insert(Command::MoveWest, vec![Left, Numpad4]);
+                keys.insert(Command::MoveNorthEast, vec![Numpad9]);
+                keys.insert(Command::MoveNorthWest, vec![Numpad7]);
+                keys.insert(Command::MoveSouthEast, vec![Numpad3]);
+                keys.insert(Command::MoveSouthWest, vec![Numpad1]);
+                keys.insert(Command::Wait, vec![Numpad5, Space]);
+            }
+            Preset::ViKeys => {
+                keys.insert(Command::MoveNorth, vec![Up, K]);
+                keys.insert(Command::MoveSouth, vec![Down, J]);
+                keys.insert(Command::MoveEast, vec![Right, L]);
+                keys.insert(Command::MoveWest, vec![Left, H]);
+                keys.insert(Command::MoveNorthEast, vec![U]);
+                keys.insert(Command::MoveNorthWest, vec![Y]);
+                keys.insert(Command::MoveSouthEast, vec![N]);
+                keys.insert(Command::MoveSouthWest, vec![B]);
+            }
+            Preset::Wasd => {
+                keys.insert(Command::MoveNorth, vec![Up, W]);
+                keys.insert(Command::MoveSouth, vec![Down, S]);
+                keys.insert(Command::MoveEast, vec![Right, D]);
+                keys.insert(Command::MoveWest, vec![Left, A]);
+                keys.insert(Command::MoveNorthEast, vec![E]);
+                keys.insert(Command::MoveNorthWest, vec![Q]);
+                keys.insert(Command::MoveSouthEast, vec![C]);
+                keys.insert(Command::MoveSouthWest, vec![Z]);
+                // the diagonals take over Z, C and D, so those commands move elsewhere
+                keys.insert(Command::Rest, vec![R]);
+                keys.insert(Command::RemoveEquipment, vec![U]);
+                keys.insert(Command::Drop, vec![V]);
+                keys.insert(Command::CharacterSheet, vec![P]);
+            }
+        }
+        keys
+    }
+}
+
+pub fn key_name(key : VirtualKeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn parse_key(name : &str) -> Option<VirtualKeyCode> {
+    BINDABLE_KEYS.iter().copied().find(|k| key_name(*k).eq_ignore_ascii_case(name))
+}
+
+pub struct KeyBindings {
+    pub preset : Preset,
+    keys : HashMap<Command, Vec<VirtualKeyCode>>,
+    commands : HashMap<VirtualKeyCode, Command>
+}
+
+impl KeyBindings {
+    /// A preset's bindings, or the keys it binds to two commands at once
+    pub fn new(preset : Preset) -> Result<KeyBindings, Vec<String>> {
+        KeyBindings::from_keys(preset, preset.bindings())
+    }
+
+    /// A preset's bindings with any doubly bound key left on the first command that claims it,
+    /// for when nothing better can be loaded
+    pub fn fallback(preset : Preset) -> KeyBindings {
+        KeyBindings::build(preset, preset.bindings()).0
+    }
+
+    // builds the key lookup, refusing any key that would trigger two commands
+    fn from_keys(preset : Preset, keys : HashMap<Command, Vec<VirtualKeyCode>>) -> Result<KeyBindings, Vec<String>> {
+        let (bindings, errors) = KeyBindings::build(preset, keys);
+        if errors.is_empty() { Ok(bindings) } else { Err(errors) }
+    }
+
+    // builds the key lookup along with a complaint for every key bound to two commands
+    fn build(preset : Preset, keys : HashMap<Command, Vec<VirtualKeyCode>>) -> (KeyBindings, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut commands : HashMap<VirtualKeyCode, Command> = HashMap::new();
+        for command in COMMANDS.iter() {
+            for key in keys.get(command).map_or(&[][..], |k| &k[..]) {
+                match commands.get(key) {
+                    Some(other) if other != command => errors.push(format!("{} is bound to both {} and {}.",
+                        key_name(*key), other.config_name(), command.config_name())),
+                    _ => { commands.insert(*key, *command); }
+                }
+            }
+        }
+
+        (KeyBindings{ preset, keys, commands }, errors)
+    }
+
+    /// Parses a bindings file: a `preset = numpad|vi|wasd` line and any number of
+    /// `command = Key, Key` lines that replace the preset's keys for that command.
+    /// Lines starting with `#` are comments.
+    pub fn parse(text : &str) -> Result<KeyBindings, Vec<String>> {
+        let mut errors = Vec::new();
+        let mut preset = Preset::Numpad;
+        let mut overrides : Vec<(Command, Vec<VirtualKeyCode>)> = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+            let line_number = i + 1;
+
+            let (setting, value) = match line.find('=') {
+                None => {
+                    errors.push(format!("Line {}: expected 'command = keys' but found '{}'.", line_number, line));
+                    continue;
+                }
+                Some(split) => (line[..split].trim(), line[split + 1..].trim())
+            };
+
+            if setting == "preset" {
+                match Preset::from_name(value) {
+                    Some(p) => preset = p,
+                    None => errors.push(format!("Line {}: unknown preset '{}', expected numpad, vi or wasd.", line_number, value))
+                }
+                continue;
+            }
+
+            let command = match Command::from_config_name(setting) {
+                Some(command) => command,
+                None => {
+                    errors.push(format!("Line {}: unknown command '{}'.", line_number, setting));
+                    continue;
+                }
+            };
+
+            let mut keys = Vec::new();
+            for name in value.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                match parse_key(name) {
+                    None if RESERVED_KEYS.iter().any(|k| key_name(*k).eq_ignore_ascii_case(name)) =>
+                        errors.push(format!("Line {}: {} is reserved for menus and cannot be bound.", line_number, name)),
+                    None => errors.push(format!("Line {}: unknown key '{}'.", line_number, name)),
+                    Some(key) => keys.push(key)
+                }
+            }
+            overrides.push((command, keys));
+        }
+
+        // the preset line may come anywhere, so overrides are applied once it is known
+        let mut keys = preset.bindings();
+        for (command, command_keys) in overrides {
+            keys.insert(command, command_keys);
+        }
+
+        match KeyBindings::from_keys(preset, keys) {
+            Ok(bindings) if errors.is_empty() => Ok(bindings),
+            Ok(_) => Err(errors),
+            Err(conflicts) => {
+                errors.extend(conflicts);
+                Err(errors)
+            }
+        }
+    }
+
+    /// Loads the bindings file, falling back to the numpad preset when there is none
+    pub fn load(path : &str) -> Result<KeyBindings, Vec<String>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => KeyBindings::parse(&text),
+            Err(_) => KeyBindings::new(Preset::Numpad)
+        }
+    }
+
+    pub fn command(&self, key : VirtualKeyCode) -> Option<Command> {
+        self.commands.get(&key).copied()
+    }
+
+    pub fn direction(&self, key : VirtualKeyCode) -> Option<(i32, i32)> {
+        self.command(key).and_then(|c| c.direction())
+    }
+
+    // the keys bound to a command, for showing on screen
+    pub fn describe(&self, command : Command) -> String {
+        match self.keys.get(&command) {
+            Some(keys) if !keys.is_empty() => keys.iter().map(|k| key_name(*k)).collect::<Vec<String>>().join(", "),
+            _ => "unbound".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_bind_no_key_twice() {
+        for preset in [Preset::Numpad, Preset::ViKeys, Preset::Wasd].iter() {
+            assert!(KeyBindings::new(*preset).is_ok(), "the {} preset has conflicts", preset.name());
+        }
+    }
+
+    #[test]
+    fn key_names_parse_regardless_of_case() {
+        assert_eq!(parse_key("numpad8"), Some(VirtualKeyCode::Numpad8));
+        assert_eq!(parse_key("Space"), Some(VirtualKeyCode::Space));
+        assert_eq!(parse_key("Escape"), None);
+        assert_eq!(parse_key("NoSuchKey"), None);
+    }
+
+    #[test]
+    fn a_file_picks_a_preset_and_overrides_its_keys() {
+        let bindings = KeyBindings::parse("# my keys\npreset = vi\npick_up = Comma\nascend = Semicolon").expect("bindings should parse");
+        assert!(bindings.preset == Preset::ViKeys);
+        assert_eq!(bindings.command(VirtualKeyCode::K), Some(Command::MoveNorth));
+        assert_eq!(bindings.command(VirtualKeyCode::Comma), Some(Command::PickUp));
+        assert_eq!(bindings.command(VirtualKeyCode::G), None);
+    }
+
+    #[test]
+    fn a_key_bound_to_two_commands_is_reported() {
+        let errors = KeyBindings::parse("pick_up = Space").err().expect("the conflict should be refused");
+        assert_eq!(errors, vec!["Space is bound to both wait and pick_up.".to_string()]);
+    }
+
+    #[test]
+    fn the_fallback_keeps_the_first_command_to_claim_a_key() {
+        let mut keys = Preset::Numpad.bindings();
+        keys.insert(Command::PickUp, vec![VirtualKeyCode::Space]);
+        let (bindings, errors) = KeyBindings::build(Preset::Numpad, keys);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(bindings.command(VirtualKeyCode::Space), Some(Command::Wait));
+    }
+
+    #[test]
+    fn bad_lines_are_reported_by_line_number() {
+        let errors = KeyBindings::parse("preset = qwerty\nfly = F\nwait = Return\nlook").err().expect("the file should be refused");
+        assert_eq!(errors.len(), 4);
+        assert!(errors[0].starts_with("Line 1:"));
+        assert!(errors[1].starts_with("Line 2:"));
+        assert!(errors[2].contains("reserved"));
+        assert!(errors[3].starts_with("Line 4:"));
+    }
+}
@@ -0,0 +1,44 @@
+use specs::prelude::*;
+use rltk::{field_of_view, Point, RGB};
+use crate::{Map, Position, LightSource, Viewshed};
+
+pub struct LightingSystem {}
+
+// works out how much light falls on every tile: the level's own light, plus whatever each light source reaches past the walls
+impl<'a> System<'a> for LightingSystem {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadExpect<'a, Entity>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, LightSource>,
+        WriteStorage<'a, Viewshed>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (mut map, player_entity, positions, light_sources, mut viewsheds) = data;
+
+        map.light = map.ambient_light.clone();
+
+        for (pos, source) in (&positions, &light_sources).join() {
+            let origin = Point::new(pos.x, pos.y);
+            let mut lit_tiles = field_of_view(origin, source.range, &*map);
+            lit_tiles.retain(|t| t.x >= 0 && t.x < map.width && t.y >= 0 && t.y < map.height);
+            for tile in lit_tiles.iter() {
+                let distance = rltk::DistanceAlg::Pythagoras.distance2d(origin, *tile);
+                let intensity = f32::max(0.0, 1.0 - distance / (source.range as f32 + 1.0));
+                let idx = map.xy_idx(tile.x, tile.y);
+                let old = map.light[idx];
+                map.light[idx] = RGB::from_f32(
+                    f32::min(1.0, old.r + source.colour.r * intensity),
+                    f32::min(1.0, old.g + source.colour.g * intensity),
+                    f32::min(1.0, old.b + source.colour.b * intensity)
+                );
+            }
+        }
+
+        // lights move about, so what the player can make out has to be worked out again
+        if let Some(viewshed) = viewsheds.get_mut(*player_entity) {
+            viewshed.dirty = true;
+        }
+    }
+}
@@ -1,12 +1,10 @@
-use rltk::{Rltk, GameState, RGB, VirtualKeyCode, Point, console, RandomNumberGenerator};
+use rltk::{Rltk, GameState, RGB, Point, console, RandomNumberGenerator};
 use specs::prelude::*;
-use std::cmp::{max,min};
 
 mod map;
 pub use map::*;
 mod rect;
 pub use rect::Rect;
-pub use rect::*;
 mod components;
 pub use components::*;
 mod player;
@@ -19,19 +17,78 @@ mod gui;
 mod game_log;
 mod spawner;
 mod inventory_system;
+mod stats_system;
+mod run_stats;
+mod regen_system;
+mod auto_explore;
+mod travel;
+mod keybindings;
+mod identification;
+mod item_generator;
+mod shop;
+mod town;
+mod bystander_ai_system;
+mod dungeon;
+mod status_system;
+mod particle_system;
+mod effect_system;
+mod spell_system;
+mod teleport_system;
+mod trigger_system;
+mod lighting_system;
+mod sense_system;
 
 pub use player::*;
 
 use visibility_system::VisibilitySystem;
 use crate::monster_ai_system::MonsterAI;
+use crate::bystander_ai_system::BystanderAI;
 use crate::map_indexing_system::MapIndexingSystem;
 use crate::melee_combat_system::MeleeCombatSystem;
 use crate::damage_system::DamageSystem;
 use crate::game_log::GameLog;
-use crate::inventory_system::{ItemCollectionSystem, PotionUseSystem, ItemDropSystem};
+use crate::inventory_system::{ItemCollectionSystem, ItemUseSystem, ItemDropSystem, ItemRemoveSystem};
+use crate::stats_system::StatsSystem;
+use crate::run_stats::RunStats;
+use crate::regen_system::RegenerationSystem;
+use crate::status_system::StatusEffectSystem;
+use crate::effect_system::ItemEffectSystem;
+use crate::spell_system::SpellCastSystem;
+use crate::teleport_system::TeleportSystem;
+use crate::trigger_system::TriggerSystem;
+use crate::lighting_system::LightingSystem;
+use crate::sense_system::SenseEffectSystem;
+use crate::particle_system::{ParticleSpawnSystem, ParticleBuilder};
+use crate::auto_explore::AutoExploreOptions;
+use crate::keybindings::{KeyBindings, Command, Preset, KEYBINDINGS_FILE};
+use crate::identification::Identification;
+use crate::dungeon::MasterDungeonMap;
 
 #[derive(PartialEq, Copy, Clone)]
-pub enum RunState { AwaitingInput, PreRun, PlayerTurn, MonsterTurn, ShowInventory, ShowDropItems }
+pub enum RunState {
+    AwaitingInput,
+    PreRun,
+    PlayerTurn,
+    MonsterTurn,
+    SlowedMonsterTurn,
+    ShowInventory { page : i32 },
+    ShowDropItems { page : i32 },
+    ShowIdentify { scroll : Entity, page : i32 },
+    ShowTargeting { item : Entity, x : i32, y : i32 },
+    ShowSpells { page : i32 },
+    ShowRemoveItem { page : i32 },
+    ShowTrade { vendor : Entity, buying : bool, page : i32 },
+    ShowCharacterSheet,
+    ShowTravelCursor { x : i32, y : i32 },
+    ShowLook { x : i32, y : i32 },
+    ShowLogHistory { offset : i32 },
+    ShowHelp,
+    MagicMapReveal { row : i32 },
+    LevelTeleport { depth : i32 },
+    NextLevel,
+    PreviousLevel,
+    GameOver
+}
 
 pub struct State {
     pub ecs: World
@@ -39,43 +96,168 @@ pub struct State {
 
 impl State {
     fn run_systems(&mut self) {
+        let mut stats = StatsSystem{};
+        stats.run_now(&self.ecs);
+        let mut lighting = LightingSystem{};
+        lighting.run_now(&self.ecs);
         let mut vis = VisibilitySystem{};
         vis.run_now(&self.ecs);
         let mut mob = MonsterAI{};
         mob.run_now(&self.ecs);
+        let mut bystanders = BystanderAI{};
+        bystanders.run_now(&self.ecs);
         let mut mapidx = MapIndexingSystem{};
         mapidx.run_now(&self.ecs);
+        let mut triggers = TriggerSystem{};
+        triggers.run_now(&self.ecs);
         let mut melee = MeleeCombatSystem{};
         melee.run_now(&self.ecs);
+        let mut casting = SpellCastSystem{};
+        casting.run_now(&self.ecs);
+        let mut effects = ItemEffectSystem{};
+        effects.run_now(&self.ecs);
+        let mut senses = SenseEffectSystem{};
+        senses.run_now(&self.ecs);
+        let mut teleports = TeleportSystem{};
+        teleports.run_now(&self.ecs);
         let mut damage = DamageSystem{};
         damage.run_now(&self.ecs);
+        let mut regen = RegenerationSystem{};
+        regen.run_now(&self.ecs);
+        let mut status = StatusEffectSystem{};
+        status.run_now(&self.ecs);
         let mut pickup = ItemCollectionSystem{};
         pickup.run_now(&self.ecs);
         let mut dropitems = ItemDropSystem{};
         dropitems.run_now(&self.ecs);
-        let mut drinks = PotionUseSystem{};
-        drinks.run_now(&self.ecs);
+        let mut itemuse = ItemUseSystem{};
+        itemuse.run_now(&self.ecs);
+        let mut removeitems = ItemRemoveSystem{};
+        removeitems.run_now(&self.ecs);
+        let mut particles = ParticleSpawnSystem{};
+        particles.run_now(&self.ecs);
         self.ecs.maintain();
     }
+
+    /// Builds and fills the level at `depth` the first time it is visited
+    fn build_level(&mut self, depth : i32) -> Map {
+        // the deeper the level, the likelier its rooms are left dark
+        let (map, lighting) = {
+            let mut rng = self.ecs.write_resource::<RandomNumberGenerator>();
+            let lighting = if depth > 1 && rng.roll_dice(1, 10) <= depth { RoomLighting::Dark } else { RoomLighting::Lit };
+            let map = if depth == 0 { town::new_town(&mut rng) } else { Map::new_map_rooms_and_corridors(&mut rng, depth, lighting) };
+            (map, lighting)
+        };
+
+        if depth == 0 {
+            town::populate_town(&mut self.ecs, &map);
+        } else {
+            // nothing waits in the room the player arrives in
+            for room in map.rooms.iter().skip(1) {
+                spawner::spawn_room(&mut self.ecs, room, depth);
+            }
+            // a dark level has the odd torch burning in its rooms
+            if lighting == RoomLighting::Dark {
+                for room in map.rooms.iter() {
+                    if self.ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, 2) == 1 {
+                        spawner::torch(&mut self.ecs, room.x1, room.y1);
+                    }
+                }
+            }
+            // one pedlar per level, somewhere along the way to the stairs
+            if let Some((vendor_x, vendor_y)) = spawner::free_spot(&self.ecs, &map, &map.rooms[map.rooms.len() / 2]) {
+                spawner::vendor(&mut self.ecs, vendor_x, vendor_y, depth, spawner::VendorKind::Pedlar);
+            }
+        }
+        map
+    }
+
+    fn place_player(&mut self, x : i32, y : i32) {
+        let player_entity = *self.ecs.fetch::<Entity>();
+        if let Some(pos) = self.ecs.write_storage::<Position>().get_mut(player_entity) {
+            pos.x = x;
+            pos.y = y;
+        }
+        self.ecs.insert(Point::new(x, y));
+        if let Some(viewshed) = self.ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+            viewshed.dirty = true;
+        }
+    }
+
+    /// Freezes and stores the current level, then brings back the level at `depth` or builds it if this is the first visit.
+    /// Returns the new level's map, which the caller puts in place once it knows where the player arrives.
+    fn leave_level_for(&mut self, depth : i32) -> (Map, bool) {
+        dungeon::freeze_level_entities(&mut self.ecs);
+        {
+            let map = self.ecs.fetch::<Map>();
+            self.ecs.write_resource::<MasterDungeonMap>().store_map(&map);
+        }
+
+        let stored = self.ecs.fetch::<MasterDungeonMap>().get_map(depth);
+        match stored {
+            Some(map) => {
+                dungeon::thaw_level_entities(&mut self.ecs, depth);
+                (map, false)
+            }
+            None => (self.build_level(depth), true)
+        }
+    }
+
+    /// Drops the player onto a random open tile of the level at `depth`
+    fn teleport_to_level(&mut self, depth : i32) {
+        let (map, _first_visit) = self.leave_level_for(depth);
+        self.ecs.insert(map);
+        // walls and monsters have to be in the blocked list before an open tile can be picked
+        let mut mapidx = MapIndexingSystem{};
+        mapidx.run_now(&self.ecs);
+
+        let arrival = {
+            let map = self.ecs.fetch::<Map>();
+            let mut rng = self.ecs.write_resource::<RandomNumberGenerator>();
+            map.random_open_tile(&mut rng).expect("Every level should have somewhere to stand")
+        };
+        self.place_player(arrival.x, arrival.y);
+        self.ecs.write_resource::<GameLog>().log(format!("You find yourself on level {} of the dungeon.", depth));
+    }
+
+    /// Takes the stairs `offset` levels down (or up, when negative), freezing the level being left
+    /// and arriving on the staircase that leads back the way the player came
+    fn change_level(&mut self, offset : i32) {
+        let depth = self.ecs.fetch::<Map>().depth + offset;
+        let (map, first_visit) = self.leave_level_for(depth);
+        let arrival = map.find_tile(if offset > 0 { TileType::UpStairs } else { TileType::DownStairs })
+            .expect("Every level should have stairs back the way the player came");
+        self.ecs.insert(map);
+        self.place_player(arrival.x, arrival.y);
+
+        let mut gamelog = self.ecs.write_resource::<GameLog>();
+        match depth {
+            0 => gamelog.log("You climb back out into the daylight of the town."),
+            1 if first_visit => gamelog.log("You have entered the dungeon. It's dark, and full of terrors."),
+            _ if offset > 0 => gamelog.log(format!("You descend to level {} of the dungeon.", depth)),
+            _ => gamelog.log(format!("You climb up to level {} of the dungeon.", depth))
+        }
+    }
 }
 
 impl GameState for State {
     fn tick(&mut self, ctx : &mut Rltk) {
         ctx.cls();
+        particle_system::cull_dead_particles(&mut self.ecs, ctx);
 
         draw_map(&self.ecs, ctx);
 
         {
             let positions = self.ecs.read_storage::<Position>();
             let renderables = self.ecs.read_storage::<Renderable>();
-            let map = self.ecs.fetch::<Map>();
+            // detection and telepathy show monsters that are out of sight, and the invisible stay hidden without see-invisible
+            let perceives = visibility_system::player_perceives(&self.ecs);
 
-            let mut data = (&positions, &renderables).join().collect::<Vec<_>>();
-            data.sort_by(|&a, &b| b.1.render_order.cmp(&a.1.render_order));
+            let mut data = (&self.ecs.entities(), &positions, &renderables).join().collect::<Vec<_>>();
+            data.sort_by_key(|d| std::cmp::Reverse(d.2.render_order));
 
-            for (pos, renderable) in data.iter() {
-                let idx = map.xy_idx(pos.x, pos.y);
-                if map.visible_tiles[idx] {
+            for (entity, pos, renderable) in data.iter() {
+                if perceives(*entity, Point::new(pos.x, pos.y)) {
                     ctx.set(pos.x, pos.y, renderable.fg, renderable.bg, renderable.glyph);
                 }
             }
@@ -99,40 +281,221 @@ impl GameState for State {
                 new_run_state = player_input(self, ctx);
             },
             RunState::PlayerTurn => {
+                let turn = {
+                    let mut run_stats = self.ecs.write_resource::<RunStats>();
+                    run_stats.turn += 1;
+                    run_stats.turn
+                };
+                self.ecs.write_resource::<GameLog>().turn = turn;
                 self.run_systems();
                 self.ecs.maintain();
-                new_run_state = RunState::MonsterTurn;
+                {
+                    let mut identification = self.ecs.write_resource::<Identification>();
+                    if let Err(error) = identification.save_if_changed() {
+                        console::log(format!("{}: {}", identification.save_path(), error));
+                    }
+                }
+                // an item may have started something that plays out over a few frames first
+                new_run_state = match *self.ecs.fetch::<RunState>() {
+                    RunState::MagicMapReveal{..} => RunState::MagicMapReveal{ row: 0 },
+                    RunState::LevelTeleport{ depth } => RunState::LevelTeleport{ depth },
+                    _ => RunState::MonsterTurn
+                };
             },
             RunState::MonsterTurn => {
+                // an overburdened player is slow, so the monsters get a second turn before the player's next one
+                let player_overburdened = {
+                    let encumbrance = self.ecs.read_storage::<Encumbrance>();
+                    encumbrance.get(*self.ecs.fetch::<Entity>()).is_some_and(|e| e.overburdened())
+                };
+                self.run_systems();
+                self.ecs.maintain();
+                new_run_state = if player_overburdened { RunState::SlowedMonsterTurn } else { RunState::AwaitingInput };
+            },
+            RunState::SlowedMonsterTurn => {
+                // the extra turn plays out as an ordinary monster turn, after the last one's dead were cleared away
+                *self.ecs.write_resource::<RunState>() = RunState::MonsterTurn;
                 self.run_systems();
                 self.ecs.maintain();
                 new_run_state = RunState::AwaitingInput;
             },
-            RunState::ShowInventory => {
-                let result = gui::show_inventory(self, ctx);
+            RunState::ShowInventory{ page } => {
+                let result = gui::show_inventory(self, ctx, page);
                 match result.0 {
                     gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
                     gui::ItemMenuResult::NoResponse => {},
+                    gui::ItemMenuResult::Page(page) => new_run_state = RunState::ShowInventory{ page },
                     gui::ItemMenuResult::Selected => {
                         let item_entity = result.1.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToDrinkPotion>();
-                        intent.insert(*self.ecs.fetch::<Entity>(), WantsToDrinkPotion{ potion: item_entity}).expect("Unable to insert intent");
+                        // an identify scroll first asks which item to read it on
+                        let identifies = self.ecs.read_storage::<IdentifiesItem>().get(item_entity).is_some();
+                        let is_ranged = self.ecs.read_storage::<Ranged>().get(item_entity).is_some();
+                        if identifies && !gui::unidentified_items(&self.ecs, item_entity).is_empty() {
+                            new_run_state = RunState::ShowIdentify{ scroll: item_entity, page: 0 };
+                        } else if is_ranged {
+                            // a ranged item is aimed first, starting from the player
+                            let player_pos = *self.ecs.fetch::<Point>();
+                            self.ecs.insert(gui::TargetingPreview::default());
+                            new_run_state = RunState::ShowTargeting{ item: item_entity, x: player_pos.x, y: player_pos.y };
+                        } else {
+                            let mut intent = self.ecs.write_storage::<WantsToUseItem>();
+                            intent.insert(*self.ecs.fetch::<Entity>(), WantsToUseItem{ item: item_entity, target_item: None, target: None }).expect("Unable to insert intent");
+                            new_run_state = RunState::PlayerTurn;
+                        }
+                    }
+                }
+            },
+            RunState::ShowIdentify{ scroll, page } => {
+                let result = gui::identify_menu(self, ctx, scroll, page);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {},
+                    gui::ItemMenuResult::Page(page) => new_run_state = RunState::ShowIdentify{ scroll, page },
+                    gui::ItemMenuResult::Selected => {
+                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
+                        intent.insert(*self.ecs.fetch::<Entity>(), WantsToUseItem{ item: scroll, target_item: result.1, target: None }).expect("Unable to insert intent");
                         new_run_state = RunState::PlayerTurn;
                     }
                 }
             },
-            RunState::ShowDropItems => {
-                let result = gui::drop_items_menu(self, ctx);
+            RunState::ShowSpells{ page } => {
+                let result = gui::cast_menu(self, ctx, page);
                 match result.0 {
                     gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
                     gui::ItemMenuResult::NoResponse => {},
+                    gui::ItemMenuResult::Page(page) => new_run_state = RunState::ShowSpells{ page },
                     gui::ItemMenuResult::Selected => {
-                        let item_entity = result.1.unwrap();
+                        let spell = result.1.unwrap();
+                        let player_entity = *self.ecs.fetch::<Entity>();
+                        let is_ranged = self.ecs.read_storage::<Ranged>().get(spell).is_some();
+                        // not having the mana costs nothing but the embarrassment
+                        if !spell_system::can_cast(&self.ecs, player_entity, spell) {
+                            self.ecs.write_resource::<GameLog>().log("You do not have enough mana for that.");
+                            new_run_state = RunState::AwaitingInput;
+                        } else if is_ranged {
+                            let player_pos = *self.ecs.fetch::<Point>();
+                            self.ecs.insert(gui::TargetingPreview::default());
+                            new_run_state = RunState::ShowTargeting{ item: spell, x: player_pos.x, y: player_pos.y };
+                        } else {
+                            let mut intent = self.ecs.write_storage::<WantsToUseItem>();
+                            intent.insert(player_entity, WantsToUseItem{ item: spell, target_item: None, target: None }).expect("Unable to insert intent");
+                            new_run_state = RunState::PlayerTurn;
+                        }
+                    }
+                }
+            },
+            RunState::ShowTargeting{ item, x, y } => {
+                match gui::targeting_cursor(&self.ecs, ctx, item, Point::new(x, y)) {
+                    gui::CursorResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::CursorResult::NoResponse => {},
+                    gui::CursorResult::Moved(cursor) => new_run_state = RunState::ShowTargeting{ item, x: cursor.x, y: cursor.y },
+                    gui::CursorResult::Selected(cursor) => {
+                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
+                        intent.insert(*self.ecs.fetch::<Entity>(), WantsToUseItem{ item, target_item: None, target: Some(cursor) }).expect("Unable to insert intent");
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                }
+            },
+            RunState::ShowDropItems{ page } => {
+                let result = gui::drop_items_menu(self, ctx, page);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {},
+                    gui::ItemMenuResult::Page(page) => new_run_state = RunState::ShowDropItems{ page },
+                    gui::ItemMenuResult::Selected => {
+                        // dropping part of a stack splits the dropped items off into their own entity first
+                        let item_entity = inventory_system::split_stack(&mut self.ecs, result.1.unwrap(), result.2);
                         let mut intent = self.ecs.write_storage::<WantsToDropItem>();
                         intent.insert(*self.ecs.fetch::<Entity>(), WantsToDropItem{ item: item_entity}).expect("Unable to insert intent");
                         new_run_state = RunState::PlayerTurn;
                     }
                 }
+            },
+            RunState::ShowRemoveItem{ page } => {
+                let result = gui::remove_item_menu(self, ctx, page);
+                match result.0 {
+                    gui::ItemMenuResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::ItemMenuResult::NoResponse => {},
+                    gui::ItemMenuResult::Page(page) => new_run_state = RunState::ShowRemoveItem{ page },
+                    gui::ItemMenuResult::Selected => {
+                        let item_entity = result.1.unwrap();
+                        let mut intent = self.ecs.write_storage::<WantsToRemoveItem>();
+                        intent.insert(*self.ecs.fetch::<Entity>(), WantsToRemoveItem{ item: item_entity}).expect("Unable to insert intent");
+                        new_run_state = RunState::PlayerTurn;
+                    }
+                }
+            },
+            RunState::ShowTrade{ vendor, buying, page } => {
+                // trading happens while the world waits, so the screen stays open for the next deal
+                match gui::trade_menu(self, ctx, vendor, buying, page) {
+                    gui::TradeResult::Leave => new_run_state = RunState::AwaitingInput,
+                    gui::TradeResult::NoResponse => {},
+                    gui::TradeResult::SwitchMode => new_run_state = RunState::ShowTrade{ vendor, buying: !buying, page: 0 },
+                    gui::TradeResult::Page(page) => new_run_state = RunState::ShowTrade{ vendor, buying, page },
+                    gui::TradeResult::Selected(item) => {
+                        if buying { shop::buy(&mut self.ecs, vendor, item); } else { shop::sell(&mut self.ecs, vendor, item); }
+                        self.ecs.maintain();
+                    }
+                }
+            },
+            RunState::ShowCharacterSheet => {
+                if gui::show_character_sheet(self, ctx) == gui::ScreenResult::Close {
+                    new_run_state = RunState::AwaitingInput;
+                }
+            },
+            RunState::ShowTravelCursor{ x, y } => {
+                match gui::travel_cursor(&self.ecs, ctx, Point::new(x, y)) {
+                    gui::CursorResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::CursorResult::NoResponse => {},
+                    gui::CursorResult::Moved(cursor) => new_run_state = RunState::ShowTravelCursor{ x: cursor.x, y: cursor.y },
+                    gui::CursorResult::Selected(cursor) => new_run_state = travel::start_travel(&mut self.ecs, cursor)
+                }
+            },
+            RunState::ShowLook{ x, y } => {
+                match gui::look_cursor(&self.ecs, ctx, Point::new(x, y)) {
+                    gui::CursorResult::Cancel => new_run_state = RunState::AwaitingInput,
+                    gui::CursorResult::NoResponse | gui::CursorResult::Selected(_) => {},
+                    gui::CursorResult::Moved(cursor) => {
+                        gui::announce_look(&self.ecs, cursor);
+                        new_run_state = RunState::ShowLook{ x: cursor.x, y: cursor.y };
+                    }
+                }
+            },
+            RunState::ShowLogHistory{ offset } => {
+                match gui::show_log_history(&self.ecs, ctx, offset) {
+                    gui::LogHistoryResult::Close => new_run_state = RunState::AwaitingInput,
+                    gui::LogHistoryResult::NoResponse => {},
+                    gui::LogHistoryResult::Scrolled(offset) => new_run_state = RunState::ShowLogHistory{ offset }
+                }
+            },
+            RunState::ShowHelp => {
+                if gui::show_help(&self.ecs, ctx) == gui::ScreenResult::Close {
+                    new_run_state = RunState::AwaitingInput;
+                }
+            },
+            RunState::MagicMapReveal{ row } => {
+                let mut map = self.ecs.fetch_mut::<Map>();
+                map.reveal_row(row);
+                new_run_state = if row >= map.height - 1 { RunState::MonsterTurn } else { RunState::MagicMapReveal{ row: row + 1 } };
+            },
+            RunState::LevelTeleport{ depth } => {
+                self.teleport_to_level(depth);
+                new_run_state = RunState::PreRun;
+            },
+            RunState::NextLevel => {
+                self.change_level(1);
+                new_run_state = RunState::PreRun;
+            },
+            RunState::PreviousLevel => {
+                self.change_level(-1);
+                new_run_state = RunState::PreRun;
+            },
+            RunState::GameOver => {
+                if gui::game_over(&self.ecs, ctx) == gui::ScreenResult::Close {
+                    // a finished run's knowledge goes with it
+                    let _ = std::fs::remove_file(self.ecs.fetch::<Identification>().save_path());
+                    ctx.quit();
+                }
             }
         }
 
@@ -158,6 +521,8 @@ fn main() -> rltk::BError {
 
     // components registration
     gs.ecs.register::<Position>();
+    gs.ecs.register::<OtherLevelPosition>();
+    gs.ecs.register::<ParticleLifetime>();
     gs.ecs.register::<Renderable>();
     gs.ecs.register::<Player>();
     gs.ecs.register::<Viewshed>();
@@ -168,33 +533,125 @@ fn main() -> rltk::BError {
     gs.ecs.register::<WantsToMelee>();
     gs.ecs.register::<SufferDamage>();
     gs.ecs.register::<Item>();
-    gs.ecs.register::<Potion>();
+    gs.ecs.register::<Weight>();
+    gs.ecs.register::<Value>();
+    gs.ecs.register::<Purse>();
+    gs.ecs.register::<Gold>();
+    gs.ecs.register::<Vendor>();
+    gs.ecs.register::<Bystander>();
+    gs.ecs.register::<Quips>();
+    gs.ecs.register::<Encumbrance>();
+    gs.ecs.register::<Consumable>();
+    gs.ecs.register::<Identifiable>();
+    gs.ecs.register::<IdentifiesItem>();
+    gs.ecs.register::<Cursed>();
+    gs.ecs.register::<CurseUnknown>();
+    gs.ecs.register::<RemovesCurse>();
+    gs.ecs.register::<MagicMapper>();
+    gs.ecs.register::<DetectsMonsters>();
+    gs.ecs.register::<DetectingMonsters>();
+    gs.ecs.register::<Ranged>();
+    gs.ecs.register::<AreaOfEffect>();
+    gs.ecs.register::<InflictsDamage>();
+    gs.ecs.register::<Poisons>();
+    gs.ecs.register::<Poisoned>();
+    gs.ecs.register::<Confusion>();
+    gs.ecs.register::<Confused>();
+    gs.ecs.register::<Mana>();
+    gs.ecs.register::<Spell>();
+    gs.ecs.register::<KnownSpells>();
+    gs.ecs.register::<TeachesSpell>();
+    gs.ecs.register::<Teleports>();
+    gs.ecs.register::<Blinks>();
+    gs.ecs.register::<TeleportsLevel>();
+    gs.ecs.register::<EntryTrigger>();
+    gs.ecs.register::<EntityMoved>();
+    gs.ecs.register::<DarkVision>();
+    gs.ecs.register::<LightSource>();
+    gs.ecs.register::<GrantsTelepathy>();
+    gs.ecs.register::<Telepathic>();
+    gs.ecs.register::<GrantsSeeInvisible>();
+    gs.ecs.register::<SeeingInvisible>();
+    gs.ecs.register::<Blinds>();
+    gs.ecs.register::<Blinded>();
+    gs.ecs.register::<Invisible>();
+    gs.ecs.register::<Stackable>();
+    gs.ecs.register::<Quantity>();
+    gs.ecs.register::<ProvidesHealing>();
     gs.ecs.register::<InBackpack>();
     gs.ecs.register::<WantsToPickupItem>();
-    gs.ecs.register::<WantsToDrinkPotion>();
+    gs.ecs.register::<WantsToUseItem>();
     gs.ecs.register::<WantsToDropItem>();
+    gs.ecs.register::<WantsToRemoveItem>();
+    gs.ecs.register::<NaturalAttack>();
+    gs.ecs.register::<MeleeWeapon>();
+    gs.ecs.register::<ExtraDamage>();
+    gs.ecs.register::<Rarity>();
+    gs.ecs.register::<Equippable>();
+    gs.ecs.register::<Equipped>();
+    gs.ecs.register::<DefenseBonus>();
+    gs.ecs.register::<AttributeBonus>();
+    gs.ecs.register::<Attributes>();
+    gs.ecs.register::<Skills>();
+    gs.ecs.register::<Progression>();
+    gs.ecs.register::<Resistances>();
+    gs.ecs.register::<LastDamagedBy>();
+    gs.ecs.register::<Description>();
 
-    gs.ecs.insert(rltk::RandomNumberGenerator::new());
-
-    let map : Map = Map::new_map_rooms_and_corridors();
+    // the first command line argument seeds the run so that a dungeon and its dice rolls can be replayed
+    let seed = std::env::args().nth(1)
+        .and_then(|arg| arg.parse::<u64>().ok())
+        .unwrap_or_else(|| RandomNumberGenerator::new().rand::<u64>());
+    console::log(format!("Seed: {}", seed));
+    let mut rng = RandomNumberGenerator::seeded(seed);
 
-    for room in map.rooms.iter() {
-        spawner::spawn_room(&mut gs.ecs, room);
-    }
+    // drawn from the seeded rng so the same seed disguises items the same way; playing a seed again
+    // picks up whatever was identified in that seed's own save file
+    let identification = Identification::new(seed, &mut rng);
+    gs.ecs.insert(Identification::load(seed).unwrap_or(identification));
+    gs.ecs.insert(rng);
 
-    // Create the player
-    let (player_x,player_y) = map.rooms[0].center();
-    let player_entity = spawner::player(&mut gs.ecs, player_x, player_y);
-    for _i in 0..4 {
-        spawner::health_potion_to_backpack(&mut gs.ecs, player_entity);
-    }
+    // Create the player; the town puts them in its tavern
+    let player_entity = spawner::player(&mut gs.ecs, 0, 0);
+    spawner::health_potion_to_backpack(&mut gs.ecs, player_entity, 4);
+    gs.ecs.insert(player_entity);
+    gs.ecs.insert(MasterDungeonMap::default());
+    gs.ecs.insert(ParticleBuilder::default());
+    gs.ecs.insert(gui::TargetingPreview::default());
+    let town = gs.build_level(0);
+    let (player_x, player_y) = town::start_position(&town);
+    gs.ecs.insert(town);
+    gs.place_player(player_x, player_y);
 
     // resource registration
-    gs.ecs.insert(map);
-    gs.ecs.insert(Point::new(player_x, player_y));
     gs.ecs.insert(RunState::PreRun);
-    gs.ecs.insert(player_entity);
-    gs.ecs.insert(GameLog{ entries: vec!["You have entered the dungeon. It's dark, and full of terrors.".to_string()]});
+    gs.ecs.insert(RunStats::default());
+    gs.ecs.insert(PlayerActivity::Idle);
+    gs.ecs.insert(AutoExploreOptions{ pick_up_items: false });
+    let mut gamelog = GameLog::default();
+    gamelog.log("You wake in the tavern with a sore head. The way into the dungeon lies at the east end of town.");
+    gamelog.log("The Barkeep has advice for anyone who asks; walk into someone to talk to them.");
+
+    // broken bindings are reported in the log rather than stopping the game
+    let bindings = match KeyBindings::load(KEYBINDINGS_FILE) {
+        Ok(bindings) => bindings,
+        Err(errors) => {
+            for error in errors.iter() {
+                console::log(format!("{}: {}", KEYBINDINGS_FILE, error));
+                gamelog.entry().colour(RGB::named(rltk::RED), format!("{}: {}", KEYBINDINGS_FILE, error)).log();
+            }
+            gamelog.log("Using the default numpad key bindings instead.");
+            KeyBindings::new(Preset::Numpad).unwrap_or_else(|conflicts| {
+                for conflict in conflicts.iter() {
+                    console::log(format!("numpad preset: {}", conflict));
+                }
+                KeyBindings::fallback(Preset::Numpad)
+            })
+        }
+    };
+    gamelog.log(format!("Press {} for a list of commands.", bindings.describe(Command::Help)));
+    gs.ecs.insert(gamelog);
+    gs.ecs.insert(bindings);
 
 
     rltk::main_loop(context, gs)
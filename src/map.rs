@@ -2,11 +2,13 @@ use rltk::{RGB, Rltk, RandomNumberGenerator, Algorithm2D, Point, BaseMap};
 use super::{Rect};
 use std::cmp::{min,max};
 use specs::prelude::*;
-use crate::{Viewshed, Player};
+use crate::{Position, EntryTrigger};
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum TileType {
-    Wall, Floor
+    Wall, Floor, DownStairs, UpStairs,
+    // town ground
+    Grass, Road, WoodFloor
 }
 
 pub const MAP_WIDTH : usize = 80;
@@ -15,7 +17,18 @@ pub const MAP_COUNT : usize = MAP_WIDTH * MAP_HEIGHT;
 pub const MAX_MONSTERS : usize = 4;
 pub const MAX_ITEMS : usize = 2;
 
-#[derive(Default)]
+// whether a dungeon level's rooms are lit, or everything is dark beyond what torches and other lights reach
+#[derive(PartialEq, Copy, Clone)]
+pub enum RoomLighting { Lit, Dark }
+
+// the light a lit room is filled with
+const ROOM_LIGHT : (f32, f32, f32) = (1.0, 1.0, 0.9);
+// a tile needs at least this much light in some colour for the player to see what is on it
+const LIGHT_THRESHOLD : f32 = 0.15;
+// how bright a visible tile is drawn however little light falls on it
+const MIN_BRIGHTNESS : f32 = 0.35;
+
+#[derive(Default, Clone)]
 pub struct Map {
     pub tiles : Vec<TileType>,
     pub rooms : Vec<Rect>,
@@ -24,7 +37,12 @@ pub struct Map {
     pub revealed_tiles : Vec<bool>,
     pub visible_tiles : Vec<bool>,
     pub blocked : Vec<bool>,
-    pub tile_content : Vec<Vec<Entity>>
+    pub tile_content : Vec<Vec<Entity>>,
+    // light that is always there, such as a lit room's; the LightingSystem adds light sources on top to make `light`
+    pub ambient_light : Vec<RGB>,
+    pub light : Vec<RGB>,
+    // how far down the dungeon this level is, which makes its items more dangerous
+    pub depth : i32
 }
 
 impl Map {
@@ -66,6 +84,23 @@ impl Map {
         !self.blocked[idx]
     }
 
+    /// Whether an entity could be put down at (x, y): on the map, not a wall and not taken by anything that blocks the tile
+    pub fn is_open(&self, x : i32, y : i32) -> bool {
+        if x < 1 || x > self.width - 2 || y < 1 || y > self.height - 2 { return false; }
+        let idx = self.xy_idx(x, y);
+        self.tiles[idx] != TileType::Wall && !self.blocked[idx]
+    }
+
+    /// A random open tile anywhere on the level, if there is one
+    pub fn random_open_tile(&self, rng : &mut RandomNumberGenerator) -> Option<Point> {
+        let open : Vec<Point> = (0..self.tiles.len() as i32)
+            .map(|idx| Point::new(idx % self.width, idx / self.width))
+            .filter(|p| self.is_open(p.x, p.y))
+            .collect();
+        if open.is_empty() { return None; }
+        Some(open[(rng.roll_dice(1, open.len() as i32) - 1) as usize])
+    }
+
     // originally populates the blocked tiles array with the walls of the map
     pub fn populate_blocked(&mut self){
         for (i,tile) in self.tiles.iter_mut().enumerate() {
@@ -73,15 +108,44 @@ impl Map {
         }
     }
 
+    // reveals a row of the level as magic mapping sweeps over it, leaving out walls buried in solid rock
+    pub fn reveal_row(&mut self, y : i32) {
+        for x in 0..self.width {
+            let idx = self.xy_idx(x, y);
+            let bordering_open = (-1..=1).any(|dy| (-1..=1).any(|dx| {
+                let (nx, ny) = (x + dx, y + dy);
+                nx >= 0 && nx < self.width && ny >= 0 && ny < self.height && self.tiles[self.xy_idx(nx, ny)] != TileType::Wall
+            }));
+            if bordering_open { self.revealed_tiles[idx] = true; }
+        }
+    }
+
+    // the first tile of a kind on the map, such as the stairs a player arrives by
+    pub fn find_tile(&self, tile : TileType) -> Option<Point> {
+        self.tiles.iter().position(|t| *t == tile).map(|idx| Point::new(idx as i32 % self.width, idx as i32 / self.width))
+    }
+
     pub fn clear_content_index(&mut self) {
         for content in self.tile_content.iter_mut() {
             content.clear();
         }
     }
 
-    /// Make a map with rooms and corridors carved out
-    pub fn new_map_rooms_and_corridors() -> Map {
-        let mut map = Map{
+    /// A level that is solid wall apart from the given floor tiles, for tests to lay out exactly what they need
+    #[cfg(test)]
+    pub fn with_floor(floor : &[(i32, i32)]) -> Map {
+        let mut map = Map::new(1);
+        for (x, y) in floor.iter() {
+            let idx = map.xy_idx(*x, *y);
+            map.tiles[idx] = TileType::Floor;
+        }
+        map.populate_blocked();
+        map
+    }
+
+    /// A solid block of wall for a map builder to carve into
+    pub fn new(depth : i32) -> Map {
+        Map{
             tiles : vec![TileType::Wall; MAP_COUNT],
             rooms : Vec::new(),
             width : MAP_WIDTH as i32,
@@ -89,15 +153,37 @@ impl Map {
             revealed_tiles : vec![false; MAP_COUNT],
             visible_tiles : vec![false; MAP_COUNT],
             blocked : vec![false; MAP_COUNT],
-            tile_content: vec![Vec::new(); MAP_COUNT]
-        };
+            tile_content: vec![Vec::new(); MAP_COUNT],
+            ambient_light : vec![RGB::from_f32(0.0, 0.0, 0.0); MAP_COUNT],
+            light : vec![RGB::from_f32(0.0, 0.0, 0.0); MAP_COUNT],
+            depth
+        }
+    }
+
+    /// Whether enough light falls on a tile to see what is standing there
+    pub fn is_lit(&self, idx : usize) -> bool {
+        let light = self.light[idx];
+        f32::max(light.r, f32::max(light.g, light.b)) >= LIGHT_THRESHOLD
+    }
+
+    // fills a room, and the walls around it, with steady light
+    fn light_room(&mut self, room : &Rect) {
+        for y in i32::max(0, room.y1 - 1)..=i32::min(self.height - 1, room.y2 + 1) {
+            for x in i32::max(0, room.x1 - 1)..=i32::min(self.width - 1, room.x2 + 1) {
+                let idx = self.xy_idx(x, y);
+                self.ambient_light[idx] = RGB::from_f32(ROOM_LIGHT.0, ROOM_LIGHT.1, ROOM_LIGHT.2);
+            }
+        }
+    }
+
+    /// Make a map with rooms and corridors carved out, its rooms lit or left dark. Corridors are always dark.
+    pub fn new_map_rooms_and_corridors(rng : &mut RandomNumberGenerator, depth : i32, lighting : RoomLighting) -> Map {
+        let mut map = Map::new(depth);
 
         const MAX_ROOMS:i32 = 30;
         const MIN_SIZE:i32 = 6;
         const MAX_SIZE:i32 = 10;
 
-        let mut rng = RandomNumberGenerator::new();
-
         for _ in 0..MAX_ROOMS {
             let w = rng.range(MIN_SIZE, MAX_SIZE);
             let h = rng.range(MIN_SIZE, MAX_SIZE);
@@ -136,10 +222,107 @@ impl Map {
             }
         }
 
+        // the way back up is where the player arrives, the way further down waits in the last room dug
+        let (up_x, up_y) = map.rooms[0].center();
+        let up_idx = map.xy_idx(up_x, up_y);
+        map.tiles[up_idx] = TileType::UpStairs;
+        let (stairs_x, stairs_y) = map.rooms[map.rooms.len() - 1].center();
+        let stairs_idx = map.xy_idx(stairs_x, stairs_y);
+        map.tiles[stairs_idx] = TileType::DownStairs;
+
+        if lighting == RoomLighting::Lit {
+            for room in map.rooms.clone().iter() {
+                map.light_room(room);
+            }
+        }
+
         map
     }
 }
 
+// what stepping onto a known trap costs a path, so one is only crossed when there is no way around it
+const TRAP_STEP_COST : f32 = 50.0;
+
+/// The tiles of every trap the player has seen, for paths to steer around
+pub fn known_traps(ecs : &World) -> Vec<usize> {
+    let map = ecs.fetch::<Map>();
+    let positions = ecs.read_storage::<Position>();
+    let triggers = ecs.read_storage::<EntryTrigger>();
+    (&positions, &triggers).join()
+        .map(|(pos, _trigger)| map.xy_idx(pos.x, pos.y))
+        .filter(|idx| map.revealed_tiles[*idx])
+        .collect()
+}
+
+/// Pathing view of a map that only walks tiles the player has already seen, and optionally
+/// the unrevealed tiles on the edge of them, so it never gives away the layout of unexplored terrain
+pub struct KnownTerrain<'a> {
+    pub map : &'a Map,
+    pub frontier : bool,
+    pub traps : &'a [usize]
+}
+
+impl<'a> KnownTerrain<'a> {
+    fn step_cost(&self, idx : usize) -> f32 {
+        if self.traps.contains(&idx) { TRAP_STEP_COST } else { 1.0 }
+    }
+
+    fn is_known_floor(&self, x:i32, y:i32) -> bool {
+        if x < 1 || x > self.map.width - 1 || y < 1 || y > self.map.height - 1 { return false; }
+        let idx = self.map.xy_idx(x, y);
+        (self.map.revealed_tiles[idx] && self.map.tiles[idx] != TileType::Wall) || (self.frontier && self.map.is_frontier(idx))
+    }
+}
+
+impl<'a> BaseMap for KnownTerrain<'a> {
+    fn get_available_exits(&self, idx:usize) -> rltk::SmallVec<[(usize, f32); 10]> {
+        let mut exits = rltk::SmallVec::new();
+        let x = idx as i32 % self.map.width;
+        let y = idx as i32 / self.map.width;
+        let w = self.map.width as usize;
+
+        if self.is_known_floor(x - 1, y) { exits.push((idx - 1, self.step_cost(idx - 1))) };
+        if self.is_known_floor(x + 1, y) { exits.push((idx + 1, self.step_cost(idx + 1))) };
+        if self.is_known_floor(x, y - 1) { exits.push((idx - w, self.step_cost(idx - w))) };
+        if self.is_known_floor(x, y + 1) { exits.push((idx + w, self.step_cost(idx + w))) };
+
+        exits
+    }
+
+    fn get_pathing_distance(&self, idx1:usize, idx2:usize) -> f32 {
+        self.map.get_pathing_distance(idx1, idx2)
+    }
+}
+
+impl Map {
+    /// Whether an unrevealed tile borders revealed floor, making it the next thing to explore
+    pub fn is_frontier(&self, idx : usize) -> bool {
+        if self.revealed_tiles[idx] { return false; }
+        let x = idx as i32 % self.width;
+        let y = idx as i32 / self.width;
+        [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter().any(|(nx, ny)| {
+            if *nx < 0 || *nx >= self.width || *ny < 0 || *ny >= self.height { return false; }
+            let n = self.xy_idx(*nx, *ny);
+            self.revealed_tiles[n] && self.tiles[n] != TileType::Wall
+        })
+    }
+
+    /// Path between two points through revealed terrain, going around the given traps where it can, excluding the starting tile
+    pub fn known_path(&self, from : Point, to : Point, traps : &[usize]) -> Option<Vec<usize>> {
+        if to.x < 0 || to.x >= self.width || to.y < 0 || to.y >= self.height { return None; }
+        let target = self.xy_idx(to.x, to.y);
+        if !self.revealed_tiles[target] || self.tiles[target] == TileType::Wall { return None; }
+        if from == to { return None; }
+
+        let path = rltk::a_star_search(self.xy_idx(from.x, from.y), target, &KnownTerrain{ map: self, frontier: false, traps });
+        if path.success && path.steps.len() > 1 {
+            Some(path.steps[1..].to_vec())
+        } else {
+            None
+        }
+    }
+}
+
 impl BaseMap for Map {
     fn is_opaque(&self, idx:usize) -> bool {
         self.tiles[idx] == TileType::Wall
@@ -196,8 +379,34 @@ pub fn draw_map(ecs: &World, ctx: &mut Rltk){
                     glyph = rltk::to_cp437('#');
                     fg = RGB::from_f32(0., 1.0, 0.);
                 }
+                TileType::DownStairs => {
+                    glyph = rltk::to_cp437('>');
+                    fg = RGB::from_f32(0., 1.0, 1.0);
+                }
+                TileType::UpStairs => {
+                    glyph = rltk::to_cp437('<');
+                    fg = RGB::from_f32(0., 1.0, 1.0);
+                }
+                TileType::Grass => {
+                    glyph = rltk::to_cp437('"');
+                    fg = RGB::from_f32(0.2, 0.6, 0.2);
+                }
+                TileType::Road => {
+                    glyph = rltk::to_cp437('.');
+                    fg = RGB::from_f32(0.6, 0.6, 0.5);
+                }
+                TileType::WoodFloor => {
+                    glyph = rltk::to_cp437('.');
+                    fg = RGB::from_f32(0.6, 0.4, 0.2);
+                }
+            }
+            if map.visible_tiles[idx] {
+                // what can be seen takes on the colour of the light falling on it
+                let light = map.light[idx];
+                fg = RGB::from_f32(fg.r * f32::max(MIN_BRIGHTNESS, light.r), fg.g * f32::max(MIN_BRIGHTNESS, light.g), fg.b * f32::max(MIN_BRIGHTNESS, light.b));
+            } else {
+                fg = fg.to_greyscale();
             }
-            if !map.visible_tiles[idx] { fg = fg.to_greyscale() }
             ctx.set(x, y, fg, RGB::from_f32(0., 0., 0.), glyph);
         }
 
@@ -210,3 +419,81 @@ pub fn draw_map(ecs: &World, ctx: &mut Rltk){
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_mapping_reveals_open_ground_and_the_walls_around_it() {
+        let mut map = Map::with_floor(&[(5, 5), (6, 5)]);
+        map.reveal_row(4);
+        map.reveal_row(5);
+        assert!(map.revealed_tiles[map.xy_idx(5, 5)]);
+        assert!(map.revealed_tiles[map.xy_idx(7, 5)]);
+        assert!(map.revealed_tiles[map.xy_idx(4, 4)]);
+        // buried rock and rows not yet swept stay hidden
+        assert!(!map.revealed_tiles[map.xy_idx(20, 5)]);
+        assert!(!map.revealed_tiles[map.xy_idx(5, 6)]);
+    }
+
+    #[test]
+    fn walls_blocked_tiles_and_the_map_edge_are_never_open() {
+        let mut map = Map::with_floor(&[(0, 5), (5, 5), (6, 5)]);
+        assert!(map.is_open(5, 5));
+        assert!(!map.is_open(7, 5));
+        assert!(!map.is_open(0, 5));
+        assert!(!map.is_open(-1, 5));
+        let idx = map.xy_idx(6, 5);
+        map.blocked[idx] = true;
+        assert!(!map.is_open(6, 5));
+    }
+
+    #[test]
+    fn random_open_tiles_are_never_walls() {
+        let mut rng = RandomNumberGenerator::seeded(9);
+        let map = Map::with_floor(&[(3, 3), (4, 3), (10, 12)]);
+        for _ in 0..100 {
+            let tile = map.random_open_tile(&mut rng).expect("there is open floor");
+            assert!(map.tiles[map.xy_idx(tile.x, tile.y)] == TileType::Floor);
+        }
+        assert!(Map::with_floor(&[]).random_open_tile(&mut rng).is_none());
+    }
+
+    #[test]
+    fn the_frontier_is_unseen_ground_next_to_seen_floor() {
+        let mut map = Map::with_floor(&[(5, 5), (6, 5), (7, 5)]);
+        let seen = map.xy_idx(5, 5);
+        map.revealed_tiles[seen] = true;
+        assert!(map.is_frontier(map.xy_idx(6, 5)));
+        // only the four orthogonal neighbours count, and seen tiles are never on the frontier
+        assert!(!map.is_frontier(map.xy_idx(6, 6)));
+        assert!(!map.is_frontier(seen));
+        assert!(!map.is_frontier(map.xy_idx(7, 5)));
+    }
+
+    #[test]
+    fn seen_walls_do_not_push_the_frontier_out() {
+        let mut map = Map::with_floor(&[(5, 5)]);
+        let wall = map.xy_idx(5, 4);
+        map.revealed_tiles[wall] = true;
+        assert!(!map.is_frontier(map.xy_idx(5, 3)));
+        assert!(!map.is_frontier(map.xy_idx(0, 0)));
+    }
+
+    #[test]
+    fn known_paths_step_around_known_traps_when_they_can() {
+        let floor = [(5, 5), (6, 5), (7, 5), (5, 6), (6, 6), (7, 6), (8, 5), (9, 5)];
+        let mut map = Map::with_floor(&floor);
+        for (x, y) in floor.iter() {
+            let idx = map.xy_idx(*x, *y);
+            map.revealed_tiles[idx] = true;
+        }
+        let detour = map.known_path(Point::new(5, 5), Point::new(7, 5), &[map.xy_idx(6, 5)]).expect("there is a way round");
+        assert!(!detour.contains(&map.xy_idx(6, 5)));
+        assert_eq!(detour.last(), Some(&map.xy_idx(7, 5)));
+        // with no way round the trap is crossed rather than giving up
+        let across = map.known_path(Point::new(7, 5), Point::new(9, 5), &[map.xy_idx(8, 5)]).expect("the trap can still be crossed");
+        assert!(across.contains(&map.xy_idx(8, 5)));
+    }
+}
@@ -1,41 +1,193 @@
 use specs::prelude::*;
-use crate::{WantsToMelee, SufferDamage, CombatStats, Name, game_log::GameLog};
+use rltk::{RandomNumberGenerator, DiceType, RGB};
+use crate::{WantsToMelee, SufferDamage, CombatStats, Name, NaturalAttack, MeleeWeapon, Equipped, Skills, DamageType, Renderable, ExtraDamage,
+            Position};
+use crate::particle_system::ParticleBuilder;
+use crate::game_log::{GameLog, entity_colour};
+
+// armour class of an entity with no defense bonus at all
+pub const BASE_ARMOUR_CLASS : i32 = 10;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum AttackOutcome { Fumble, Miss, Hit, Critical }
+
+/// How a d20 attack turns out: a natural 1 always misses and a natural 20 always lands as a critical,
+/// otherwise the attack roll has to meet the target's armour class
+pub fn resolve_attack(natural_roll : i32, attack_roll : i32, armour_class : i32) -> AttackOutcome {
+    if natural_roll == 1 { return AttackOutcome::Fumble; }
+    if natural_roll == 20 { return AttackOutcome::Critical; }
+    if attack_roll >= armour_class { AttackOutcome::Hit } else { AttackOutcome::Miss }
+}
+
+/// The hit bonus of what an entity fights with: its equipped weapon's, or else its natural attack's
+pub fn wielded_hit_bonus(entity : Entity, entities : &Entities, natural_attacks : &ReadStorage<NaturalAttack>,
+        melee_weapons : &ReadStorage<MeleeWeapon>, equipped : &ReadStorage<Equipped>) -> i32 {
+    let weapon = (entities, melee_weapons, equipped).join().find(|(_e, _weapon, equipped_by)| equipped_by.owner == entity);
+    match weapon {
+        Some((_e, weapon, _equipped_by)) => weapon.hit_bonus,
+        None => natural_attacks.get(entity).map_or(0, |attack| attack.hit_bonus)
+    }
+}
+
+/// Everything added to the d20 when an entity attacks: its weapon's hit bonus, its melee skill and its power
+pub fn attack_bonus(hit_bonus : i32, skills : Option<&Skills>, stats : &CombatStats) -> i32 {
+    hit_bonus + skills.map_or(0, |s| s.melee) + stats.power
+}
 
 pub struct MeleeCombatSystem {}
 
 impl<'a> System<'a> for MeleeCombatSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
         Entities<'a>,
         WriteExpect<'a, GameLog>,
+        WriteExpect<'a, RandomNumberGenerator>,
         WriteStorage<'a, WantsToMelee>,
         WriteStorage<'a, SufferDamage>,
         ReadStorage<'a, Name>,
-        ReadStorage<'a, CombatStats>
+        ReadStorage<'a, CombatStats>,
+        ReadStorage<'a, NaturalAttack>,
+        ReadStorage<'a, MeleeWeapon>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, Skills>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, ExtraDamage>,
+        ReadStorage<'a, Position>,
+        WriteExpect<'a, ParticleBuilder>
     );
 
     fn run(&mut self, data : Self::SystemData) {
-        let (entities, mut gamelog, mut wants_melee, mut inflict_damage, names, combat_stats) = data;
+        let (entities, mut gamelog, mut rng, mut wants_melee, mut inflict_damage, names, combat_stats, natural_attacks, melee_weapons, equipped, skills,
+            renderables, extra_damage, positions, mut particle_builder) = data;
 
-        for(_entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
+        for(entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
             if stats.hp > 0 {
                 let target_stats = combat_stats.get(wants_melee.target).unwrap();
 
                 if target_stats.hp > 0 {
                     let target_name = names.get(wants_melee.target).unwrap();
 
-                    let damage = i32::max(0, stats.power - target_stats.defense);
+                    // an equipped weapon takes precedence over the attacker's natural attack
+                    let mut verb = "hits".to_string();
+                    let mut with = String::new();
+                    let mut damage_dice = DiceType::new(1, 4, 0);
+                    let mut damage_type = DamageType::Bludgeoning;
+                    let mut weapon_used : Option<Entity> = None;
+                    if let Some(attack) = natural_attacks.get(entity) {
+                        verb = attack.name.clone();
+                        damage_dice = attack.damage;
+                        damage_type = attack.damage_type;
+                    }
+                    for (weapon_entity, weapon, equipped_by) in (&entities, &melee_weapons, &equipped).join() {
+                        if equipped_by.owner == entity {
+                            verb = "hits".to_string();
+                            with = format!(" with the {}", names.get(weapon_entity).map_or("weapon", |n| &n.name));
+                            damage_dice = weapon.damage;
+                            damage_type = weapon.damage_type;
+                            weapon_used = Some(weapon_entity);
+                        }
+                    }
+
+                    let natural_roll = rng.roll_dice(1, 20);
+                    let hit_bonus = wielded_hit_bonus(entity, &entities, &natural_attacks, &melee_weapons, &equipped);
+                    let attack_roll = natural_roll + attack_bonus(hit_bonus, skills.get(entity), stats);
+                    let armour_class = BASE_ARMOUR_CLASS + target_stats.defense;
+                    let attacker_colour = entity_colour(&renderables, entity);
+                    let target_colour = entity_colour(&renderables, wants_melee.target);
+
+                    let outcome = resolve_attack(natural_roll, attack_roll, armour_class);
+                    let hit = outcome == AttackOutcome::Hit || outcome == AttackOutcome::Critical;
+                    // the target flashes when struck
+                    if let Some(pos) = positions.get(wants_melee.target).filter(|_| hit) {
+                        let flash = if outcome == AttackOutcome::Critical { RGB::named(rltk::RED) } else { RGB::named(rltk::ORANGE) };
+                        particle_builder.request(pos.x, pos.y, flash, RGB::named(rltk::BLACK), rltk::to_cp437('‼'), 200.0);
+                    }
+                    match outcome {
+                        AttackOutcome::Fumble => {
+                            gamelog.entry().colour(attacker_colour, &name.name).text(" fumbles the attack on ")
+                                .colour(target_colour, &target_name.name).text("!").log();
+                        }
+                        AttackOutcome::Critical => {
+                            // critical hits roll the damage dice twice
+                            let damage = i32::max(1, rng.roll(damage_dice) + rng.roll(damage_dice) + stats.power);
+                            gamelog.entry().colour(RGB::named(rltk::ORANGE), "Critical hit! ").colour(attacker_colour, &name.name)
+                                .text(format!(" {} ", verb)).colour(target_colour, &target_name.name).text(format!("{} for ", with))
+                                .damage(damage).text(".").log();
+                            SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage, damage_type, Some(entity));
+                        }
+                        AttackOutcome::Miss => {
+                            gamelog.entry().colour(attacker_colour, &name.name).text(" attacks ")
+                                .colour(target_colour, &target_name.name).text(" but misses.").log();
+                        }
+                        AttackOutcome::Hit => {
+                            let damage = i32::max(1, rng.roll(damage_dice) + stats.power);
+                            gamelog.entry().colour(attacker_colour, &name.name).text(format!(" {} ", verb))
+                                .colour(target_colour, &target_name.name).text(format!("{} for ", with))
+                                .damage(damage).text(".").log();
+                            SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage, damage_type, Some(entity));
+                        }
+                    }
 
-                    if damage == 0 {
-                        gamelog.entries.push(format!("{} does no damage to {}", &name.name, &target_name.name));
-                        // console::log(&format!("{} does no damage to {}", &name.name, &target_name.name));
-                    } else {
-                        gamelog.entries.push(format!("{} deals {} damage to {}", &name.name, damage, &target_name.name));
-                        // console::log(&format!("{} deals {} damage to {}", &name.name, damage, &target_name.name));
-                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
+                    // enchanted weapons pile damage of another type onto each hit
+                    if let Some(extra) = weapon_used.and_then(|w| extra_damage.get(w)).filter(|_| hit) {
+                        let damage = rng.roll(extra.damage);
+                        gamelog.entry().text("The ").colour(target_colour, &target_name.name).text(" takes another ")
+                            .damage(damage).text(format!(" from the {}.", extra.damage_type.noun())).log();
+                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage, extra.damage_type, Some(entity));
                     }
                 }
             }
         }
         wants_melee.clear();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EquipmentSlot;
+
+    #[test]
+    fn natural_one_always_misses() {
+        assert_eq!(resolve_attack(1, 40, BASE_ARMOUR_CLASS), AttackOutcome::Fumble);
+    }
+
+    #[test]
+    fn natural_twenty_always_lands_as_a_critical() {
+        assert_eq!(resolve_attack(20, 0, 99), AttackOutcome::Critical);
+    }
+
+    #[test]
+    fn attack_roll_has_to_meet_armour_class() {
+        assert_eq!(resolve_attack(10, BASE_ARMOUR_CLASS, BASE_ARMOUR_CLASS), AttackOutcome::Hit);
+        assert_eq!(resolve_attack(10, BASE_ARMOUR_CLASS - 1, BASE_ARMOUR_CLASS), AttackOutcome::Miss);
+    }
+
+    #[test]
+    fn a_wielded_weapon_sets_the_hit_bonus_instead_of_the_natural_attack() {
+        let mut ecs = World::new();
+        ecs.register::<NaturalAttack>();
+        ecs.register::<MeleeWeapon>();
+        ecs.register::<Equipped>();
+        let fighter = ecs.create_entity()
+            .with(NaturalAttack{ name: "punches".to_string(), hit_bonus: 1, damage: DiceType::new(1, 2, 0), damage_type: DamageType::Bludgeoning })
+            .build();
+        let hit_bonus = |ecs : &World| wielded_hit_bonus(fighter, &ecs.entities(), &ecs.read_storage::<NaturalAttack>(),
+            &ecs.read_storage::<MeleeWeapon>(), &ecs.read_storage::<Equipped>());
+        assert_eq!(hit_bonus(&ecs), 1);
+
+        ecs.create_entity()
+            .with(MeleeWeapon{ hit_bonus: 3, damage: DiceType::new(1, 6, 0), damage_type: DamageType::Piercing })
+            .with(Equipped{ owner: fighter, slot: EquipmentSlot::Melee })
+            .build();
+        assert_eq!(hit_bonus(&ecs), 3);
+    }
+
+    #[test]
+    fn the_attack_bonus_adds_skill_and_power_to_the_weapon() {
+        let stats = CombatStats{ max_hp: 10, hp: 10, defense: 0, power: 2, initiative: 0 };
+        let skills = Skills{ melee: 3, defence: 0, magic: 0 };
+        assert_eq!(attack_bonus(1, Some(&skills), &stats), 6);
+        assert_eq!(attack_bonus(1, None, &stats), 3);
+    }
+}
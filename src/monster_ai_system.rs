@@ -1,7 +1,10 @@
 use specs::prelude::*;
 use super::{Viewshed, Position, Map, Monster};
-use rltk::{field_of_view, Point, console};
-use crate::{RunState, WantsToMelee};
+use rltk::{Point, RandomNumberGenerator};
+use crate::{RunState, WantsToMelee, CombatStats, Confused, EntityMoved, KnownSpells, Spell, Blinks, Ranged, Mana, WantsToUseItem};
+
+// one turn in this many, a monster that can blink does so rather than running up to the player
+const BLINK_CHANCE : i32 = 3;
 
 pub struct MonsterAI {
 
@@ -13,28 +16,68 @@ impl<'a> System<'a> for MonsterAI {
                         ReadExpect<'a, Point>,
                         ReadExpect<'a, Entity>,
                         ReadExpect<'a, RunState>,
+                        WriteExpect<'a, RandomNumberGenerator>,
                         Entities<'a>,
                         WriteStorage<'a, Viewshed>,
                         ReadStorage<'a, Monster>,
                         WriteStorage<'a, Position>,
-                        WriteStorage<'a, WantsToMelee>);
+                        WriteStorage<'a, WantsToMelee>,
+                        ReadStorage<'a, CombatStats>,
+                        ReadStorage<'a, Confused>,
+                        WriteStorage<'a, EntityMoved>,
+                        ReadStorage<'a, KnownSpells>,
+                        ReadStorage<'a, Spell>,
+                        ReadStorage<'a, Blinks>,
+                        ReadStorage<'a, Ranged>,
+                        ReadStorage<'a, Mana>,
+                        WriteStorage<'a, WantsToUseItem>);
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, player_pos, player_entity, runstate, entities, mut viewshed, monster, mut position, mut wants_to_melee) = data;
+        let (mut map, player_pos, player_entity, runstate, mut rng, entities, mut viewshed, monster, mut position, mut wants_to_melee, combat_stats,
+            confused, mut moved, known_spells, spells, blinks, ranged, manas, mut wants_use) = data;
 
         if *runstate != RunState::MonsterTurn { return; }
 
-        for (entity, mut viewshed,_monster,mut pos) in (&entities, &mut viewshed, &monster, &mut position).join() {
+        // the quickest monsters take their turns first, so they claim contested tiles before slower ones
+        let mut turn_order : Vec<(Entity, i32)> = (&entities, &monster, &viewshed, &position).join()
+            .map(|(entity, _monster, _viewshed, _pos)| (entity, combat_stats.get(entity).map_or(0, |s| s.initiative)))
+            .collect();
+        turn_order.sort_by_key(|turn| std::cmp::Reverse(turn.1));
+
+        for (entity, _initiative) in turn_order {
+            let viewshed = viewshed.get_mut(entity).unwrap();
+            let pos = position.get_mut(entity).unwrap();
+            // a confused monster loses its turns until its head clears
+            if confused.get(entity).is_some() { continue; }
+
             let distance = rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
             if distance < 1.5 {
                 wants_to_melee.insert(entity, WantsToMelee{ target: *player_entity }).expect("Unable to insert attack");
             }
             else if viewshed.visible_tiles.contains(&*player_pos) {
+                // a blinker sometimes skips the run and appears right beside the player
+                let blink = known_spells.get(entity).and_then(|known| known.spells.iter().copied().find(|s| blinks.get(*s).is_some()));
+                if let Some(blink) = blink {
+                    let cost = spells.get(blink).map_or(0, |s| s.mana_cost);
+                    let range = ranged.get(blink).map_or(0, |r| r.range) as f32;
+                    let can_pay = manas.get(entity).is_some_and(|m| m.mana >= cost);
+                    if can_pay && rng.roll_dice(1, BLINK_CHANCE) == 1 {
+                        let prey = *player_pos;
+                        let landing = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| Point::new(prey.x + dx, prey.y + dy)))
+                            .find(|p| map.is_open(p.x, p.y) && viewshed.visible_tiles.contains(p)
+                                && rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *p) <= range);
+                        if let Some(landing) = landing {
+                            wants_use.insert(entity, WantsToUseItem{ item: blink, target_item: None, target: Some(landing) }).expect("Unable to insert intent");
+                            continue;
+                        }
+                    }
+                }
+
                 // Path to the player
                 let path = rltk::a_star_search(
                     map.xy_idx(pos.x, pos.y),
                     map.xy_idx(player_pos.x, player_pos.y),
-                    &mut *map
+                    &*map
                 );
                 if path.success && path.steps.len()>1 {
                     let mut idx = map.xy_idx(pos.x, pos.y);
@@ -44,6 +87,7 @@ impl<'a> System<'a> for MonsterAI {
                     idx = map.xy_idx(pos.x, pos.y);
                     map.blocked[idx] = true;
                     viewshed.dirty = true;
+                    moved.insert(entity, EntityMoved{}).expect("Unable to insert moved marker");
                 }
             }
         }
@@ -0,0 +1,74 @@
+use specs::prelude::*;
+use rltk::{Rltk, RGB};
+use crate::{Position, Renderable, ParticleLifetime};
+use crate::spawner::RenderOrder;
+
+/// Removes particles whose time is up; called every frame, so they fade on the clock rather than by game turns
+pub fn cull_dead_particles(ecs : &mut World, ctx : &Rltk) {
+    let mut dead_particles : Vec<Entity> = Vec::new();
+    {
+        let mut particles = ecs.write_storage::<ParticleLifetime>();
+        let entities = ecs.entities();
+        for (entity, particle) in (&entities, &mut particles).join() {
+            particle.lifetime_ms -= ctx.frame_time_ms;
+            if particle.lifetime_ms < 0.0 {
+                dead_particles.push(entity);
+            }
+        }
+    }
+    for dead in dead_particles.iter() {
+        ecs.delete_entity(*dead).expect("Particle will not die");
+    }
+}
+
+struct ParticleRequest {
+    x : i32,
+    y : i32,
+    fg : RGB,
+    bg : RGB,
+    glyph : rltk::FontCharType,
+    lifetime : f32
+}
+
+/// Systems push particles in here; the ParticleSpawnSystem turns them into entities at the end of the turn
+#[derive(Default)]
+pub struct ParticleBuilder {
+    requests : Vec<ParticleRequest>
+}
+
+impl ParticleBuilder {
+    pub fn request(&mut self, x : i32, y : i32, fg : RGB, bg : RGB, glyph : rltk::FontCharType, lifetime : f32) {
+        self.requests.push(ParticleRequest{ x, y, fg, bg, glyph, lifetime });
+    }
+}
+
+pub struct ParticleSpawnSystem {}
+
+impl<'a> System<'a> for ParticleSpawnSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Renderable>,
+        WriteStorage<'a, ParticleLifetime>,
+        WriteExpect<'a, ParticleBuilder>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (entities, mut positions, mut renderables, mut particles, mut particle_builder) = data;
+
+        for new_particle in particle_builder.requests.iter() {
+            let particle = entities.create();
+            positions.insert(particle, Position{ x: new_particle.x, y: new_particle.y }).expect("Unable to insert position");
+            renderables.insert(particle, Renderable{
+                glyph: new_particle.glyph,
+                fg: new_particle.fg,
+                bg: new_particle.bg,
+                render_order: RenderOrder::Particle as i32
+            }).expect("Unable to insert renderable");
+            particles.insert(particle, ParticleLifetime{ lifetime_ms: new_particle.lifetime }).expect("Unable to insert lifetime");
+        }
+
+        particle_builder.requests.clear();
+    }
+}
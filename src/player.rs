@@ -1,9 +1,21 @@
-use rltk::{VirtualKeyCode, Rltk, Point, console};
+use rltk::{Rltk, Point, RandomNumberGenerator};
 use specs::prelude::*;
-use std::cmp::{max, min};
 use super::{Position, Player, Viewshed, TileType, State, Map, RunState};
-use crate::{CombatStats, WantsToMelee, Item, WantsToPickupItem};
+use crate::{CombatStats, WantsToMelee, Item, WantsToPickupItem, Monster, Name, Vendor, Bystander, Quips, KnownSpells, EntityMoved, Confused,
+            Invisible, SeeingInvisible};
 use crate::game_log::GameLog;
+use crate::visibility_system::visible_hostile;
+use crate::{auto_explore, travel};
+use crate::keybindings::{KeyBindings, Command};
+
+// something the player keeps doing across turns until it finishes or is interrupted
+#[derive(PartialEq, Clone)]
+pub enum PlayerActivity {
+    Idle,
+    Resting,
+    Exploring { seen_items : Vec<Entity>, hp : i32 },
+    Travelling { path : Vec<usize> }
+}
 
 pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World){
     let mut positions = ecs.write_storage::<Position>();
@@ -14,6 +26,7 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World){
     let map = ecs.fetch::<Map>();
     let entities = ecs.entities();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
+    let mut moved = ecs.write_storage::<EntityMoved>();
 
     for (entity, _player, pos, viewshed) in (&entities, &mut players, &mut positions, &mut viewsheds).join() {
         let destination_idx = map.xy_idx(pos.x + delta_x, pos.y + delta_y);
@@ -28,10 +41,11 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World){
         }
 
         if !map.blocked[destination_idx]{
-            pos.x = min(79, max(0, pos.x + delta_x));
-            pos.y = min(49, max(0, pos.y + delta_y));
+            pos.x = (pos.x + delta_x).clamp(0, 79);
+            pos.y = (pos.y + delta_y).clamp(0, 49);
 
             viewshed.dirty = true;
+            moved.insert(entity, EntityMoved{}).expect("Unable to insert moved marker");
             let mut ppos = ecs.write_resource::<Point>();
             ppos.x = pos.x;
             ppos.y = pos.y;
@@ -39,39 +53,205 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World){
     }
 }
 
+/// The direction the player actually moves in: a confused player stumbles off any which way
+pub fn stagger(ecs : &World, delta_x : i32, delta_y : i32) -> (i32, i32) {
+    if ecs.read_storage::<Confused>().get(*ecs.fetch::<Entity>()).is_none() { return (delta_x, delta_y); }
+    let directions = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+    let roll = ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, directions.len() as i32) - 1;
+    directions[roll as usize]
+}
+
+// a vendor standing next to the player in the given direction
+fn vendor_at(ecs : &World, delta_x : i32, delta_y : i32) -> Option<Entity> {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let vendors = ecs.read_storage::<Vendor>();
+    let destination_idx = map.xy_idx(player_pos.x + delta_x, player_pos.y + delta_y);
+    map.tile_content[destination_idx].iter().find(|e| vendors.get(**e).is_some()).copied()
+}
+
+// whoever is standing in the given direction and has something to say
+fn talker_at(ecs : &World, delta_x : i32, delta_y : i32) -> Option<Entity> {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let quips = ecs.read_storage::<Quips>();
+    let destination_idx = map.xy_idx(player_pos.x + delta_x, player_pos.y + delta_y);
+    map.tile_content[destination_idx].iter().find(|e| quips.get(**e).is_some()).copied()
+}
+
+// bumping into a townsperson gets a word out of them, and bystanders step aside by trading places
+fn talk_to(ecs : &mut World, talker : Entity) {
+    {
+        let quips = ecs.read_storage::<Quips>();
+        let names = ecs.read_storage::<Name>();
+        if let Some(quips) = quips.get(talker) {
+            if !quips.lines.is_empty() {
+                let line = (ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, quips.lines.len() as i32) - 1) as usize;
+                let name = names.get(talker).map_or("Someone", |n| &n.name);
+                ecs.write_resource::<GameLog>().log(format!("{} says: \"{}\"", name, quips.lines[line]));
+            }
+        }
+    }
+
+    if ecs.read_storage::<Bystander>().get(talker).is_none() { return; }
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut positions = ecs.write_storage::<Position>();
+    let (player_x, player_y) = match positions.get(player_entity) { Some(pos) => (pos.x, pos.y), None => return };
+    let (talker_x, talker_y) = match positions.get(talker) { Some(pos) => (pos.x, pos.y), None => return };
+    positions.insert(talker, Position{ x: player_x, y: player_y }).expect("Unable to insert position");
+    positions.insert(player_entity, Position{ x: talker_x, y: talker_y }).expect("Unable to insert position");
+
+    let mut ppos = ecs.write_resource::<Point>();
+    ppos.x = talker_x;
+    ppos.y = talker_y;
+    if let Some(viewshed) = ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+        viewshed.dirty = true;
+    }
+}
+
 pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
-    match ctx.key {
+    let activity = (*gs.ecs.fetch::<PlayerActivity>()).clone();
+    if activity != PlayerActivity::Idle {
+        // any key press cancels an ongoing activity
+        if ctx.key.is_some() {
+            *gs.ecs.write_resource::<PlayerActivity>() = PlayerActivity::Idle;
+            gs.ecs.write_resource::<GameLog>().log("You stop what you are doing.");
+            return RunState::AwaitingInput;
+        }
+        return match activity {
+            PlayerActivity::Resting => rest(&mut gs.ecs),
+            PlayerActivity::Exploring{..} => auto_explore::explore(&mut gs.ecs),
+            PlayerActivity::Travelling{..} => travel::travel(&mut gs.ecs),
+            PlayerActivity::Idle => RunState::AwaitingInput
+        };
+    }
+
+    // clicking a revealed tile walks there one turn at a time; clicks on the panels below the map are not travel targets
+    if ctx.left_click {
+        let (x, y) = ctx.mouse_pos();
+        let on_map = {
+            let map = gs.ecs.fetch::<Map>();
+            x >= 0 && x < map.width && y >= 0 && y < map.height
+        };
+        if on_map {
+            return travel::start_travel(&mut gs.ecs, Point::new(x, y));
+        }
+    }
+
+    // keys only mean something through the bindings loaded at startup
+    let command = match ctx.key {
+        None => return RunState::AwaitingInput,
+        Some(key) => gs.ecs.fetch::<KeyBindings>().command(key)
+    };
+
+    match command {
         None => { return RunState::AwaitingInput }
-        Some(key) => match key {
-            VirtualKeyCode::Left |
-            VirtualKeyCode::Numpad4 => try_move_player(-1, 0, &mut gs.ecs),
-            VirtualKeyCode::Right |
-            VirtualKeyCode::Numpad6 => try_move_player(1, 0, &mut gs.ecs),
-            VirtualKeyCode::Up |
-            VirtualKeyCode::Numpad8 => try_move_player(0, -1, &mut gs.ecs),
-            VirtualKeyCode::Down |
-            VirtualKeyCode::Numpad2 => try_move_player(0, 1, &mut gs.ecs),
-
-            // diagonals
-            VirtualKeyCode::Numpad9 => try_move_player(1, -1, &mut gs.ecs),   // up right
-            VirtualKeyCode::Numpad7 => try_move_player(-1,-1, &mut gs.ecs),   // up left
-            VirtualKeyCode::Numpad1 => try_move_player(-1, 1, &mut gs.ecs),   // down left
-            VirtualKeyCode::Numpad3 => try_move_player(1,1, &mut gs.ecs),     // down right
+        Some(command) => match command {
+            Command::MoveNorth | Command::MoveSouth | Command::MoveEast | Command::MoveWest |
+            Command::MoveNorthEast | Command::MoveNorthWest | Command::MoveSouthEast | Command::MoveSouthWest => {
+                if let Some((delta_x, delta_y)) = command.direction() {
+                    let (delta_x, delta_y) = stagger(&gs.ecs, delta_x, delta_y);
+                    // walking into a vendor opens their wares instead
+                    if let Some(vendor) = vendor_at(&gs.ecs, delta_x, delta_y) {
+                        return RunState::ShowTrade{ vendor, buying: true, page: 0 };
+                    }
+                    if let Some(talker) = talker_at(&gs.ecs, delta_x, delta_y) {
+                        talk_to(&mut gs.ecs, talker);
+                        return RunState::PlayerTurn;
+                    }
+                    try_move_player(delta_x, delta_y, &mut gs.ecs);
+                }
+            },
+            // skip a turn
+            Command::Wait => {},
+            // rest until healed
+            Command::Rest => {
+                *gs.ecs.write_resource::<PlayerActivity>() = PlayerActivity::Resting;
+                return rest(&mut gs.ecs);
+            },
+
+            // walk towards unexplored territory until something interesting happens
+            Command::AutoExplore => return auto_explore::start_exploring(&mut gs.ecs),
+            // choose whether exploring stops to pick up the items it walks over; this takes no time
+            Command::ToggleAutoPickUp => {
+                auto_explore::toggle_pick_up(&mut gs.ecs);
+                return RunState::AwaitingInput;
+            },
+            // pick a spot to travel to with the keyboard
+            Command::Travel => {
+                let player_pos = gs.ecs.fetch::<Point>();
+                return RunState::ShowTravelCursor{ x: player_pos.x, y: player_pos.y };
+            },
+            // head for whichever known stairs are closest
+            Command::TravelToStairs => return travel::start_travel_to_stairs(&mut gs.ecs),
+            // inspect the surroundings with a keyboard cursor
+            Command::Look => {
+                let player_pos = gs.ecs.fetch::<Point>();
+                return RunState::ShowLook{ x: player_pos.x, y: player_pos.y };
+            },
+
+            // take the stairs, or head for them when they are somewhere else
+            Command::Descend => return use_stairs(&mut gs.ecs, TileType::DownStairs),
+            Command::Ascend => return use_stairs(&mut gs.ecs, TileType::UpStairs),
 
             // pickup
-            VirtualKeyCode::G => get_item(&mut gs.ecs),
+            Command::PickUp => get_item(&mut gs.ecs),
             // show inventory
-            VirtualKeyCode::I => return RunState::ShowInventory,
+            Command::Inventory => return RunState::ShowInventory{ page: 0 },
             // drop items
-            VirtualKeyCode::D => return RunState::ShowDropItems,
-            _ => { return RunState::AwaitingInput }
-
+            Command::Drop => return RunState::ShowDropItems{ page: 0 },
+            // take off equipment
+            Command::RemoveEquipment => return RunState::ShowRemoveItem{ page: 0 },
+            // cast a known spell
+            Command::Cast => return cast_spell(&mut gs.ecs),
+            // character sheet
+            Command::CharacterSheet => return RunState::ShowCharacterSheet,
+            // message history
+            Command::MessageHistory => return RunState::ShowLogHistory{ offset: 0 },
+            // list the current key bindings
+            Command::Help => return RunState::ShowHelp
         },
     }
     RunState::PlayerTurn
 }
 
-fn get_item(ecs : &mut World) {
+// standing on the stairs takes them; otherwise the player travels to them if they have been seen
+fn use_stairs(ecs : &mut World, stairs : TileType) -> RunState {
+    let (on_stairs, known_stairs) = {
+        let player_pos = ecs.fetch::<Point>();
+        let map = ecs.fetch::<Map>();
+        let known_stairs = map.find_tile(stairs).filter(|p| map.revealed_tiles[map.xy_idx(p.x, p.y)]);
+        (map.tiles[map.xy_idx(player_pos.x, player_pos.y)] == stairs, known_stairs)
+    };
+    let direction = if stairs == TileType::DownStairs { "down" } else { "up" };
+
+    if on_stairs {
+        return if stairs == TileType::DownStairs { RunState::NextLevel } else { RunState::PreviousLevel };
+    }
+    match known_stairs {
+        Some(target) => {
+            ecs.write_resource::<GameLog>().log(format!("You head for the way {}.", direction));
+            travel::start_travel(ecs, target)
+        }
+        None => {
+            ecs.write_resource::<GameLog>().log(format!("You don't know of a way {} from here.", direction));
+            RunState::AwaitingInput
+        }
+    }
+}
+
+// opens the spell list, unless there is nothing on it
+fn cast_spell(ecs : &mut World) -> RunState {
+    let player_entity = *ecs.fetch::<Entity>();
+    let knows_spells = ecs.read_storage::<KnownSpells>().get(player_entity).is_some_and(|k| !k.spells.is_empty());
+    if !knows_spells {
+        ecs.write_resource::<GameLog>().log("You do not know any spells.");
+        return RunState::AwaitingInput;
+    }
+    RunState::ShowSpells{ page: 0 }
+}
+
+pub fn get_item(ecs : &mut World) {
     let player_pos = ecs.fetch::<Point>();
     let player_entity = ecs.fetch::<Entity>();
     let entities = ecs.entities();
@@ -87,10 +267,42 @@ fn get_item(ecs : &mut World) {
     }
 
     match target_item {
-        None => {gamelog.entries.push("There is nothing to pickup here.".to_string()); },
+        None => {gamelog.log("There is nothing to pickup here."); },
         Some(item) => {
             let mut pickup = ecs.write_storage::<WantsToPickupItem>();
-            pickup.insert(*player_entity, WantsToPickupItem{ collected_by: *player_entity, item: item}).expect("Unable to insert want to pickup");
+            pickup.insert(*player_entity, WantsToPickupItem{ collected_by: *player_entity, item}).expect("Unable to insert want to pickup");
         }
     }
-}
\ No newline at end of file
+}
+// waits a turn unless the player is fully healed or can see something hostile
+fn rest(ecs : &mut World) -> RunState {
+    let player_entity = ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+    let monsters = ecs.read_storage::<Monster>();
+    let invisible = ecs.read_storage::<Invisible>();
+    let see_invisible = ecs.read_storage::<SeeingInvisible>().get(*player_entity).is_some();
+    let names = ecs.read_storage::<Name>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+    let mut activity = ecs.fetch_mut::<PlayerActivity>();
+
+    if let Some(viewshed) = viewsheds.get(*player_entity) {
+        if let Some(hostile) = visible_hostile(&map, viewshed, &monsters, &invisible, see_invisible) {
+            let name = names.get(hostile).map_or("something", |n| &n.name);
+            gamelog.log(format!("You cannot rest with the {} in view.", name));
+            *activity = PlayerActivity::Idle;
+            return RunState::AwaitingInput;
+        }
+    }
+
+    if let Some(stats) = combat_stats.get(*player_entity) {
+        if stats.hp >= stats.max_hp {
+            gamelog.log("You feel rested.");
+            *activity = PlayerActivity::Idle;
+            return RunState::AwaitingInput;
+        }
+    }
+
+    RunState::PlayerTurn
+}
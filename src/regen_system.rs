@@ -0,0 +1,130 @@
+use specs::prelude::*;
+use crate::{CombatStats, Attributes, Viewshed, Monster, Map, Mana, RunState, Invisible, SeeingInvisible};
+use crate::run_stats::RunStats;
+use crate::visibility_system::visible_hostile;
+
+// turns between natural regeneration ticks before fitness is taken into account
+const BASE_REGEN_INTERVAL : i32 = 10;
+// the same for mana, which comes back quicker the sharper the mind
+const BASE_MANA_INTERVAL : i32 = 8;
+
+pub struct RegenerationSystem {}
+
+impl<'a> System<'a> for RegenerationSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, RunState>,
+        ReadExpect<'a, RunStats>,
+        WriteStorage<'a, CombatStats>,
+        ReadStorage<'a, Attributes>,
+        ReadStorage<'a, Viewshed>,
+        ReadStorage<'a, Monster>,
+        WriteStorage<'a, Mana>,
+        Entities<'a>,
+        ReadStorage<'a, Invisible>,
+        ReadStorage<'a, SeeingInvisible>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (map, player_entity, runstate, run_stats, mut combat_stats, attributes, viewsheds, monsters, mut manas, entities,
+            invisible, seeing_invisible) = data;
+
+        if *runstate != RunState::PlayerTurn { return; }
+
+        // mana returns to every caster, monsters included, whether or not there is danger about
+        for (entity, mana) in (&entities, &mut manas).join() {
+            let intelligence_bonus = attributes.get(entity).map_or(0, |a| a.intelligence.bonus);
+            let mana_interval = i32::max(2, BASE_MANA_INTERVAL - intelligence_bonus);
+            if run_stats.turn % mana_interval == 0 {
+                mana.mana = i32::min(mana.max_mana, mana.mana + 1);
+            }
+        }
+
+        let fitness_bonus = attributes.get(*player_entity).map_or(0, |a| a.fitness.bonus);
+        let interval = i32::max(3, BASE_REGEN_INTERVAL - fitness_bonus);
+        if run_stats.turn % interval != 0 { return; }
+
+        // wounds only knit while nothing hostile is in sight
+        if let Some(viewshed) = viewsheds.get(*player_entity) {
+            if visible_hostile(&map, viewshed, &monsters, &invisible, seeing_invisible.get(*player_entity).is_some()).is_some() { return; }
+        }
+
+        if let Some(stats) = combat_stats.get_mut(*player_entity) {
+            stats.hp = i32::min(stats.max_hp, stats.hp + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rltk::Point;
+
+    // a wounded player on the given turn, with a monster standing in view if asked for
+    fn world(turn : i32, monster_in_view : bool) -> (World, Entity) {
+        let mut ecs = World::new();
+        ecs.register::<CombatStats>();
+        ecs.register::<Attributes>();
+        ecs.register::<Viewshed>();
+        ecs.register::<Monster>();
+        ecs.register::<Mana>();
+        ecs.register::<Invisible>();
+        ecs.register::<SeeingInvisible>();
+
+        let mut map = Map::with_floor(&[(5, 5)]);
+        let player = ecs.create_entity()
+            .with(CombatStats{ max_hp: 20, hp: 10, defense: 0, power: 0, initiative: 0 })
+            .with(Viewshed{ visible_tiles: vec![Point::new(5, 5)], range: 8, dirty: false })
+            .build();
+        if monster_in_view {
+            let monster = ecs.create_entity().with(Monster{}).build();
+            let idx = map.xy_idx(5, 5);
+            map.tile_content[idx].push(monster);
+        }
+        ecs.insert(map);
+        ecs.insert(player);
+        ecs.insert(RunState::PlayerTurn);
+        ecs.insert(RunStats{ turn, kills: 0, cause_of_death: None });
+        (ecs, player)
+    }
+
+    fn hp_after_regen(ecs : &World, player : Entity) -> i32 {
+        RegenerationSystem{}.run_now(ecs);
+        let stats = ecs.read_storage::<CombatStats>();
+        stats.get(player).unwrap().hp
+    }
+
+    #[test]
+    fn wounds_knit_every_few_turns() {
+        let (ecs, player) = world(BASE_REGEN_INTERVAL, false);
+        assert_eq!(hp_after_regen(&ecs, player), 11);
+        let (ecs, player) = world(BASE_REGEN_INTERVAL + 1, false);
+        assert_eq!(hp_after_regen(&ecs, player), 10);
+    }
+
+    #[test]
+    fn wounds_stay_open_with_a_monster_in_view() {
+        let (ecs, player) = world(BASE_REGEN_INTERVAL, true);
+        assert_eq!(hp_after_regen(&ecs, player), 10);
+    }
+
+    #[test]
+    fn an_unseen_invisible_monster_does_not_keep_wounds_open() {
+        let (ecs, player) = world(BASE_REGEN_INTERVAL, true);
+        let monster = (&ecs.entities(), &ecs.read_storage::<Monster>()).join().map(|(e, _)| e).next().unwrap();
+        ecs.write_storage::<Invisible>().insert(monster, Invisible{}).expect("Unable to insert");
+        assert_eq!(hp_after_regen(&ecs, player), 11);
+        ecs.write_storage::<SeeingInvisible>().insert(player, SeeingInvisible{ turns: 5 }).expect("Unable to insert");
+        assert_eq!(hp_after_regen(&ecs, player), 11);
+    }
+
+    #[test]
+    fn mana_returns_even_with_a_monster_in_view() {
+        let (ecs, player) = world(BASE_MANA_INTERVAL, true);
+        ecs.write_storage::<Mana>().insert(player, Mana{ max_mana: 10, mana: 5 }).expect("Unable to insert mana");
+        RegenerationSystem{}.run_now(&ecs);
+        assert_eq!(ecs.read_storage::<Mana>().get(player).unwrap().mana, 6);
+    }
+}
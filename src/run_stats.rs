@@ -0,0 +1,7 @@
+// running totals for the current game, shown on the game over screen
+#[derive(Default)]
+pub struct RunStats {
+    pub turn : i32,
+    pub kills : i32,
+    pub cause_of_death : Option<String>
+}
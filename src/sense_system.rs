@@ -0,0 +1,78 @@
+use specs::prelude::*;
+use rltk::RGB;
+use crate::{WantsToUseItem, Name, Renderable, Identifiable, Viewshed, DetectsMonsters, DetectingMonsters, GrantsTelepathy, Telepathic,
+            GrantsSeeInvisible, SeeingInvisible, Blinds, Blinded};
+use crate::game_log::{GameLog, entity_colour};
+use crate::identification::{Identification, display_name};
+
+pub struct SenseEffectSystem {}
+
+// applies the effects of used items that sharpen or dull the user's senses
+impl<'a> System<'a> for SenseEffectSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        ReadStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Identifiable>,
+        ReadExpect<'a, Identification>,
+        WriteStorage<'a, Viewshed>,
+        ReadStorage<'a, DetectsMonsters>,
+        WriteStorage<'a, DetectingMonsters>,
+        ReadStorage<'a, GrantsTelepathy>,
+        WriteStorage<'a, Telepathic>,
+        ReadStorage<'a, GrantsSeeInvisible>,
+        WriteStorage<'a, SeeingInvisible>,
+        ReadStorage<'a, Blinds>,
+        WriteStorage<'a, Blinded>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, mut gamelog, entities, wants_use, names, renderables, identifiables, identification, mut viewsheds,
+            detects_monsters, mut detecting_monsters, grants_telepathy, mut telepathic, grants_see_invisible, mut seeing_invisible,
+            blinds, mut blinded) = data;
+
+        for (entity, useitem) in (&entities, &wants_use).join() {
+            let is_player = entity == *player_entity;
+            let item_name = display_name(&names, &identifiables, &identification, useitem.item);
+            let item_colour = entity_colour(&renderables, useitem.item);
+            let mut drank = false;
+            let mut drink = |gamelog : &mut GameLog| {
+                if is_player && !drank {
+                    gamelog.entry().text("You drink the ").colour(item_colour, &item_name).text(".").log();
+                    drank = true;
+                }
+            };
+
+            if let Some(detector) = detects_monsters.get(useitem.item) {
+                detecting_monsters.insert(entity, DetectingMonsters{ turns: detector.turns }).expect("Unable to insert detecting monsters");
+                drink(&mut gamelog);
+                if is_player { gamelog.log("You sense the creatures lurking around you."); }
+            }
+
+            if let Some(telepathy) = grants_telepathy.get(useitem.item) {
+                telepathic.insert(entity, Telepathic{ range: telepathy.range, turns: telepathy.turns }).expect("Unable to insert telepathic");
+                drink(&mut gamelog);
+                if is_player { gamelog.entry().colour(RGB::named(rltk::MAGENTA), "Your mind reaches out to the minds around you.").log(); }
+            }
+
+            if let Some(see_invisible) = grants_see_invisible.get(useitem.item) {
+                seeing_invisible.insert(entity, SeeingInvisible{ turns: see_invisible.turns }).expect("Unable to insert seeing invisible");
+                drink(&mut gamelog);
+                if is_player { gamelog.entry().colour(RGB::named(rltk::MAGENTA), "Your eyes tingle.").log(); }
+            }
+
+            if let Some(blindness) = blinds.get(useitem.item) {
+                blinded.insert(entity, Blinded{ turns: blindness.turns }).expect("Unable to insert blinded");
+                drink(&mut gamelog);
+                if is_player { gamelog.entry().colour(RGB::named(rltk::MAGENTA), "Darkness falls over your eyes!").log(); }
+                if let Some(viewshed) = viewsheds.get_mut(entity) {
+                    viewshed.dirty = true;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use specs::prelude::*;
+use rltk::RGB;
+use crate::{Value, Purse, Identifiable, CurseUnknown, Renderable, Name};
+use crate::game_log::{GameLog, entity_colour};
+use crate::identification::{Identification, is_unidentified, entity_name};
+use crate::inventory_system::{split_stack, stow};
+
+/// What a vendor asks for one of an item
+pub fn buy_price(ecs : &World, item : Entity) -> i32 {
+    ecs.read_storage::<Value>().get(item).map_or(0, |v| v.gold)
+}
+
+/// What a vendor pays for one of an item: half its value, or a quarter while the player cannot vouch for what it is
+pub fn sell_price(ecs : &World, item : Entity) -> i32 {
+    let value = buy_price(ecs, item);
+    let unidentified = is_unidentified(&ecs.read_storage::<Identifiable>(), &ecs.fetch::<Identification>(), item)
+        || ecs.read_storage::<CurseUnknown>().get(item).is_some();
+    if unidentified { value / 4 } else { value / 2 }
+}
+
+pub fn gold_carried(ecs : &World, entity : Entity) -> i32 {
+    ecs.read_storage::<Purse>().get(entity).map_or(0, |p| p.gold)
+}
+
+// hands one of an item from the seller's pack to the buyer's, and the price the other way
+fn exchange(ecs : &mut World, item : Entity, seller : Entity, buyer : Entity, price : i32) {
+    {
+        let mut purses = ecs.write_storage::<Purse>();
+        if let Some(purse) = purses.get_mut(buyer) { purse.gold -= price; }
+        if let Some(purse) = purses.get_mut(seller) { purse.gold += price; }
+    }
+    let one = split_stack(ecs, item, 1);
+    stow(ecs, one, buyer);
+}
+
+/// The player buys one of `item` from the vendor's stock
+pub fn buy(ecs : &mut World, vendor : Entity, item : Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let price = buy_price(ecs, item);
+    let name = entity_name(ecs, item);
+    let colour = entity_colour(&ecs.read_storage::<Renderable>(), item);
+
+    if gold_carried(ecs, player_entity) < price {
+        ecs.write_resource::<GameLog>().entry().text("You cannot afford the ").colour(colour, name).text(".").log();
+        return;
+    }
+
+    exchange(ecs, item, vendor, player_entity, price);
+    ecs.write_resource::<GameLog>().entry().text("You buy the ").colour(colour, name)
+        .text(" for ").colour(RGB::named(rltk::GOLD), format!("{} gold", price)).text(".").log();
+}
+
+/// The player sells one of `item` to the vendor
+pub fn sell(ecs : &mut World, vendor : Entity, item : Entity) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let price = sell_price(ecs, item);
+    let name = entity_name(ecs, item);
+    let colour = entity_colour(&ecs.read_storage::<Renderable>(), item);
+    let vendor_name = ecs.read_storage::<Name>().get(vendor).map_or("The vendor".to_string(), |n| format!("The {}", n.name));
+
+    if price < 1 {
+        ecs.write_resource::<GameLog>().entry().text(format!("{} has no interest in the ", vendor_name)).colour(colour, name).text(".").log();
+        return;
+    }
+    if gold_carried(ecs, vendor) < price {
+        ecs.write_resource::<GameLog>().entry().text(format!("{} cannot afford the ", vendor_name)).colour(colour, name).text(".").log();
+        return;
+    }
+
+    exchange(ecs, item, player_entity, vendor, price);
+    ecs.write_resource::<GameLog>().entry().text("You sell the ").colour(colour, name)
+        .text(" for ").colour(RGB::named(rltk::GOLD), format!("{} gold", price)).text(".").log();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rltk::RandomNumberGenerator;
+    use crate::{ItemTemplate, InBackpack, Position, Stackable, Quantity};
+
+    fn world() -> World {
+        let mut ecs = World::new();
+        ecs.register::<Value>();
+        ecs.register::<Purse>();
+        ecs.register::<Identifiable>();
+        ecs.register::<CurseUnknown>();
+        ecs.register::<Renderable>();
+        ecs.register::<Name>();
+        ecs.register::<InBackpack>();
+        ecs.register::<Position>();
+        ecs.register::<Stackable>();
+        ecs.register::<Quantity>();
+        ecs.insert(Identification::new(1, &mut RandomNumberGenerator::seeded(1)));
+        ecs.insert(GameLog::default());
+        ecs
+    }
+
+    #[test]
+    fn vendors_pay_half_for_what_the_player_knows() {
+        let mut ecs = world();
+        let item = ecs.create_entity().with(Value{ gold: 40 }).build();
+        assert_eq!(buy_price(&ecs, item), 40);
+        assert_eq!(sell_price(&ecs, item), 20);
+    }
+
+    #[test]
+    fn vendors_pay_a_quarter_for_unidentified_items() {
+        let mut ecs = world();
+        let potion = ecs.create_entity().with(Value{ gold: 40 }).with(Identifiable{ template: ItemTemplate::HealthPotion }).build();
+        assert_eq!(sell_price(&ecs, potion), 10);
+        ecs.fetch_mut::<Identification>().identify(ItemTemplate::HealthPotion);
+        assert_eq!(sell_price(&ecs, potion), 20);
+    }
+
+    #[test]
+    fn vendors_pay_a_quarter_for_gear_that_might_be_cursed() {
+        let mut ecs = world();
+        let sword = ecs.create_entity().with(Value{ gold: 40 }).with(CurseUnknown{}).build();
+        assert_eq!(sell_price(&ecs, sword), 10);
+    }
+
+    #[test]
+    fn worthless_items_sell_for_nothing() {
+        let mut ecs = world();
+        let rock = ecs.create_entity().build();
+        assert_eq!(sell_price(&ecs, rock), 0);
+    }
+
+    #[test]
+    fn buying_moves_the_item_and_the_gold() {
+        let mut ecs = world();
+        let player = ecs.create_entity().with(Purse{ gold: 50 }).build();
+        let vendor = ecs.create_entity().with(Purse{ gold: 0 }).build();
+        let sword = ecs.create_entity().with(Name{ name: "Sword".to_string() }).with(Value{ gold: 30 }).with(InBackpack{ owner: vendor }).build();
+        ecs.insert(player);
+
+        buy(&mut ecs, vendor, sword);
+        assert_eq!(gold_carried(&ecs, player), 20);
+        assert_eq!(gold_carried(&ecs, vendor), 30);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(sword).unwrap().owner, player);
+
+        // the player cannot afford it back at full price
+        let axe = ecs.create_entity().with(Name{ name: "Axe".to_string() }).with(Value{ gold: 30 }).with(InBackpack{ owner: vendor }).build();
+        buy(&mut ecs, vendor, axe);
+        assert_eq!(ecs.read_storage::<InBackpack>().get(axe).unwrap().owner, vendor);
+        assert_eq!(ecs.fetch::<GameLog>().entries.last().unwrap().text(), "You cannot afford the Axe.");
+    }
+}
@@ -1,15 +1,57 @@
 use::specs::prelude::*;
-use rltk::{RGB, RandomNumberGenerator};
-use crate::{Position, Renderable, Player, Viewshed, Name, CombatStats, Monster, BlocksTile, Rect, MAX_MONSTERS, MAP_WIDTH, Item, Potion, MAX_ITEMS, InBackpack};
+use rltk::{RGB, RandomNumberGenerator, DiceType};
+use crate::{Position, Renderable, Player, Viewshed, Name, CombatStats, Monster, BlocksTile, Rect, MAX_MONSTERS, MAP_WIDTH, Item, MAX_ITEMS, InBackpack,
+            NaturalAttack, MeleeWeapon, Equipped, Equippable, EquipmentSlot, Attributes, Attribute, Skills, Progression,
+            Consumable, ProvidesHealing, DamageType, DamageResponse, Resistances, Description, ItemTemplate, Stackable, Quantity, Weight, Encumbrance,
+            Identifiable, IdentifiesItem, RemovesCurse, Value, Purse, Gold, Vendor,
+            Bystander, Quips, MagicMapper, DetectsMonsters, Ranged, AreaOfEffect, InflictsDamage, Poisons, Confusion,
+            Mana, Spell, SpellTemplate, KnownSpells, TeachesSpell, Teleports, Blinks, TeleportsLevel, EntryTrigger, GrantsTelepathy, GrantsSeeInvisible, Blinds, Invisible,
+            DarkVision, LightSource, Map, TileType};
+use crate::stats_system::{max_hp_at_level, max_mana_at_level, attr_bonus};
+use crate::item_generator;
+use crate::inventory_system;
 
+// lower orders are drawn last, on top of everything else
 pub enum RenderOrder {
-    Player = 0,
-    Monster = 1,
-    Item = 2
+    Particle = 0,
+    Player = 1,
+    Monster = 2,
+    Item = 3
+}
+
+// turns a dice string such as "1d6+2" into a DiceType; templates are hard coded so a bad string is a bug
+fn dice(dice_string : &str) -> DiceType {
+    rltk::parse_dice_string(dice_string).expect("Invalid dice string")
+}
+
+fn attributes(might : i32, fitness : i32, quickness : i32, intelligence : i32) -> Attributes {
+    Attributes{
+        might: Attribute::new(might),
+        fitness: Attribute::new(fitness),
+        quickness: Attribute::new(quickness),
+        intelligence: Attribute::new(intelligence)
+    }
+}
+
+// starting combat stats; the StatsSystem keeps them in step with attributes and equipment afterwards
+fn combat_stats(attrs : &Attributes, skills : &Skills, level : i32) -> CombatStats {
+    let max_hp = max_hp_at_level(&attrs.fitness, level);
+    CombatStats{
+        max_hp,
+        hp: max_hp,
+        defense: skills.defence + attr_bonus(attrs.quickness.base),
+        power: attr_bonus(attrs.might.base),
+        initiative: attr_bonus(attrs.quickness.base)
+    }
 }
 
 pub fn player(ecs:&mut World, player_x:i32, player_y:i32) -> Entity {
-    ecs
+    let attrs = attributes(12, 14, 12, 10);
+    let skills = Skills{ melee: 1, defence: 1, magic: 0 };
+    let stats = combat_stats(&attrs, &skills, 1);
+    let max_mana = max_mana_at_level(&attrs.intelligence, skills.magic, 1);
+
+    let player = ecs
         .create_entity()
         .with(Position{ x: player_x, y:player_y})
         .with(Renderable{
@@ -20,32 +62,45 @@ pub fn player(ecs:&mut World, player_x:i32, player_y:i32) -> Entity {
         })
         .with(Player{})
         .with(Viewshed{ visible_tiles : Vec::new(), range : 8, dirty : true})
+        .with(DarkVision{ range: 2 })
         .with(Name{name: "Player".to_string()})
-        .with(CombatStats{
-            max_hp: 30,
-            hp: 30,
-            defense: 2,
-            power: 5
-        })
-        .build()
+        .with(Description{ text: "That's you, an adventurer with more courage than sense.".to_string() })
+        .with(stats)
+        .with(attrs)
+        .with(skills)
+        .with(Progression{ level: 1, xp: 0 })
+        .with(NaturalAttack{ name: "punches".to_string(), hit_bonus: 0, damage: dice("1d3"), damage_type: DamageType::Bludgeoning })
+        .with(Encumbrance{ carried: 0.0, capacity: 0.0 })
+        .with(Purse{ gold: 25 })
+        .with(Mana{ max_mana, mana: max_mana })
+        .with(KnownSpells{ spells: Vec::new() })
+        .build();
+
+    shortsword_equipped(ecs, player);
+
+    player
 }
 
 pub fn random_monster(ecs:&mut World, x:i32, y:i32) {
     let roll:i32;
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        roll = rng.roll_dice(1,2);
+        roll = rng.roll_dice(1,7);
     }
 
     match roll {
-        1 => { orc(ecs, x, y) }
-        _ => { goblin(ecs, x, y) }
+        1 | 2 => { orc(ecs, x, y) }
+        3 | 4 => { goblin(ecs, x, y) }
+        5 => { blink_dog(ecs, x, y) }
+        6 => { ghost(ecs, x, y) }
+        _ => { fire_troll(ecs, x, y) }
     }
 }
 
-pub fn spawn_room(ecs : &mut World, room : &Rect) {
+pub fn spawn_room(ecs : &mut World, room : &Rect, depth : i32) {
     let mut monster_spawn_points : Vec<usize> = Vec::new();
     let mut item_spawn_points : Vec<usize> = Vec::new();
+    let mut trap_spawn_point : Option<usize> = None;
 
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
@@ -78,6 +133,16 @@ pub fn spawn_room(ecs : &mut World, room : &Rect) {
                 }
             }
         }
+
+        // one room in four has a trap, somewhere nothing else was put
+        if rng.roll_dice(1, 4) == 1 {
+            let x = (room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1))) as usize;
+            let y = (room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1))) as usize;
+            let idx = (y * MAP_WIDTH) + x;
+            if !monster_spawn_points.contains(&idx) && !item_spawn_points.contains(&idx) {
+                trap_spawn_point = Some(idx);
+            }
+        }
     }
 
     for idx in monster_spawn_points.iter() {
@@ -89,19 +154,122 @@ pub fn spawn_room(ecs : &mut World, room : &Rect) {
     for idx in item_spawn_points.iter() {
         let x = *idx % MAP_WIDTH;
         let y = *idx / MAP_WIDTH;
-        health_potion(ecs, x as i32, y as i32);
+        random_item(ecs, x as i32, y as i32, depth);
 
     }
+
+    if let Some(idx) = trap_spawn_point {
+        teleport_trap(ecs, (idx % MAP_WIDTH) as i32, (idx / MAP_WIDTH) as i32);
+    }
 }
 
-fn orc (ecs:&mut World, x:i32, y:i32) { monster(ecs, x, y, RGB::named(rltk::LIGHT_GREEN), rltk::to_cp437('O'), "Orc"); }
-fn goblin(ecs:&mut World, x:i32, y:i32) { monster(ecs, x, y, RGB::named(rltk::RED), rltk::to_cp437('g'), "Goblin"); }
+fn orc (ecs:&mut World, x:i32, y:i32) {
+    let orc = monster(ecs, x, y, RGB::named(rltk::LIGHT_GREEN), rltk::to_cp437('O'), "Orc",
+            "A hulking brute with green skin, filthy claws and a short temper.",
+            NaturalAttack{ name: "claws".to_string(), hit_bonus: 1, damage: dice("1d6"), damage_type: DamageType::Slashing },
+            attributes(14, 8, 8, 6));
+
+    let mut resistances = Resistances::default();
+    resistances.responses.insert(DamageType::Poison, DamageResponse::Resistant);
+    resistances.responses.insert(DamageType::Fire, DamageResponse::Vulnerable);
+    ecs.write_storage::<Resistances>().insert(orc, resistances).expect("Unable to insert resistances");
+}
+fn goblin(ecs:&mut World, x:i32, y:i32) {
+    let goblin = monster(ecs, x, y, RGB::named(rltk::RED), rltk::to_cp437('g'), "Goblin",
+            "A wiry little creature with too many teeth, quick on its feet. It carries a sputtering torch.",
+            NaturalAttack{ name: "bites".to_string(), hit_bonus: 0, damage: dice("1d4"), damage_type: DamageType::Piercing },
+            attributes(8, 6, 14, 8));
+    ecs.write_storage::<LightSource>().insert(goblin, LightSource{ colour: RGB::from_f32(1.0, 0.6, 0.3), range: 4 }).expect("Unable to insert light");
+}
+
+// a lean hound that blinks through space to land at its prey's side
+fn blink_dog(ecs : &mut World, x : i32, y : i32) {
+    let dog = monster(ecs, x, y, RGB::named(rltk::LIGHT_BLUE), rltk::to_cp437('d'), "Blink Dog",
+            "A pale, lean hound that flickers in and out of sight as it runs.",
+            NaturalAttack{ name: "bites".to_string(), hit_bonus: 0, damage: dice("1d4"), damage_type: DamageType::Piercing },
+            attributes(8, 8, 14, 10));
+
+    let blink = spell(ecs, SpellTemplate::Blink);
+    let max_mana = {
+        let attributes = ecs.read_storage::<Attributes>();
+        let skills = ecs.read_storage::<Skills>();
+        max_mana_at_level(&attributes.get(dog).unwrap().intelligence, skills.get(dog).unwrap().magic, 1)
+    };
+    ecs.write_storage::<Mana>().insert(dog, Mana{ max_mana, mana: max_mana }).expect("Unable to insert mana");
+    ecs.write_storage::<KnownSpells>().insert(dog, KnownSpells{ spells: vec![blink] }).expect("Unable to insert known spells");
+    ecs.write_storage::<LightSource>().insert(dog, LightSource{ colour: RGB::from_f32(0.4, 0.6, 1.0), range: 1 }).expect("Unable to insert light");
+}
+
+// a restless spirit that no one sees coming without magic to help them
+fn ghost(ecs : &mut World, x : i32, y : i32) {
+    let ghost = monster(ecs, x, y, RGB::named(rltk::GREY), rltk::to_cp437('G'), "Ghost",
+            "A cold, pale shape drifting just out of the corner of your eye.",
+            NaturalAttack{ name: "touches".to_string(), hit_bonus: 0, damage: dice("1d3"), damage_type: DamageType::Cold },
+            attributes(6, 8, 12, 10));
+    ecs.write_storage::<Invisible>().insert(ghost, Invisible{}).expect("Unable to insert invisible");
+}
+
+// a smouldering troll that fire cannot touch, though frost bites it deep
+fn fire_troll(ecs : &mut World, x : i32, y : i32) {
+    let troll = monster(ecs, x, y, RGB::named(rltk::ORANGE_RED), rltk::to_cp437('T'), "Fire Troll",
+            "A stooped giant whose cracked hide glows like banked coals. Heat rolls off it in waves.",
+            NaturalAttack{ name: "claws".to_string(), hit_bonus: 1, damage: dice("1d8"), damage_type: DamageType::Fire },
+            attributes(16, 14, 6, 6));
+
+    let mut resistances = Resistances::default();
+    resistances.responses.insert(DamageType::Fire, DamageResponse::Immune);
+    resistances.responses.insert(DamageType::Cold, DamageResponse::Vulnerable);
+    ecs.write_storage::<Resistances>().insert(troll, resistances).expect("Unable to insert resistances");
+    ecs.write_storage::<LightSource>().insert(troll, LightSource{ colour: RGB::from_f32(1.0, 0.4, 0.2), range: 2 }).expect("Unable to insert light");
+}
+
+/// A torch burning in a bracket, lighting up the room around it
+pub fn torch(ecs : &mut World, x : i32, y : i32) {
+    ecs.create_entity()
+        .with(Position{ x, y })
+        .with(Renderable{
+            glyph: rltk::to_cp437('☼'),
+            fg: RGB::named(rltk::ORANGE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: RenderOrder::Item as i32
+        })
+        .with(Name{ name: "Torch".to_string() })
+        .with(Description{ text: "A pitch-soaked torch, burning steadily in an iron bracket.".to_string() })
+        .with(LightSource{ colour: RGB::from_f32(1.0, 0.8, 0.5), range: 6 })
+        .build();
+}
+
+/// A teleport trap: whatever steps on it is sent somewhere else on the level
+pub fn teleport_trap(ecs : &mut World, x : i32, y : i32) {
+    ecs.create_entity()
+        .with(Position{ x, y })
+        .with(Renderable{
+            glyph: rltk::to_cp437('^'),
+            fg: RGB::named(rltk::MAGENTA),
+            bg: RGB::named(rltk::BLACK),
+            render_order: RenderOrder::Item as i32
+        })
+        .with(Name{ name: "Teleport Trap".to_string() })
+        .with(Description{ text: "A ring of faintly glowing runes scratched into the floor.".to_string() })
+        .with(EntryTrigger{})
+        .with(Teleports{})
+        .build();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn monster<S:ToString>(ecs : &mut World, x:i32, y:i32, colour : RGB, glyph : rltk::FontCharType, name : S, description : &str, attack : NaturalAttack, attrs : Attributes) -> Entity {
+    let skills = Skills{ melee: 0, defence: 0, magic: 0 };
+    let stats = combat_stats(&attrs, &skills, 1);
+    // about half of all monsters carry a few coins
+    let gold = {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        if rng.roll_dice(1, 2) == 1 { rng.roll_dice(2, 6) } else { 0 }
+    };
 
-fn monster<S:ToString>(ecs : &mut World, x:i32, y:i32, colour : RGB, glyph : rltk::FontCharType, name : S) {
     ecs.create_entity()
         .with(Position{x,y})
         .with(Renderable {
-            glyph : glyph,
+            glyph,
             fg : colour,
             bg: RGB::named(rltk::BLACK),
             render_order: RenderOrder::Monster as i32
@@ -113,45 +281,612 @@ fn monster<S:ToString>(ecs : &mut World, x:i32, y:i32, colour : RGB, glyph : rlt
         })
         .with(Monster{})
         .with(Name{ name: name.to_string() })
+        .with(Description{ text: description.to_string() })
         .with(BlocksTile{})
-        .with(CombatStats{
-            max_hp: 16,
-            hp: 16,
-            defense: 1,
-            power: 4
+        .with(stats)
+        .with(attrs)
+        .with(skills)
+        .with(Progression{ level: 1, xp: 0 })
+        .with(attack)
+        .with(Purse{ gold })
+        .build()
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum VendorKind {
+    // wanders the dungeon with a bit of everything
+    Pedlar,
+    // the town's weapons and armour
+    Blacksmith,
+    // the town's potions and scrolls
+    Alchemist
+}
+
+/// A floor tile in `room` with no stairs and nothing already placed on it, trying the centre first
+pub fn free_spot(ecs : &World, map : &Map, room : &Rect) -> Option<(i32, i32)> {
+    let positions = ecs.read_storage::<Position>();
+    let entities = ecs.entities();
+    let player_entity = ecs.fetch::<Entity>();
+    let taken : Vec<(i32, i32)> = (&entities, &positions).join()
+        .filter(|(entity, _pos)| *entity != *player_entity)
+        .map(|(_entity, pos)| (pos.x, pos.y))
+        .collect();
+
+    let center = room.center();
+    let spots = (room.y1..=room.y2).flat_map(|y| (room.x1..=room.x2).map(move |x| (x, y)));
+    std::iter::once(center).chain(spots).find(|(x, y)| {
+        let idx = map.xy_idx(*x, *y);
+        map.tiles[idx] == TileType::Floor && !taken.contains(&(*x, *y))
+    })
+}
+
+/// A trader standing on the map, stocked from the same spawn tables as the loot found at `depth`
+pub fn vendor(ecs : &mut World, x : i32, y : i32, depth : i32, kind : VendorKind) -> Entity {
+    let (name, description, gold) = match kind {
+        VendorKind::Pedlar => ("Pedlar", "A travelling merchant with a heavy pack, happy to buy or sell anything.", 200 + depth * 50),
+        VendorKind::Blacksmith => ("Blacksmith", "A broad woman in a scorched leather apron, selling blades and mail.", 400),
+        VendorKind::Alchemist => ("Alchemist", "A stooped old man surrounded by bubbling flasks and stacks of parchment.", 300)
+    };
+    let vendor = ecs.create_entity()
+        .with(Position{x,y})
+        .with(Renderable{
+            glyph: rltk::to_cp437('☺'),
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: RenderOrder::Monster as i32
         })
+        .with(Name{ name: name.to_string() })
+        .with(Description{ text: description.to_string() })
+        .with(BlocksTile{})
+        .with(Vendor{})
+        // a vendor keeps a lantern lit, so customers can find them in the dark
+        .with(LightSource{ colour: RGB::from_f32(1.0, 0.9, 0.6), range: 3 })
+        .with(Purse{ gold })
         .build();
+
+    let mut stock : Vec<Entity> = Vec::new();
+    match kind {
+        VendorKind::Pedlar => {
+            stock.push(stackable_item(ecs, ItemTemplate::HealthPotion).with(Quantity{ amount: 3 }).build());
+            for _i in 0..6 { stock.push(random_loot(ecs, depth).build()); }
+        }
+        VendorKind::Blacksmith => {
+            for _i in 0..8 { stock.push(item_generator::equipment(ecs, depth).build()); }
+        }
+        VendorKind::Alchemist => {
+            stock.push(stackable_item(ecs, ItemTemplate::HealthPotion).with(Quantity{ amount: 5 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::IdentifyScroll).with(Quantity{ amount: 3 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::RemoveCurseScroll).with(Quantity{ amount: 2 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::MagicMappingScroll).with(Quantity{ amount: 2 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::DetectMonstersPotion).with(Quantity{ amount: 2 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::FireballScroll).with(Quantity{ amount: 1 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::PoisonCloudScroll).with(Quantity{ amount: 1 }).build());
+            stock.push(spellbook(ecs, SpellTemplate::FireBolt).build());
+            stock.push(spellbook(ecs, SpellTemplate::MendWounds).build());
+            stock.push(stackable_item(ecs, ItemTemplate::TeleportScroll).with(Quantity{ amount: 2 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::BlinkScroll).with(Quantity{ amount: 1 }).build());
+            stock.push(stackable_item(ecs, ItemTemplate::SeeInvisiblePotion).with(Quantity{ amount: 1 }).build());
+        }
+    }
+    for item in stock {
+        inventory_system::stow(ecs, item, vendor);
+    }
+
+    vendor
 }
 
-pub fn health_potion(ecs : &mut World, x : i32, y : i32) {
+#[allow(clippy::too_many_arguments)]
+fn townsfolk<'a, S:ToString>(ecs : &'a mut World, x : i32, y : i32, glyph : char, colour : RGB, name : S, description : &str, lines : &[&str]) -> EntityBuilder<'a> {
     ecs.create_entity()
-        .with(Position {x,y})
+        .with(Position{x,y})
         .with(Renderable{
-            glyph: rltk::to_cp437('i'),
-            fg: RGB::named(rltk::MAGENTA),
+            glyph: rltk::to_cp437(glyph),
+            fg: colour,
+            bg: RGB::named(rltk::BLACK),
+            render_order: RenderOrder::Monster as i32
+        })
+        .with(Name{ name: name.to_string() })
+        .with(Description{ text: description.to_string() })
+        .with(BlocksTile{})
+        .with(Quips{ lines: lines.iter().map(|l| l.to_string()).collect() })
+}
+
+// stays behind the bar, handing out advice to new adventurers
+pub fn barkeep(ecs : &mut World, x : i32, y : i32) {
+    townsfolk(ecs, x, y, '☺', RGB::named(rltk::CYAN), "Barkeep", "A red-faced man polishing the same tankard he has been polishing for years.", &[
+        "The dungeon entrance is at the east end of the road. Plenty go down; fewer come back up.",
+        "Bring back what you find. The Blacksmith and the Alchemist pay for it, though not much for things you can't name.",
+        "Drink an unknown potion when you are safe, not when an orc is at your elbow.",
+        "If your gear won't come off, it's cursed. The Alchemist sells scrolls for that.",
+        "Carry too much and the monsters will run rings round you."
+    ]).build();
+}
+
+pub fn patron(ecs : &mut World, x : i32, y : i32) {
+    townsfolk(ecs, x, y, '☺', RGB::from_u8(200, 160, 100), "Patron", "A regular, well into their cups.", &[
+        "Hic. Another round!",
+        "I went down there once. Once.",
+        "Goblins are quick, but orcs hit harder. Or was it the other way round?"
+    ]).with(Bystander{}).build();
+}
+
+pub fn townsperson(ecs : &mut World, x : i32, y : i32) {
+    townsfolk(ecs, x, y, '☺', RGB::from_u8(160, 160, 200), "Townsperson", "One of the town's folk, going about their business.", &[
+        "Lovely day for it.",
+        "Mind the road east. Nothing good comes out of that hole.",
+        "Excuse me.",
+        "The Blacksmith's prices are criminal, I tell you."
+    ]).with(Bystander{}).build();
+}
+
+// starts an item from a stackable template; the caller adds where it is and how many there are
+pub fn stackable_item(ecs : &mut World, template : ItemTemplate) -> EntityBuilder<'_> {
+    match template {
+        ItemTemplate::HealthPotion => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('i'),
+                    fg: RGB::named(rltk::MAGENTA),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Health Potion".to_string()})
+                .with(Description{ text: "A small flask of bubbling red liquid that knits wounds closed.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(ProvidesHealing{ heal_amount: 8 })
+                .with(Weight{ weight: 0.5 })
+                .with(Value{ gold: 25 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::IdentifyScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Identify".to_string()})
+                .with(Description{ text: "Reading the words aloud lays bare the nature of a single item.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(IdentifiesItem{})
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 40 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::RemoveCurseScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Remove Curse".to_string()})
+                .with(Description{ text: "A prayer of unbinding that loosens the grip of cursed gear.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(RemovesCurse{})
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 60 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::MagicMappingScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Magic Mapping".to_string()})
+                .with(Description{ text: "The ink shifts into the shape of the halls around you, every one of them.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(MagicMapper{})
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 80 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::DetectMonstersPotion => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('i'),
+                    fg: RGB::named(rltk::MAGENTA),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Potion of Monster Detection".to_string()})
+                .with(Description{ text: "A bitter draught that lets you feel the heartbeat of every creature on the level.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(DetectsMonsters{ turns: 50 })
+                .with(Weight{ weight: 0.5 })
+                .with(Value{ gold: 50 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::FireballScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Fireball".to_string()})
+                .with(Description{ text: "Hurls a ball of fire that bursts where it lands. Stand well back.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(Ranged{ range: 6 })
+                .with(AreaOfEffect{ radius: 3 })
+                .with(InflictsDamage{ damage: dice("4d6"), damage_type: DamageType::Fire })
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 100 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::PoisonCloudScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Poison Cloud".to_string()})
+                .with(Description{ text: "Summons a choking green cloud that lingers in the lungs of all who breathe it.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(Ranged{ range: 6 })
+                .with(AreaOfEffect{ radius: 2 })
+                .with(InflictsDamage{ damage: dice("1d4"), damage_type: DamageType::Poison })
+                .with(Poisons{ turns: 5, damage: 2 })
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 70 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::TeleportScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Teleportation".to_string()})
+                .with(Description{ text: "Whisks the reader away to somewhere else on the level. Where, exactly, is anyone's guess.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(Teleports{})
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 40 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::BlinkScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Blinking".to_string()})
+                .with(Description{ text: "Carries the reader in an eyeblink to any spot they can see.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(Ranged{ range: 8 })
+                .with(Blinks{})
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 50 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::LevelTeleportScroll => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('?'),
+                    fg: RGB::named(rltk::YELLOW),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Scroll of Level Teleport".to_string()})
+                .with(Description{ text: "Drops the reader through the floor, or up through the ceiling, onto another level of the dungeon.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(TeleportsLevel{})
+                .with(Weight{ weight: 0.1 })
+                .with(Value{ gold: 60 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::TelepathyPotion => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('i'),
+                    fg: RGB::named(rltk::MAGENTA),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Potion of Telepathy".to_string()})
+                .with(Description{ text: "A cloudy, humming draught that lets you hear the thoughts of nearby creatures through solid rock.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(GrantsTelepathy{ range: 12, turns: 60 })
+                .with(Weight{ weight: 0.5 })
+                .with(Value{ gold: 60 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::SeeInvisiblePotion => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('i'),
+                    fg: RGB::named(rltk::MAGENTA),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Potion of See Invisible".to_string()})
+                .with(Description{ text: "A clear tonic that stings the eyes, and shows you what would rather not be seen.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(GrantsSeeInvisible{ turns: 80 })
+                .with(Weight{ weight: 0.5 })
+                .with(Value{ gold: 50 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+        ItemTemplate::BlindnessPotion => {
+            ecs.create_entity()
+                .with(Renderable{
+                    glyph: rltk::to_cp437('i'),
+                    fg: RGB::named(rltk::MAGENTA),
+                    bg: RGB::named(rltk::BLACK),
+                    render_order: RenderOrder::Item as i32
+                })
+                .with(Name{name: "Potion of Blindness".to_string()})
+                .with(Description{ text: "An inky sludge that leaves the drinker unable to see past the end of their nose.".to_string() })
+                .with(Item{})
+                .with(Consumable{})
+                .with(Blinds{ turns: 15 })
+                .with(Weight{ weight: 0.5 })
+                .with(Value{ gold: 10 })
+                .with(Stackable{ template })
+                .with(Identifiable{ template })
+        }
+    }
+}
+
+pub fn health_potion_to_backpack(ecs : &mut World, player_entity : Entity, amount : i32) {
+    stackable_item(ecs, ItemTemplate::HealthPotion)
+        .with(InBackpack{ owner: player_entity })
+        .with(Quantity{ amount })
+        .build();
+}
+
+// a pile of coins, worth more the deeper it is found
+pub fn gold(ecs : &mut World, x : i32, y : i32, amount : i32) {
+    ecs.create_entity()
+        .with(Position{x,y})
+        .with(Renderable{
+            glyph: rltk::to_cp437('$'),
+            fg: RGB::named(rltk::GOLD),
             bg: RGB::named(rltk::BLACK),
             render_order: RenderOrder::Item as i32
         })
-        .with(Name{name: "Health Potion".to_string()})
+        .with(Name{ name: "Gold".to_string() })
+        .with(Description{ text: "A scattering of coins.".to_string() })
         .with(Item{})
-        .with(Potion{ amount: 8})
+        .with(Gold{ amount })
         .build();
 }
 
-pub fn health_potion_to_backpack(ecs : &mut World, player_entity : Entity) {
+pub fn random_item(ecs : &mut World, x : i32, y : i32, depth : i32) {
+    let roll : i32;
+    {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        roll = rng.roll_dice(1, 10);
+    }
+
+    match roll {
+        1 | 2 => {
+            let amount = ecs.write_resource::<RandomNumberGenerator>().roll_dice(depth + 1, 8);
+            gold(ecs, x, y, amount)
+        }
+        _ => { random_loot(ecs, depth).with(Position{x,y}).build(); }
+    }
+}
+
+// a random item of the sort found lying around at this depth, not yet placed anywhere
+pub fn random_loot(ecs : &mut World, depth : i32) -> EntityBuilder<'_> {
+    let roll : i32;
+    {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        roll = rng.roll_dice(1, 18);
+    }
+
+    match roll {
+        1..=3 => { item_generator::equipment(ecs, depth) }
+        4 => { stackable_item(ecs, ItemTemplate::IdentifyScroll).with(Quantity{ amount: 1 }) }
+        5 => { stackable_item(ecs, ItemTemplate::RemoveCurseScroll).with(Quantity{ amount: 1 }) }
+        6 => { stackable_item(ecs, ItemTemplate::MagicMappingScroll).with(Quantity{ amount: 1 }) }
+        7 => { stackable_item(ecs, ItemTemplate::DetectMonstersPotion).with(Quantity{ amount: 1 }) }
+        8 => { stackable_item(ecs, ItemTemplate::FireballScroll).with(Quantity{ amount: 1 }) }
+        9 => { stackable_item(ecs, ItemTemplate::PoisonCloudScroll).with(Quantity{ amount: 1 }) }
+        10 => { random_spellbook(ecs) }
+        11 => { stackable_item(ecs, ItemTemplate::TeleportScroll).with(Quantity{ amount: 1 }) }
+        12 => { stackable_item(ecs, ItemTemplate::BlinkScroll).with(Quantity{ amount: 1 }) }
+        13 => { stackable_item(ecs, ItemTemplate::LevelTeleportScroll).with(Quantity{ amount: 1 }) }
+        14 => { stackable_item(ecs, ItemTemplate::TelepathyPotion).with(Quantity{ amount: 1 }) }
+        15 => { stackable_item(ecs, ItemTemplate::SeeInvisiblePotion).with(Quantity{ amount: 1 }) }
+        16 => { stackable_item(ecs, ItemTemplate::BlindnessPotion).with(Quantity{ amount: 1 }) }
+        _ => { stackable_item(ecs, ItemTemplate::HealthPotion).with(Quantity{ amount: 1 }) }
+    }
+}
+
+/// Creates a spell from its template. Spells have no place in the world: they carry the same
+/// effect components as the items that do the same thing, plus what they cost to cast.
+pub fn spell(ecs : &mut World, template : SpellTemplate) -> Entity {
+    match template {
+        SpellTemplate::FireBolt => {
+            ecs.create_entity()
+                .with(Name{ name: "Fire Bolt".to_string() })
+                .with(Description{ text: "A dart of flame flung at a single foe.".to_string() })
+                .with(Spell{ template, mana_cost: 2 })
+                .with(Ranged{ range: 6 })
+                .with(InflictsDamage{ damage: dice("2d6"), damage_type: DamageType::Fire })
+                .build()
+        }
+        SpellTemplate::MendWounds => {
+            ecs.create_entity()
+                .with(Name{ name: "Mend Wounds".to_string() })
+                .with(Description{ text: "Knits the caster's flesh back together.".to_string() })
+                .with(Spell{ template, mana_cost: 4 })
+                .with(ProvidesHealing{ heal_amount: 10 })
+                .build()
+        }
+        SpellTemplate::Confuse => {
+            ecs.create_entity()
+                .with(Name{ name: "Confusion".to_string() })
+                .with(Description{ text: "Fills a creature's head with fog, so it forgets what it was doing.".to_string() })
+                .with(Spell{ template, mana_cost: 3 })
+                .with(Ranged{ range: 6 })
+                .with(Confusion{ turns: 4 })
+                .build()
+        }
+        SpellTemplate::Fireball => {
+            ecs.create_entity()
+                .with(Name{ name: "Fireball".to_string() })
+                .with(Description{ text: "A ball of fire that bursts where it lands.".to_string() })
+                .with(Spell{ template, mana_cost: 8 })
+                .with(Ranged{ range: 6 })
+                .with(AreaOfEffect{ radius: 2 })
+                .with(InflictsDamage{ damage: dice("3d6"), damage_type: DamageType::Fire })
+                .build()
+        }
+        SpellTemplate::Blink => {
+            ecs.create_entity()
+                .with(Name{ name: "Blink".to_string() })
+                .with(Description{ text: "Steps the caster through space to a spot they can see.".to_string() })
+                .with(Spell{ template, mana_cost: 3 })
+                .with(Ranged{ range: 6 })
+                .with(Blinks{})
+                .build()
+        }
+    }
+}
+
+/// Starts a spellbook that teaches the spell from `template`; the caller adds where it is
+pub fn spellbook(ecs : &mut World, template : SpellTemplate) -> EntityBuilder<'_> {
+    let spell = spell(ecs, template);
+    let (spell_name, mana_cost) = {
+        let names = ecs.read_storage::<Name>();
+        let spells = ecs.read_storage::<Spell>();
+        (names.get(spell).unwrap().name.clone(), spells.get(spell).unwrap().mana_cost)
+    };
     ecs.create_entity()
-        // .with(Position {x,y})
-        .with(InBackpack{
-            owner: player_entity
-        })
         .with(Renderable{
-            glyph: rltk::to_cp437('i'),
+            glyph: rltk::to_cp437('+'),
             fg: RGB::named(rltk::MAGENTA),
             bg: RGB::named(rltk::BLACK),
             render_order: RenderOrder::Item as i32
         })
-        .with(Name{name: "Health Potion".to_string()})
+        .with(Name{ name: format!("Spellbook of {}", spell_name) })
+        .with(Description{ text: format!("A worn tome. Studying it would teach you to cast {}.", spell_name) })
+        .with(Item{})
+        .with(Consumable{})
+        .with(TeachesSpell{ spell })
+        .with(Weight{ weight: 1.0 })
+        .with(Value{ gold: mana_cost * 25 })
+}
+
+fn random_spellbook(ecs : &mut World) -> EntityBuilder<'_> {
+    let roll = ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, 5);
+    let template = match roll {
+        1 => SpellTemplate::FireBolt,
+        2 => SpellTemplate::MendWounds,
+        3 => SpellTemplate::Confuse,
+        4 => SpellTemplate::Blink,
+        _ => SpellTemplate::Fireball
+    };
+    spellbook(ecs, template)
+}
+
+pub fn shortsword_equipped(ecs : &mut World, owner : Entity) {
+    ecs.create_entity()
+        .with(Equipped{ owner, slot: EquipmentSlot::Melee })
+        .with(Renderable{
+            glyph: rltk::to_cp437('/'),
+            fg: RGB::named(rltk::CYAN),
+            bg: RGB::named(rltk::BLACK),
+            render_order: RenderOrder::Item as i32
+        })
+        .with(Name{name: "Shortsword".to_string()})
+        .with(Description{ text: "A plain soldier's sword, made for thrusting.".to_string() })
         .with(Item{})
-        .with(Potion{ amount: 8})
+        .with(Equippable{ slot: EquipmentSlot::Melee })
+        .with(MeleeWeapon{ hit_bonus: 1, damage: dice("1d6"), damage_type: DamageType::Piercing })
+        .with(Weight{ weight: 2.0 })
+        .with(Value{ gold: 15 })
         .build();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dice_strings_parse_into_dice() {
+        let d = dice("1d6+2");
+        assert_eq!((d.n_dice, d.die_type, d.bonus), (1, 6, 2));
+        let d = dice("4d6");
+        assert_eq!((d.n_dice, d.die_type, d.bonus), (4, 6, 0));
+    }
+
+    #[test]
+    fn dice_strings_take_penalties() {
+        let d = dice("2d4-1");
+        assert_eq!((d.n_dice, d.die_type, d.bonus), (2, 4, -1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn a_bad_dice_string_is_a_bug() {
+        dice("a handful of dice");
+    }
+
+    #[test]
+    fn fire_trolls_shrug_off_flames_but_not_frost() {
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<Renderable>();
+        ecs.register::<Viewshed>();
+        ecs.register::<Monster>();
+        ecs.register::<Name>();
+        ecs.register::<Description>();
+        ecs.register::<BlocksTile>();
+        ecs.register::<CombatStats>();
+        ecs.register::<Attributes>();
+        ecs.register::<Skills>();
+        ecs.register::<Progression>();
+        ecs.register::<NaturalAttack>();
+        ecs.register::<Purse>();
+        ecs.register::<Resistances>();
+        ecs.register::<LightSource>();
+        ecs.insert(RandomNumberGenerator::seeded(1));
+
+        fire_troll(&mut ecs, 1, 1);
+        let resistances = ecs.read_storage::<Resistances>();
+        let troll = resistances.join().next().expect("the troll should have resistances");
+        assert!(troll.responses[&DamageType::Fire] == DamageResponse::Immune);
+        assert!(troll.responses[&DamageType::Cold] == DamageResponse::Vulnerable);
+    }
+}
@@ -0,0 +1,108 @@
+use specs::prelude::*;
+use rltk::RGB;
+use crate::{WantsToUseItem, Spell, Mana, Name};
+use crate::game_log::GameLog;
+
+/// Whether `caster` has the mana to cast `spell` right now
+pub fn can_cast(ecs : &World, caster : Entity, spell : Entity) -> bool {
+    let cost = ecs.read_storage::<Spell>().get(spell).map_or(0, |s| s.mana_cost);
+    ecs.read_storage::<Mana>().get(caster).is_some_and(|m| m.mana >= cost)
+}
+
+pub struct SpellCastSystem {}
+
+// pays for the spells being cast this turn; a spell that cannot be paid for fizzles before the ItemEffectSystem sees it
+impl<'a> System<'a> for SpellCastSystem {
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Spell>,
+        WriteStorage<'a, Mana>,
+        ReadStorage<'a, Name>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, mut gamelog, entities, mut wants_use, spells, mut manas, names) = data;
+
+        let mut fizzled : Vec<Entity> = Vec::new();
+        for (entity, useitem) in (&entities, &wants_use).join() {
+            if let Some(spell) = spells.get(useitem.item) {
+                let spell_name = &names.get(useitem.item).unwrap().name;
+                match manas.get_mut(entity) {
+                    Some(mana) if mana.mana >= spell.mana_cost => {
+                        mana.mana -= spell.mana_cost;
+                        if entity == *player_entity {
+                            gamelog.entry().text("You cast ").colour(RGB::named(rltk::CYAN), spell_name).text(".").log();
+                        }
+                    }
+                    _ => {
+                        fizzled.push(entity);
+                        if entity == *player_entity {
+                            gamelog.entry().text("You do not have the mana to cast ").colour(RGB::named(rltk::CYAN), spell_name).text(".").log();
+                        }
+                    }
+                }
+            }
+        }
+        for entity in fizzled {
+            wants_use.remove(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpellTemplate;
+
+    // a player with `mana` points about to cast a spell costing 5
+    fn world(mana : i32) -> (World, Entity) {
+        let mut ecs = World::new();
+        ecs.register::<WantsToUseItem>();
+        ecs.register::<Spell>();
+        ecs.register::<Mana>();
+        ecs.register::<Name>();
+        ecs.insert(GameLog::default());
+        let spell = ecs.create_entity()
+            .with(Spell{ template: SpellTemplate::FireBolt, mana_cost: 5 })
+            .with(Name{ name: "Fire Bolt".to_string() })
+            .build();
+        let player = ecs.create_entity()
+            .with(Mana{ max_mana: 10, mana })
+            .with(WantsToUseItem{ item: spell, target_item: None, target: None })
+            .build();
+        ecs.insert(player);
+        (ecs, spell)
+    }
+
+    #[test]
+    fn casting_needs_enough_mana() {
+        let (ecs, spell) = world(5);
+        let player = *ecs.fetch::<Entity>();
+        assert!(can_cast(&ecs, player, spell));
+        let (ecs, spell) = world(4);
+        let player = *ecs.fetch::<Entity>();
+        assert!(!can_cast(&ecs, player, spell));
+    }
+
+    #[test]
+    fn casting_spends_the_mana() {
+        let (ecs, _spell) = world(7);
+        let player = *ecs.fetch::<Entity>();
+        SpellCastSystem{}.run_now(&ecs);
+        assert_eq!(ecs.read_storage::<Mana>().get(player).unwrap().mana, 2);
+        assert!(ecs.read_storage::<WantsToUseItem>().get(player).is_some());
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].text(), "You cast Fire Bolt.");
+    }
+
+    #[test]
+    fn a_spell_without_the_mana_fizzles() {
+        let (ecs, _spell) = world(3);
+        let player = *ecs.fetch::<Entity>();
+        SpellCastSystem{}.run_now(&ecs);
+        assert_eq!(ecs.read_storage::<Mana>().get(player).unwrap().mana, 3);
+        assert!(ecs.read_storage::<WantsToUseItem>().get(player).is_none());
+    }
+}
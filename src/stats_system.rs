@@ -0,0 +1,248 @@
+use specs::prelude::*;
+use rltk::RGB;
+use crate::{CombatStats, Attributes, Attribute, Skills, Progression, Equipped, DefenseBonus, AttributeBonus, InBackpack, Weight, Quantity,
+            Encumbrance, Mana};
+use crate::game_log::GameLog;
+
+// modifier an attribute value grants to the rolls and stats derived from it
+pub fn attr_bonus(value : i32) -> i32 {
+    (value - 10).div_euclid(2)
+}
+
+pub fn max_hp_at_level(fitness : &Attribute, level : i32) -> i32 {
+    i32::max(1, 10 + fitness.total() + level * (5 + fitness.bonus))
+}
+
+// each point of magic skill adds this much to the mana pool
+const MANA_PER_MAGIC_SKILL : i32 = 2;
+
+pub fn max_mana_at_level(intelligence : &Attribute, magic : i32, level : i32) -> i32 {
+    i32::max(1, intelligence.total() / 2 + level * (2 + intelligence.bonus) + magic * MANA_PER_MAGIC_SKILL)
+}
+
+pub fn xp_to_next_level(level : i32) -> i32 {
+    level * 100
+}
+
+// experience awarded for slaying an entity of the given level
+pub fn xp_for_kill(level : i32) -> i32 {
+    level * 50
+}
+
+// pounds an entity can carry before it is overburdened
+pub fn carry_capacity(might : &Attribute) -> f32 {
+    (might.total() * 5) as f32
+}
+
+// defense lost while overburdened, on top of being slowed
+const OVERBURDENED_DEFENSE_PENALTY : i32 = 2;
+
+fn apply_modifiers(attr : &mut Attribute, modifiers : i32) {
+    attr.modifiers = modifiers;
+    attr.bonus = attr_bonus(attr.total());
+}
+
+pub struct StatsSystem {}
+
+impl<'a> System<'a> for StatsSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteStorage<'a, CombatStats>,
+        WriteStorage<'a, Attributes>,
+        WriteStorage<'a, Skills>,
+        WriteStorage<'a, Progression>,
+        ReadStorage<'a, Equipped>,
+        ReadStorage<'a, DefenseBonus>,
+        ReadStorage<'a, AttributeBonus>,
+        ReadStorage<'a, InBackpack>,
+        ReadStorage<'a, Weight>,
+        ReadStorage<'a, Quantity>,
+        WriteStorage<'a, Encumbrance>,
+        WriteStorage<'a, Mana>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (entities, player_entity, mut gamelog, mut combat_stats, mut attributes, mut skills, mut progression, equipped, defense_bonuses,
+            attribute_bonuses, backpack, weights, quantities, mut encumbrance, mut manas) = data;
+
+        for (entity, stats, attrs, skills, progress, burden, mana) in (&entities, &mut combat_stats, &mut attributes, &mut skills, &mut progression,
+                (&mut encumbrance).maybe(), (&mut manas).maybe()).join() {
+            // level up, training every skill and the weakest attribute
+            let mut levelled_up = false;
+            while progress.xp >= xp_to_next_level(progress.level) {
+                progress.xp -= xp_to_next_level(progress.level);
+                progress.level += 1;
+                skills.melee += 1;
+                skills.defence += 1;
+                skills.magic += 1;
+
+                let mut trainable = [&mut attrs.might, &mut attrs.fitness, &mut attrs.quickness, &mut attrs.intelligence];
+                if let Some(weakest) = trainable.iter_mut().min_by_key(|a| a.base) {
+                    weakest.base += 1;
+                }
+
+                levelled_up = true;
+                if entity == *player_entity {
+                    gamelog.entry().colour(RGB::named(rltk::MAGENTA), format!("Congratulations, you are now level {}!", progress.level)).log();
+                }
+            }
+
+            // gather modifiers from everything the entity is wearing or wielding
+            let mut defense = 0;
+            let mut might = 0;
+            let mut fitness = 0;
+            let mut quickness = 0;
+            let mut intelligence = 0;
+            for (item, worn) in (&entities, &equipped).join() {
+                if worn.owner == entity {
+                    if let Some(bonus) = defense_bonuses.get(item) {
+                        defense += bonus.defense;
+                    }
+                    if let Some(bonus) = attribute_bonuses.get(item) {
+                        might += bonus.might;
+                        fitness += bonus.fitness;
+                        quickness += bonus.quickness;
+                        intelligence += bonus.intelligence;
+                    }
+                }
+            }
+
+            apply_modifiers(&mut attrs.might, might);
+            apply_modifiers(&mut attrs.fitness, fitness);
+            apply_modifiers(&mut attrs.quickness, quickness);
+            apply_modifiers(&mut attrs.intelligence, intelligence);
+
+            stats.max_hp = max_hp_at_level(&attrs.fitness, progress.level);
+            stats.power = attrs.might.bonus;
+            stats.defense = skills.defence + attrs.quickness.bonus + defense;
+            stats.initiative = attrs.quickness.bonus;
+
+            // everything carried or worn counts towards the load
+            if let Some(burden) = burden {
+                let was_overburdened = burden.overburdened();
+                burden.carried = 0.0;
+                for (item, weight) in (&entities, &weights).join() {
+                    let held = backpack.get(item).is_some_and(|b| b.owner == entity) || equipped.get(item).is_some_and(|e| e.owner == entity);
+                    if held {
+                        burden.carried += weight.weight * quantities.get(item).map_or(1, |q| q.amount) as f32;
+                    }
+                }
+                burden.capacity = carry_capacity(&attrs.might);
+
+                if burden.overburdened() {
+                    stats.defense -= OVERBURDENED_DEFENSE_PENALTY;
+                }
+                if entity == *player_entity && burden.overburdened() != was_overburdened {
+                    if burden.overburdened() {
+                        gamelog.entry().colour(RGB::named(rltk::ORANGE), "You are overburdened, and slow to move.").log();
+                    } else {
+                        gamelog.log("You are no longer overburdened.");
+                    }
+                }
+            }
+
+            if levelled_up {
+                stats.hp = stats.max_hp;
+            }
+            stats.hp = i32::min(stats.hp, stats.max_hp);
+
+            if let Some(mana) = mana {
+                mana.max_mana = max_mana_at_level(&attrs.intelligence, skills.magic, progress.level);
+                if levelled_up {
+                    mana.mana = mana.max_mana;
+                }
+                mana.mana = i32::min(mana.mana, mana.max_mana);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_two_points_past_ten_add_one_to_the_bonus() {
+        assert_eq!(attr_bonus(10), 0);
+        assert_eq!(attr_bonus(11), 0);
+        assert_eq!(attr_bonus(14), 2);
+        assert_eq!(attr_bonus(9), -1);
+        assert_eq!(attr_bonus(6), -2);
+    }
+
+    #[test]
+    fn fitness_and_level_raise_max_hp() {
+        assert_eq!(max_hp_at_level(&Attribute::new(10), 1), 25);
+        assert!(max_hp_at_level(&Attribute::new(14), 1) > max_hp_at_level(&Attribute::new(10), 1));
+        assert!(max_hp_at_level(&Attribute::new(10), 2) > max_hp_at_level(&Attribute::new(10), 1));
+    }
+
+    #[test]
+    fn intelligence_magic_and_level_raise_max_mana() {
+        assert_eq!(max_mana_at_level(&Attribute::new(10), 0, 1), 7);
+        assert!(max_mana_at_level(&Attribute::new(14), 0, 1) > max_mana_at_level(&Attribute::new(10), 0, 1));
+        assert_eq!(max_mana_at_level(&Attribute::new(10), 2, 1), 7 + 2 * MANA_PER_MAGIC_SKILL);
+        assert!(max_mana_at_level(&Attribute::new(10), 0, 2) > max_mana_at_level(&Attribute::new(10), 0, 1));
+    }
+
+    #[test]
+    fn modifiers_change_the_bonus() {
+        let mut might = Attribute::new(10);
+        apply_modifiers(&mut might, 4);
+        assert_eq!(might.total(), 14);
+        assert_eq!(might.bonus, 2);
+    }
+
+    // a player with 10 might, carrying `potions` potions weighing 10 pounds each
+    fn encumbrance_with(potions : i32) -> (bool, i32, usize) {
+        let mut ecs = World::new();
+        ecs.register::<CombatStats>();
+        ecs.register::<Attributes>();
+        ecs.register::<Skills>();
+        ecs.register::<Progression>();
+        ecs.register::<Equipped>();
+        ecs.register::<DefenseBonus>();
+        ecs.register::<AttributeBonus>();
+        ecs.register::<InBackpack>();
+        ecs.register::<Weight>();
+        ecs.register::<Quantity>();
+        ecs.register::<Encumbrance>();
+        ecs.register::<Mana>();
+        let player = ecs.create_entity()
+            .with(CombatStats{ max_hp: 25, hp: 25, defense: 0, power: 0, initiative: 0 })
+            .with(Attributes{ might: Attribute::new(10), fitness: Attribute::new(10), quickness: Attribute::new(10), intelligence: Attribute::new(10) })
+            .with(Skills{ melee: 1, defence: 1, magic: 1 })
+            .with(Progression{ level: 1, xp: 0 })
+            .with(Encumbrance{ carried: 0.0, capacity: 0.0 })
+            .build();
+        ecs.create_entity().with(InBackpack{ owner: player }).with(Weight{ weight: 10.0 }).with(Quantity{ amount: potions }).build();
+        ecs.insert(player);
+        ecs.insert(GameLog::default());
+
+        StatsSystem{}.run_now(&ecs);
+
+        let burden = ecs.read_storage::<Encumbrance>();
+        let stats = ecs.read_storage::<CombatStats>();
+        let log = ecs.fetch::<GameLog>();
+        (burden.get(player).unwrap().overburdened(), stats.get(player).unwrap().defense, log.entries.len())
+    }
+
+    #[test]
+    fn might_sets_the_carry_capacity() {
+        assert_eq!(carry_capacity(&Attribute::new(10)), 50.0);
+        assert_eq!(carry_capacity(&Attribute::new(14)), 70.0);
+    }
+
+    #[test]
+    fn carrying_up_to_capacity_is_no_burden() {
+        assert_eq!(encumbrance_with(5), (false, 1, 0));
+    }
+
+    #[test]
+    fn carrying_past_capacity_costs_defense() {
+        assert_eq!(encumbrance_with(6), (true, 1 - OVERBURDENED_DEFENSE_PENALTY, 1));
+    }
+}
@@ -0,0 +1,181 @@
+use specs::prelude::*;
+use crate::{DetectingMonsters, Telepathic, SeeingInvisible, Blinded, Viewshed, Confused, Poisoned, SufferDamage, DamageType, Position, RunState};
+use crate::game_log::GameLog;
+
+pub struct StatusEffectSystem {}
+
+// counts down timed effects once per player turn and lifts them when they run out
+impl<'a> System<'a> for StatusEffectSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, RunState>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, DetectingMonsters>,
+        WriteStorage<'a, Telepathic>,
+        WriteStorage<'a, SeeingInvisible>,
+        WriteStorage<'a, Blinded>,
+        WriteStorage<'a, Viewshed>,
+        WriteStorage<'a, Confused>,
+        WriteStorage<'a, Poisoned>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Position>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, runstate, mut gamelog, entities, mut detecting, mut telepathic, mut seeing_invisible, mut blinded, mut viewsheds, mut confused, mut poisoned, mut suffer_damage, positions) = data;
+
+        if *runstate != RunState::PlayerTurn { return; }
+
+        let mut expired : Vec<Entity> = Vec::new();
+        for (entity, detect) in (&entities, &mut detecting).join() {
+            detect.turns -= 1;
+            if detect.turns < 1 { expired.push(entity); }
+        }
+        for entity in expired {
+            detecting.remove(entity);
+            if entity == *player_entity {
+                gamelog.log("Your sense of the creatures around you fades.");
+            }
+        }
+
+        let mut expired : Vec<Entity> = Vec::new();
+        for (entity, telepathy) in (&entities, &mut telepathic).join() {
+            telepathy.turns -= 1;
+            if telepathy.turns < 1 { expired.push(entity); }
+        }
+        for entity in expired {
+            telepathic.remove(entity);
+            if entity == *player_entity {
+                gamelog.log("The minds around you fall silent.");
+            }
+        }
+
+        let mut expired : Vec<Entity> = Vec::new();
+        for (entity, see_invisible) in (&entities, &mut seeing_invisible).join() {
+            see_invisible.turns -= 1;
+            if see_invisible.turns < 1 { expired.push(entity); }
+        }
+        for entity in expired {
+            seeing_invisible.remove(entity);
+            if entity == *player_entity {
+                gamelog.log("Your eyes stop tingling.");
+            }
+        }
+
+        // sight comes back in full as soon as the blindness lifts
+        let mut cured : Vec<Entity> = Vec::new();
+        for (entity, blindness) in (&entities, &mut blinded).join() {
+            blindness.turns -= 1;
+            if blindness.turns < 1 { cured.push(entity); }
+        }
+        for entity in cured {
+            blinded.remove(entity);
+            if let Some(viewshed) = viewsheds.get_mut(entity) {
+                viewshed.dirty = true;
+            }
+            if entity == *player_entity {
+                gamelog.log("You can see again!");
+            }
+        }
+
+        let mut recovered : Vec<Entity> = Vec::new();
+        for (entity, confusion) in (&entities, &mut confused).join() {
+            confusion.turns -= 1;
+            if confusion.turns < 1 { recovered.push(entity); }
+        }
+        for entity in recovered {
+            confused.remove(entity);
+            if entity == *player_entity {
+                gamelog.log("Your head clears.");
+            }
+        }
+
+        // poison bites every turn, and whoever poisoned the victim gets the credit if it kills them;
+        // anyone left behind on another level has to wait until the player comes back
+        let mut cured : Vec<Entity> = Vec::new();
+        for (entity, poison, _pos) in (&entities, &mut poisoned, &positions).join() {
+            SufferDamage::new_damage(&mut suffer_damage, entity, poison.damage, DamageType::Poison, poison.source);
+            if entity == *player_entity {
+                gamelog.entry().text("The poison burns in your veins for ").damage(poison.damage).text(".").log();
+            }
+            poison.turns -= 1;
+            if poison.turns < 1 { cured.push(entity); }
+        }
+        for entity in cured {
+            poisoned.remove(entity);
+            if entity == *player_entity {
+                gamelog.log("The poison has worked its way out of you.");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_turn(ecs : &mut World) {
+        StatusEffectSystem{}.run_now(ecs);
+        ecs.maintain();
+    }
+
+    #[test]
+    fn monster_detection_fades_after_its_turns() {
+        let mut ecs = World::new();
+        ecs.register::<DetectingMonsters>();
+        ecs.register::<Poisoned>();
+        ecs.register::<SufferDamage>();
+        ecs.register::<Position>();
+        ecs.register::<Confused>();
+        ecs.register::<Telepathic>();
+        ecs.register::<SeeingInvisible>();
+        ecs.register::<Blinded>();
+        ecs.register::<Viewshed>();
+        let player = ecs.create_entity().with(DetectingMonsters{ turns: 2 }).build();
+        ecs.insert(player);
+        ecs.insert(RunState::PlayerTurn);
+        ecs.insert(GameLog::default());
+
+        run_turn(&mut ecs);
+        assert_eq!(ecs.read_storage::<DetectingMonsters>().get(player).unwrap().turns, 1);
+        run_turn(&mut ecs);
+        assert!(ecs.read_storage::<DetectingMonsters>().get(player).is_none());
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].text(), "Your sense of the creatures around you fades.");
+
+        // it only counts the player's turns
+        ecs.write_storage::<DetectingMonsters>().insert(player, DetectingMonsters{ turns: 1 }).expect("Unable to insert detecting monsters");
+        *ecs.write_resource::<RunState>() = RunState::MonsterTurn;
+        run_turn(&mut ecs);
+        assert!(ecs.read_storage::<DetectingMonsters>().get(player).is_some());
+    }
+
+    #[test]
+    fn poison_bites_each_turn_until_it_wears_off() {
+        let mut ecs = World::new();
+        ecs.register::<DetectingMonsters>();
+        ecs.register::<Poisoned>();
+        ecs.register::<SufferDamage>();
+        ecs.register::<Position>();
+        ecs.register::<Confused>();
+        ecs.register::<Telepathic>();
+        ecs.register::<SeeingInvisible>();
+        ecs.register::<Blinded>();
+        ecs.register::<Viewshed>();
+        let orc = ecs.create_entity()
+            .with(Position{ x: 1, y: 1 })
+            .with(Poisoned{ turns: 2, damage: 3, source: None })
+            .build();
+        let player = ecs.create_entity().build();
+        ecs.insert(player);
+        ecs.insert(RunState::PlayerTurn);
+        ecs.insert(GameLog::default());
+
+        run_turn(&mut ecs);
+        assert_eq!(ecs.read_storage::<SufferDamage>().get(orc).unwrap().amount.len(), 1);
+        assert_eq!(ecs.read_storage::<Poisoned>().get(orc).unwrap().turns, 1);
+        run_turn(&mut ecs);
+        assert!(ecs.read_storage::<Poisoned>().get(orc).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use specs::prelude::*;
+use rltk::{RGB, Point, RandomNumberGenerator};
+use crate::{Map, RunState, WantsToUseItem, Name, Renderable, Position, Viewshed, BlocksTile, Identifiable, Teleports, Blinks, TeleportsLevel, Item};
+use crate::game_log::{GameLog, entity_colour};
+use crate::identification::{Identification, display_name};
+
+// how many levels a level teleport can carry the player, up or down
+const LEVEL_TELEPORT_RANGE : i32 = 3;
+
+// puts an entity down on a new tile, keeping the map's blocking in step and making it look around again
+fn move_to(map : &mut Map, pos : &mut Position, viewshed : Option<&mut Viewshed>, blocks : bool, destination : Point) {
+    if blocks {
+        let old_idx = map.xy_idx(pos.x, pos.y);
+        map.blocked[old_idx] = false;
+        let new_idx = map.xy_idx(destination.x, destination.y);
+        map.blocked[new_idx] = true;
+    }
+    pos.x = destination.x;
+    pos.y = destination.y;
+    if let Some(viewshed) = viewshed {
+        viewshed.dirty = true;
+    }
+}
+
+pub struct TeleportSystem {}
+
+// carries out the teleport effects of whatever was used this turn, whether an item, a spell or a trap
+impl<'a> System<'a> for TeleportSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Map>,
+        WriteExpect<'a, Point>,
+        WriteExpect<'a, RandomNumberGenerator>,
+        WriteExpect<'a, RunState>,
+        Entities<'a>,
+        ReadStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Identifiable>,
+        ReadExpect<'a, Identification>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Viewshed>,
+        ReadStorage<'a, BlocksTile>,
+        ReadStorage<'a, Teleports>,
+        ReadStorage<'a, Blinks>,
+        ReadStorage<'a, TeleportsLevel>,
+        ReadStorage<'a, Item>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, mut gamelog, mut map, mut player_pos, mut rng, mut runstate, entities, wants_use, names, renderables,
+            identifiables, identification, mut positions, mut viewsheds, blockers, teleports, blinks, teleports_level, items) = data;
+
+        for (entity, useitem) in (&entities, &wants_use).join() {
+            let is_player = entity == *player_entity;
+            let item_name = display_name(&names, &identifiables, &identification, useitem.item);
+            let item_colour = entity_colour(&renderables, useitem.item);
+            // only an item is read here, as spells and traps have nothing to read; ranged items were already announced when they took effect
+            let magic_item = items.get(useitem.item).is_some();
+            let entity_name = names.get(entity).map_or("Something".to_string(), |n| n.name.clone());
+            let entity_colour = entity_colour(&renderables, entity);
+
+            let destination = if teleports.get(useitem.item).is_some() {
+                if is_player && magic_item {
+                    gamelog.entry().text("You read the ").colour(item_colour, &item_name).text(".").log();
+                }
+                map.random_open_tile(&mut rng)
+            } else if blinks.get(useitem.item).is_some() {
+                // a blink only lands somewhere the user can see, and never inside anything
+                let seen = useitem.target.is_some_and(|t| viewsheds.get(entity).is_some_and(|v| v.visible_tiles.contains(&t)));
+                match useitem.target {
+                    Some(target) if seen && map.is_open(target.x, target.y) => Some(target),
+                    _ => {
+                        if is_player { gamelog.log("Something holds you in place."); }
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
+            if let Some(destination) = destination {
+                if let Some(pos) = positions.get_mut(entity) {
+                    let was_seen = map.visible_tiles[map.xy_idx(pos.x, pos.y)];
+                    move_to(&mut map, pos, viewsheds.get_mut(entity), blockers.get(entity).is_some(), destination);
+                    if is_player {
+                        player_pos.x = destination.x;
+                        player_pos.y = destination.y;
+                        gamelog.entry().colour(RGB::named(rltk::MAGENTA), "The world blurs around you.").log();
+                    } else if was_seen {
+                        gamelog.entry().colour(entity_colour, &entity_name).colour(RGB::named(rltk::MAGENTA), " vanishes!").log();
+                    }
+                }
+            }
+
+            // only the player can be sent to another level; the level itself is swapped once this turn's systems are done
+            if teleports_level.get(useitem.item).is_some() && is_player {
+                if magic_item {
+                    gamelog.entry().text("You read the ").colour(item_colour, &item_name).text(".").log();
+                }
+                let mut depth = map.depth;
+                while depth == map.depth {
+                    depth = i32::max(1, map.depth + rng.range(-LEVEL_TELEPORT_RANGE, LEVEL_TELEPORT_RANGE + 1));
+                }
+                gamelog.entry().colour(RGB::named(rltk::MAGENTA), "The floor drops away beneath you!").log();
+                *runstate = RunState::LevelTeleport{ depth };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a player at (2, 2) on a strip of floor, using a blink item aimed at `target` or a teleport item
+    fn world(blink : bool, target : Option<Point>) -> (World, Entity) {
+        let mut ecs = World::new();
+        ecs.register::<WantsToUseItem>();
+        ecs.register::<Name>();
+        ecs.register::<Renderable>();
+        ecs.register::<Identifiable>();
+        ecs.register::<Position>();
+        ecs.register::<Viewshed>();
+        ecs.register::<BlocksTile>();
+        ecs.register::<Teleports>();
+        ecs.register::<Blinks>();
+        ecs.register::<TeleportsLevel>();
+        ecs.register::<Item>();
+
+        let floor : Vec<(i32, i32)> = (2..=8).map(|x| (x, 2)).collect();
+        ecs.insert(Map::with_floor(&floor));
+        ecs.insert(Point::new(2, 2));
+        ecs.insert(RandomNumberGenerator::seeded(4));
+        ecs.insert(RunState::PlayerTurn);
+        ecs.insert(GameLog::default());
+        ecs.insert(Identification::new(4, &mut RandomNumberGenerator::seeded(4)));
+
+        let item = if blink { ecs.create_entity().with(Item{}).with(Blinks{}).build() } else { ecs.create_entity().with(Item{}).with(Teleports{}).build() };
+        let player = ecs.create_entity()
+            .with(Position{ x: 2, y: 2 })
+            .with(Viewshed{ visible_tiles: vec![Point::new(2, 2), Point::new(3, 2), Point::new(4, 2)], range: 8, dirty: false })
+            .with(WantsToUseItem{ item, target_item: None, target })
+            .build();
+        ecs.insert(player);
+        (ecs, player)
+    }
+
+    fn position(ecs : &World, entity : Entity) -> Point {
+        let positions = ecs.read_storage::<Position>();
+        let pos = positions.get(entity).unwrap();
+        Point::new(pos.x, pos.y)
+    }
+
+    #[test]
+    fn teleporting_lands_on_open_floor() {
+        let (ecs, player) = world(false, None);
+        TeleportSystem{}.run_now(&ecs);
+        let landed = position(&ecs, player);
+        assert!(ecs.fetch::<Map>().is_open(landed.x, landed.y));
+        assert_eq!(*ecs.fetch::<Point>(), landed);
+        assert!(ecs.read_storage::<Viewshed>().get(player).unwrap().dirty);
+    }
+
+    #[test]
+    fn blinking_moves_to_a_seen_tile() {
+        let (ecs, player) = world(true, Some(Point::new(4, 2)));
+        TeleportSystem{}.run_now(&ecs);
+        assert_eq!(position(&ecs, player), Point::new(4, 2));
+    }
+
+    #[test]
+    fn blinking_refuses_unseen_tiles() {
+        let (ecs, player) = world(true, Some(Point::new(7, 2)));
+        TeleportSystem{}.run_now(&ecs);
+        assert_eq!(position(&ecs, player), Point::new(2, 2));
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].text(), "Something holds you in place.");
+    }
+}
@@ -0,0 +1,140 @@
+use rltk::{RandomNumberGenerator, RGB};
+use specs::prelude::*;
+use crate::{Map, TileType, Rect, MAP_WIDTH, MAP_HEIGHT};
+use crate::spawner::{self, VendorKind};
+
+// the main street runs east to west across the middle of town, ending at the dungeon entrance
+const ROAD_Y : i32 = MAP_HEIGHT as i32 / 2;
+const MAX_BUILDINGS : usize = 10;
+
+// draws a building's walls around a floored interior, with a door facing the road
+fn apply_building(map : &mut Map, building : &Rect) {
+    for x in building.x1..=building.x2 {
+        for y in building.y1..=building.y2 {
+            let idx = map.xy_idx(x, y);
+            let edge = x == building.x1 || x == building.x2 || y == building.y1 || y == building.y2;
+            map.tiles[idx] = if edge { TileType::Wall } else { TileType::WoodFloor };
+        }
+    }
+
+    // a path runs from the door straight to the road
+    let (door_x, _) = building.center();
+    let (door_y, step) = if building.y2 < ROAD_Y { (building.y2, 1) } else { (building.y1, -1) };
+    let door_idx = map.xy_idx(door_x, door_y);
+    map.tiles[door_idx] = TileType::WoodFloor;
+    let mut y = door_y + step;
+    while y != ROAD_Y {
+        let idx = map.xy_idx(door_x, y);
+        map.tiles[idx] = TileType::Road;
+        y += step;
+    }
+}
+
+/// Lays out the town at depth 0: buildings either side of a road that leads east to the dungeon entrance.
+/// `rooms` holds the building interiors, largest first, so the tavern is always `rooms[0]`
+/// and the blacksmith and alchemist are `rooms[1]` and `rooms[2]` when the town has that many buildings.
+pub fn new_town(rng : &mut RandomNumberGenerator) -> Map {
+    let mut map = Map::new(0);
+    for y in 1..map.height - 1 {
+        for x in 1..map.width - 1 {
+            let idx = map.xy_idx(x, y);
+            map.tiles[idx] = TileType::Grass;
+        }
+    }
+    for x in 1..map.width - 1 {
+        let idx = map.xy_idx(x, ROAD_Y);
+        map.tiles[idx] = TileType::Road;
+    }
+
+    // buildings sit back from the road, leaving the east end clear around the entrance
+    let mut buildings : Vec<Rect> = Vec::new();
+    for _attempt in 0..200 {
+        if buildings.len() >= MAX_BUILDINGS { break; }
+        let w = rng.range(7, 15);
+        let h = rng.range(5, 9);
+        let x = rng.range(2, MAP_WIDTH as i32 - 10 - w);
+        // one row either side of the road, so no building stands between another and the street
+        let y = if rng.range(0, 2) == 0 {
+            ROAD_Y - 2 - h - rng.range(0, 3)
+        } else {
+            ROAD_Y + 2 + rng.range(0, 3)
+        };
+        let building = Rect::new(x, y, w, h);
+        let grounds = Rect::new(x - 1, y - 1, w + 2, h + 2);
+        if buildings.iter().all(|other| !grounds.intersects(other)) {
+            buildings.push(building);
+        }
+    }
+    buildings.sort_by_key(|b| -((b.x2 - b.x1) * (b.y2 - b.y1)));
+
+    for building in buildings.iter() {
+        apply_building(&mut map, building);
+        map.rooms.push(Rect{ x1: building.x1 + 1, x2: building.x2 - 1, y1: building.y1 + 1, y2: building.y2 - 1 });
+    }
+
+    let entrance_idx = map.xy_idx(map.width - 3, ROAD_Y);
+    map.tiles[entrance_idx] = TileType::DownStairs;
+
+    // there is nothing to discover about a town you live next to, and it is always broad daylight
+    for revealed in map.revealed_tiles.iter_mut() {
+        *revealed = true;
+    }
+    for light in map.ambient_light.iter_mut() {
+        *light = RGB::named(rltk::WHITE);
+    }
+
+    map
+}
+
+// where a new adventurer wakes up: a corner of the tavern
+pub fn start_position(map : &Map) -> (i32, i32) {
+    (map.rooms[0].x1, map.rooms[0].y1)
+}
+
+// a random spot inside a rectangle
+fn spot_in(rng : &mut RandomNumberGenerator, area : &Rect) -> (i32, i32) {
+    (rng.range(area.x1, area.x2 + 1), rng.range(area.y1, area.y2 + 1))
+}
+
+/// Fills the town with its barkeep, shopkeepers and wandering townsfolk
+pub fn populate_town(ecs : &mut World, map : &Map) {
+    let tavern = &map.rooms[0];
+    let (bar_x, bar_y) = tavern.center();
+    spawner::barkeep(ecs, bar_x, bar_y);
+
+    // a crowded layout can leave too few buildings for every shop; whoever has no room to trade from stays away
+    if let Some(smithy) = map.rooms.get(1) {
+        let (smith_x, smith_y) = smithy.center();
+        spawner::vendor(ecs, smith_x, smith_y, 1, VendorKind::Blacksmith);
+    }
+    if let Some(shop) = map.rooms.get(2) {
+        let (alchemist_x, alchemist_y) = shop.center();
+        spawner::vendor(ecs, alchemist_x, alchemist_y, 1, VendorKind::Alchemist);
+    }
+
+    // a few drinkers in the tavern, a householder or two at home and people out on the road
+    let mut folk : Vec<(i32, i32, bool)> = Vec::new();
+    {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        for _i in 0..rng.roll_dice(1, 3) + 1 {
+            let (x, y) = spot_in(&mut rng, tavern);
+            folk.push((x, y, true));
+        }
+        for house in map.rooms.iter().skip(3) {
+            for _i in 0..rng.roll_dice(1, 3) - 1 {
+                let (x, y) = spot_in(&mut rng, house);
+                folk.push((x, y, false));
+            }
+        }
+        for _i in 0..rng.roll_dice(2, 3) {
+            let x = rng.range(2, map.width - 6);
+            folk.push((x, ROAD_Y, false));
+        }
+    }
+
+    for (x, y, in_tavern) in folk {
+        // never on top of someone already standing there
+        if (x, y) == tavern.center() || (x, y) == start_position(map) { continue; }
+        if in_tavern { spawner::patron(ecs, x, y); } else { spawner::townsperson(ecs, x, y); }
+    }
+}
@@ -0,0 +1,223 @@
+use specs::prelude::*;
+use rltk::Point;
+use crate::{Map, TileType, known_traps, Viewshed, Monster, Name, RunState, PlayerActivity, Confused, Invisible, SeeingInvisible, try_move_player};
+use crate::game_log::GameLog;
+use crate::visibility_system::visible_hostile;
+
+pub fn start_travel(ecs : &mut World, target : Point) -> RunState {
+    let traps = known_traps(ecs);
+    let path = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Point>();
+        map.known_path(*player_pos, target, &traps)
+    };
+
+    match path {
+        None => {
+            ecs.write_resource::<GameLog>().log("You don't know a way there.");
+            RunState::AwaitingInput
+        },
+        Some(path) => follow(ecs, path)
+    }
+}
+
+/// Heads for whichever seen staircase, up or down, is the shorter walk through known ground
+pub fn start_travel_to_stairs(ecs : &mut World) -> RunState {
+    let traps = known_traps(ecs);
+    let nearest = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Point>();
+        [TileType::DownStairs, TileType::UpStairs].iter()
+            .filter_map(|stairs| map.find_tile(*stairs))
+            .filter_map(|stairs| map.known_path(*player_pos, stairs, &traps))
+            .min_by_key(|path| path.len())
+    };
+
+    match nearest {
+        None => {
+            ecs.write_resource::<GameLog>().log("You don't know a way to any stairs.");
+            RunState::AwaitingInput
+        },
+        Some(path) => {
+            ecs.write_resource::<GameLog>().log("You head for the stairs.");
+            follow(ecs, path)
+        }
+    }
+}
+
+fn follow(ecs : &mut World, path : Vec<usize>) -> RunState {
+    *ecs.write_resource::<PlayerActivity>() = PlayerActivity::Travelling{ path };
+    travel(ecs)
+}
+
+fn stop_travelling(ecs : &mut World, message : Option<String>) -> RunState {
+    *ecs.write_resource::<PlayerActivity>() = PlayerActivity::Idle;
+    if let Some(message) = message {
+        ecs.write_resource::<GameLog>().log(message);
+    }
+    RunState::AwaitingInput
+}
+
+// takes the next step along the travel path unless something hostile has come into view
+pub fn travel(ecs : &mut World) -> RunState {
+    let mut path = match &*ecs.fetch::<PlayerActivity>() {
+        PlayerActivity::Travelling{ path } => path.clone(),
+        _ => return RunState::AwaitingInput
+    };
+
+    let player_entity = *ecs.fetch::<Entity>();
+    let hostile = {
+        let map = ecs.fetch::<Map>();
+        let viewsheds = ecs.read_storage::<Viewshed>();
+        let monsters = ecs.read_storage::<Monster>();
+        let invisible = ecs.read_storage::<Invisible>();
+        let see_invisible = ecs.read_storage::<SeeingInvisible>().get(player_entity).is_some();
+        viewsheds.get(player_entity).and_then(|v| visible_hostile(&map, v, &monsters, &invisible, see_invisible))
+    };
+    if let Some(hostile) = hostile {
+        let name = ecs.read_storage::<Name>().get(hostile).map_or("something".to_string(), |n| n.name.clone());
+        return stop_travelling(ecs, Some(format!("You spot the {} and stop.", name)));
+    }
+
+    if ecs.read_storage::<Confused>().get(player_entity).is_some() {
+        return stop_travelling(ecs, Some("You are too confused to find your way.".to_string()));
+    }
+
+    if path.is_empty() {
+        return stop_travelling(ecs, None);
+    }
+
+    let next = path.remove(0);
+    let (delta_x, delta_y) = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Point>();
+        (next as i32 % map.width - player_pos.x, next as i32 / map.width - player_pos.y)
+    };
+
+    // anything that has wandered into the way ends the journey rather than being attacked
+    if i32::abs(delta_x) + i32::abs(delta_y) != 1 || ecs.fetch::<Map>().blocked[next] {
+        return stop_travelling(ecs, Some("Your way is blocked.".to_string()));
+    }
+
+    try_move_player(delta_x, delta_y, ecs);
+    *ecs.write_resource::<PlayerActivity>() = if path.is_empty() { PlayerActivity::Idle } else { PlayerActivity::Travelling{ path } };
+    RunState::PlayerTurn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Position, Player, CombatStats, WantsToMelee, EntityMoved, EntryTrigger};
+
+    // a corridor running east from the player, seen all the way along apart from the tiles in `unseen`
+    fn world(unseen : &[i32]) -> World {
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<Player>();
+        ecs.register::<Viewshed>();
+        ecs.register::<CombatStats>();
+        ecs.register::<Monster>();
+        ecs.register::<Name>();
+        ecs.register::<WantsToMelee>();
+        ecs.register::<EntityMoved>();
+        ecs.register::<Confused>();
+        ecs.register::<EntryTrigger>();
+        ecs.register::<Invisible>();
+        ecs.register::<SeeingInvisible>();
+
+        let corridor : Vec<(i32, i32)> = (1..=10).map(|x| (x, 5)).collect();
+        let mut map = Map::with_floor(&corridor);
+        for x in (1..=10).filter(|x| !unseen.contains(x)) {
+            let idx = map.xy_idx(x, 5);
+            map.revealed_tiles[idx] = true;
+        }
+        let player = ecs.create_entity()
+            .with(Position{ x: 2, y: 5 })
+            .with(Player{})
+            .with(Viewshed{ visible_tiles: vec![Point::new(2, 5), Point::new(3, 5), Point::new(4, 5)], range: 8, dirty: false })
+            .build();
+        ecs.insert(map);
+        ecs.insert(player);
+        ecs.insert(Point::new(2, 5));
+        ecs.insert(GameLog::default());
+        ecs.insert(PlayerActivity::Idle);
+        ecs
+    }
+
+    #[test]
+    fn known_paths_only_cross_revealed_tiles() {
+        let ecs = world(&[6]);
+        let map = ecs.fetch::<Map>();
+        assert!(map.known_path(Point::new(2, 5), Point::new(5, 5), &[]).is_some());
+        assert!(map.known_path(Point::new(2, 5), Point::new(8, 5), &[]).is_none());
+        assert!(map.known_path(Point::new(2, 5), Point::new(6, 5), &[]).is_none());
+    }
+
+    #[test]
+    fn travel_takes_one_step_a_turn() {
+        let mut ecs = world(&[]);
+        assert!(start_travel(&mut ecs, Point::new(5, 5)) == RunState::PlayerTurn);
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(3, 5));
+        assert!(travel(&mut ecs) == RunState::PlayerTurn);
+        assert!(travel(&mut ecs) == RunState::PlayerTurn);
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(5, 5));
+        assert!(*ecs.fetch::<PlayerActivity>() == PlayerActivity::Idle);
+    }
+
+    #[test]
+    fn travel_refuses_unknown_destinations() {
+        let mut ecs = world(&[8]);
+        assert!(start_travel(&mut ecs, Point::new(8, 5)) == RunState::AwaitingInput);
+        assert_eq!(ecs.fetch::<GameLog>().entries.last().unwrap().text(), "You don't know a way there.");
+    }
+
+    #[test]
+    fn travel_stops_when_something_hostile_appears() {
+        let mut ecs = world(&[]);
+        let goblin = ecs.create_entity().with(Monster{}).with(Name{ name: "Goblin".to_string() }).build();
+        {
+            let mut map = ecs.fetch_mut::<Map>();
+            let idx = map.xy_idx(4, 5);
+            map.tile_content[idx].push(goblin);
+        }
+        assert!(start_travel(&mut ecs, Point::new(8, 5)) == RunState::AwaitingInput);
+        assert_eq!(ecs.fetch::<GameLog>().entries.last().unwrap().text(), "You spot the Goblin and stop.");
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(2, 5));
+    }
+
+    // the corridor with a way up at its west end and a way down at `down_x`
+    fn world_with_stairs(unseen : &[i32], down_x : i32) -> World {
+        let ecs = world(unseen);
+        {
+            let mut map = ecs.fetch_mut::<Map>();
+            let up = map.xy_idx(1, 5);
+            let down = map.xy_idx(down_x, 5);
+            map.tiles[up] = TileType::UpStairs;
+            map.tiles[down] = TileType::DownStairs;
+        }
+        ecs
+    }
+
+    #[test]
+    fn travelling_to_the_stairs_takes_the_nearest_known_flight() {
+        let mut ecs = world_with_stairs(&[], 8);
+        assert!(start_travel_to_stairs(&mut ecs) == RunState::PlayerTurn);
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(1, 5));
+        assert_eq!(ecs.fetch::<GameLog>().entries.last().unwrap().text(), "You head for the stairs.");
+
+        let mut ecs = world_with_stairs(&[], 3);
+        assert!(start_travel_to_stairs(&mut ecs) == RunState::PlayerTurn);
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(3, 5));
+    }
+
+    #[test]
+    fn travelling_to_the_stairs_ignores_unseen_ones() {
+        let mut ecs = world_with_stairs(&[1], 8);
+        assert!(start_travel_to_stairs(&mut ecs) == RunState::PlayerTurn);
+        assert_eq!(*ecs.fetch::<Point>(), Point::new(3, 5));
+
+        let mut ecs = world_with_stairs(&[1, 8], 8);
+        assert!(start_travel_to_stairs(&mut ecs) == RunState::AwaitingInput);
+        assert_eq!(ecs.fetch::<GameLog>().entries.last().unwrap().text(), "You don't know a way to any stairs.");
+    }
+}
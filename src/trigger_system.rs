@@ -0,0 +1,44 @@
+use specs::prelude::*;
+use crate::{Map, Position, EntityMoved, EntryTrigger, WantsToUseItem, Name, Renderable};
+use crate::game_log::{GameLog, entity_colour};
+
+pub struct TriggerSystem {}
+
+// sets off any trap on the tile an entity just moved onto, by having the entity use it
+impl<'a> System<'a> for TriggerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, EntityMoved>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, EntryTrigger>,
+        WriteStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Renderable>
+    );
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (player_entity, mut gamelog, map, entities, mut moved, positions, triggers, mut wants_use, names, renderables) = data;
+
+        for (entity, _moved, pos) in (&entities, &moved, &positions).join() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            for trap in map.tile_content[idx].iter().filter(|e| **e != entity && triggers.get(**e).is_some()) {
+                wants_use.insert(entity, WantsToUseItem{ item: *trap, target_item: None, target: None }).expect("Unable to insert intent");
+
+                let trap_name = &names.get(*trap).unwrap().name;
+                if entity == *player_entity {
+                    gamelog.entry().text("You step on a ").colour(entity_colour(&renderables, *trap), trap_name).text("!").log();
+                } else if map.visible_tiles[idx] {
+                    let name = names.get(entity).map_or("Something".to_string(), |n| n.name.clone());
+                    gamelog.entry().colour(entity_colour(&renderables, entity), name).text(" steps on a ")
+                        .colour(entity_colour(&renderables, *trap), trap_name).text("!").log();
+                }
+            }
+        }
+
+        moved.clear();
+    }
+}
@@ -1,7 +1,44 @@
 use specs::prelude::*;
 use super::{Viewshed, Position, Map};
 use rltk::{field_of_view, Point};
-use crate::Player;
+use crate::{Player, Monster, DarkVision, Blinded, Invisible, SeeingInvisible, DetectingMonsters, Telepathic};
+
+// first hostile standing on a tile in the viewshed that the viewer can actually see, used to interrupt resting and other long actions
+pub fn visible_hostile(map : &Map, viewshed : &Viewshed, monsters : &ReadStorage<Monster>, invisible : &ReadStorage<Invisible>, see_invisible : bool) -> Option<Entity> {
+    for tile in viewshed.visible_tiles.iter() {
+        let idx = map.xy_idx(tile.x, tile.y);
+        for entity in map.tile_content[idx].iter() {
+            if monsters.get(*entity).is_some() && (see_invisible || invisible.get(*entity).is_none()) {
+                return Some(*entity);
+            }
+        }
+    }
+    None
+}
+
+/// Whether the player can make out an entity standing at `pos`: it must be on a visible tile and not invisible to them,
+/// unless it is a monster they can sense some other way, through monster detection or telepathy
+pub fn player_perceives(ecs : &World) -> impl Fn(Entity, Point) -> bool + '_ {
+    let map = ecs.fetch::<Map>();
+    let player_entity = *ecs.fetch::<Entity>();
+    let player_pos = *ecs.fetch::<Point>();
+    let monsters = ecs.read_storage::<Monster>();
+    let invisible = ecs.read_storage::<Invisible>();
+    let see_invisible = ecs.read_storage::<SeeingInvisible>().get(player_entity).is_some();
+    let detecting = ecs.read_storage::<DetectingMonsters>().get(player_entity).is_some();
+    let telepathy_range = ecs.read_storage::<Telepathic>().get(player_entity).map(|t| t.range as f32);
+
+    move |entity, pos| {
+        if monsters.get(entity).is_some() {
+            if detecting { return true; }
+            if let Some(range) = telepathy_range {
+                if rltk::DistanceAlg::Pythagoras.distance2d(player_pos, pos) <= range { return true; }
+            }
+        }
+        if pos.x < 0 || pos.x >= map.width || pos.y < 0 || pos.y >= map.height { return false; }
+        map.visible_tiles[map.xy_idx(pos.x, pos.y)] && (see_invisible || invisible.get(entity).is_none())
+    }
+}
 
 pub struct VisibilitySystem {
 
@@ -12,23 +49,33 @@ impl <'a> System<'a> for VisibilitySystem {
                         Entities<'a>,
                         WriteStorage<'a, Viewshed>,
                         WriteStorage<'a, Position>,
-                        ReadStorage<'a, Player>);
+                        ReadStorage<'a, Player>,
+                        ReadStorage<'a, DarkVision>,
+                        ReadStorage<'a, Blinded>);
 
     fn run(&mut self, data : Self::SystemData)
     {
-        let (mut map, entities, mut viewshed, pos, player) = data;
+        let (mut map, entities, mut viewshed, pos, player, dark_vision, blinded) = data;
 
         for (ent, viewshed, pos) in (&entities, &mut viewshed, &pos).join() {
 
             if viewshed.dirty {
                 viewshed.dirty = false;
                 // viewshed.visible_tiles.clear();
-                viewshed.visible_tiles = field_of_view(Point::new(pos.x, pos.y), viewshed.range, &*map);
+                // the blind only make out what is right next to them
+                let range = if blinded.get(ent).is_some() { 1 } else { viewshed.range };
+                viewshed.visible_tiles = field_of_view(Point::new(pos.x, pos.y), range, &*map);
                 viewshed.visible_tiles.retain(|p| p.x >= 0 && p.x < map.width && p.y >= 0 && p.y < map.height );
 
                 // If this is player, reveal what they can see
                 let _p : Option<&Player> = player.get(ent);
                 if let Some(_p) = _p {
+                    // the player only makes out tiles with light on them, or ones close enough to see in the dark
+                    let dark_range = dark_vision.get(ent).map_or(0, |d| d.range) as f32;
+                    let here = Point::new(pos.x, pos.y);
+                    viewshed.visible_tiles.retain(|p| map.is_lit(map.xy_idx(p.x, p.y))
+                        || rltk::DistanceAlg::Pythagoras.distance2d(here, *p) <= dark_range);
+
                     for t in map.visible_tiles.iter_mut() { *t = false }
                     for vis in viewshed.visible_tiles.iter() {
                         let idx = map.xy_idx(vis.x, vis.y);
@@ -39,4 +86,133 @@ impl <'a> System<'a> for VisibilitySystem {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the player at the west end of a corridor, with light on the tiles in `lit` and the given dark-vision
+    fn visible_after_looking(lit : &[i32], dark_vision : Option<i32>) -> Vec<i32> {
+        let mut ecs = World::new();
+        ecs.register::<Viewshed>();
+        ecs.register::<Position>();
+        ecs.register::<Player>();
+        ecs.register::<DarkVision>();
+        ecs.register::<Blinded>();
+
+        let corridor : Vec<(i32, i32)> = (1..=10).map(|x| (x, 5)).collect();
+        let mut map = Map::with_floor(&corridor);
+        for x in lit.iter() {
+            let idx = map.xy_idx(*x, 5);
+            map.light[idx] = rltk::RGB::from_f32(1.0, 1.0, 1.0);
+        }
+        ecs.insert(map);
+
+        let mut player = ecs.create_entity()
+            .with(Position{ x: 1, y: 5 })
+            .with(Player{})
+            .with(Viewshed{ visible_tiles: Vec::new(), range: 8, dirty: true });
+        if let Some(range) = dark_vision { player = player.with(DarkVision{ range }); }
+        player.build();
+
+        VisibilitySystem{}.run_now(&ecs);
+        let map = ecs.fetch::<Map>();
+        (1..=10).filter(|x| map.visible_tiles[map.xy_idx(*x, 5)]).collect()
+    }
+
+    #[test]
+    fn lit_tiles_are_seen_as_far_as_the_viewshed_reaches() {
+        assert_eq!(visible_after_looking(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], None), vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn dark_tiles_need_dark_vision_to_be_seen() {
+        // the player always makes out the tile they stand on
+        assert_eq!(visible_after_looking(&[6], None), vec![1, 6]);
+        assert_eq!(visible_after_looking(&[6], Some(2)), vec![1, 2, 3, 6]);
+    }
+
+    // what the player at (1, 5) makes out of an orc at `orc_at`, with the tiles up to x = 5 in view
+    fn perceives_orc(orc_at : Point, invisible : bool, senses : fn(&mut World, Entity)) -> bool {
+        let mut ecs = World::new();
+        ecs.register::<Monster>();
+        ecs.register::<Invisible>();
+        ecs.register::<SeeingInvisible>();
+        ecs.register::<DetectingMonsters>();
+        ecs.register::<Telepathic>();
+
+        let mut map = Map::with_floor(&[]);
+        for x in 1..=5 {
+            let idx = map.xy_idx(x, 5);
+            map.visible_tiles[idx] = true;
+        }
+        ecs.insert(map);
+        ecs.insert(Point::new(1, 5));
+        let player = ecs.create_entity().build();
+        ecs.insert(player);
+        senses(&mut ecs, player);
+
+        let mut orc = ecs.create_entity().with(Monster{});
+        if invisible { orc = orc.with(Invisible{}); }
+        let orc = orc.build();
+
+        let perceives = player_perceives(&ecs);
+        perceives(orc, orc_at)
+    }
+
+    fn no_senses(_ecs : &mut World, _player : Entity) {}
+
+    fn telepathy(ecs : &mut World, player : Entity) {
+        ecs.write_storage::<Telepathic>().insert(player, Telepathic{ range: 10, turns: 5 }).expect("Unable to insert telepathy");
+    }
+
+    fn see_invisible(ecs : &mut World, player : Entity) {
+        ecs.write_storage::<SeeingInvisible>().insert(player, SeeingInvisible{ turns: 5 }).expect("Unable to insert see invisible");
+    }
+
+    #[test]
+    fn monsters_are_seen_on_visible_tiles_only() {
+        assert!(perceives_orc(Point::new(4, 5), false, no_senses));
+        assert!(!perceives_orc(Point::new(8, 5), false, no_senses));
+        assert!(!perceives_orc(Point::new(-3, 5), false, no_senses));
+    }
+
+    #[test]
+    fn telepathy_senses_monsters_out_of_sight_within_its_range() {
+        assert!(perceives_orc(Point::new(8, 5), false, telepathy));
+        assert!(!perceives_orc(Point::new(20, 5), false, telepathy));
+    }
+
+    #[test]
+    fn invisible_monsters_need_see_invisible_or_another_sense() {
+        assert!(!perceives_orc(Point::new(4, 5), true, no_senses));
+        assert!(perceives_orc(Point::new(4, 5), true, see_invisible));
+        assert!(perceives_orc(Point::new(4, 5), true, telepathy));
+    }
+
+    #[test]
+    fn the_blind_only_see_next_to_them() {
+        let mut ecs = World::new();
+        ecs.register::<Viewshed>();
+        ecs.register::<Position>();
+        ecs.register::<Player>();
+        ecs.register::<DarkVision>();
+        ecs.register::<Blinded>();
+        let corridor : Vec<(i32, i32)> = (1..=10).map(|x| (x, 5)).collect();
+        let mut map = Map::with_floor(&corridor);
+        for light in map.light.iter_mut() { *light = rltk::RGB::from_f32(1.0, 1.0, 1.0); }
+        ecs.insert(map);
+        ecs.create_entity()
+            .with(Position{ x: 1, y: 5 })
+            .with(Player{})
+            .with(Viewshed{ visible_tiles: Vec::new(), range: 8, dirty: true })
+            .with(Blinded{ turns: 3 })
+            .build();
+
+        VisibilitySystem{}.run_now(&ecs);
+        let map = ecs.fetch::<Map>();
+        let seen : Vec<i32> = (1..=10).filter(|x| map.visible_tiles[map.xy_idx(*x, 5)]).collect();
+        assert_eq!(seen, vec![1, 2]);
+    }
+}